@@ -0,0 +1,108 @@
+//! `cargo bench --bench adjacency`: links up a generated map of about 10k vertexes the way
+//! `setup_entity_adjacencies` used to, comparing every pair of objects, and through the lookups.
+
+use std::{collections::HashMap, time::Instant};
+
+use bevy::prelude::Entity;
+use catan::{
+    map::{
+        self,
+        adjacency::{self, SpawnedMapObject},
+        generator::{self, GeneratorConfig, GeneratorKind},
+        EdgeClientServerLookup, MaterialClientServerLookup, VertexClientServerLookup,
+    },
+    protocol::RoomId,
+};
+
+const ROOM: RoomId = RoomId(1);
+
+fn spawned_map() -> (
+    Vec<SpawnedMapObject>,
+    VertexClientServerLookup,
+    EdgeClientServerLookup,
+    MaterialClientServerLookup,
+) {
+    let level = generator::generate(&GeneratorConfig {
+        kind: GeneratorKind::Hex,
+        players: 4,
+        size: 40,
+        seed: 0,
+    })
+    .unwrap();
+    let mut lookups = [HashMap::new(), HashMap::new(), HashMap::new()];
+    let spawned: Vec<SpawnedMapObject> = map::level_spawn_events(ROOM, &level)
+        .into_iter()
+        .enumerate()
+        .map(|(i, spawn)| {
+            let entity = Entity::from_raw(i as u32);
+            lookups[spawn.map_type as usize - 1].insert((ROOM, spawn.map_type_id), entity);
+            SpawnedMapObject {
+                room: spawn.room,
+                map_type: spawn.map_type,
+                id: spawn.map_type_id,
+                entity,
+                adjacencies: map::Adjacencies {
+                    vertex_list: spawn.vertex_list,
+                    edge_list: spawn.edge_list,
+                    material_list: spawn.material_list,
+                },
+                material_type: spawn.material_type,
+            }
+        })
+        .collect();
+    let [vertexes, edges, materials] = lookups;
+    (
+        spawned,
+        VertexClientServerLookup(vertexes),
+        EdgeClientServerLookup(edges),
+        MaterialClientServerLookup(materials),
+    )
+}
+
+/// Every vertex against every vertex, edge and mana point, searching the id lists each time.
+fn pairwise(spawned: &[SpawnedMapObject]) -> usize {
+    let of_type = |map_type| spawned.iter().filter(move |o| o.map_type == map_type);
+    let mut links = 0;
+    for vertex in of_type(map::VERTEX) {
+        for other in of_type(map::VERTEX) {
+            if vertex.room == other.room && vertex.adjacencies.vertex_list.contains(&other.id) {
+                links += 1;
+            }
+        }
+        for edge in of_type(map::EDGE) {
+            if vertex.room == edge.room && vertex.adjacencies.edge_list.contains(&edge.id) {
+                links += 2;
+            }
+        }
+        for material in of_type(map::MATERIAL) {
+            if vertex.room == material.room
+                && vertex.adjacencies.material_list.contains(&material.id)
+            {
+                links += 2;
+            }
+        }
+    }
+    links
+}
+
+fn main() {
+    let (spawned, vertexes, edges, materials) = spawned_map();
+    let count = spawned.iter().filter(|o| o.map_type == map::VERTEX).count();
+    println!("{} vertexes, {} map objects", count, spawned.len());
+
+    let start = Instant::now();
+    let links = pairwise(&spawned);
+    println!("pairwise: {} links in {:?}", links, start.elapsed());
+
+    let start = Instant::now();
+    let resolved = adjacency::resolve_adjacencies(&spawned, &vertexes, &edges, &materials);
+    let links: usize = resolved
+        .values()
+        .map(|a| a.vertex_list.len() + a.edge_list.len() + a.material_list.len())
+        .sum();
+    println!(
+        "lookups: {} links, edges to edges and mana included, in {:?}",
+        links,
+        start.elapsed()
+    );
+}
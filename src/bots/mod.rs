@@ -0,0 +1,223 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::*;
+use bevy_quinnet::shared::ClientId;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    lobby::{self, RoomStartedEvent},
+    map, players,
+    protocol::{self, PlayerColor, RoomId},
+    server::ServerConfig,
+};
+
+mod strategies;
+
+pub use strategies::{GreedyCollector, RandomWalk};
+
+/// Bots take client ids counting down from here, far away from the ids quinnet hands out.
+pub const BOT_CLIENT_ID_START: ClientId = ClientId::MAX;
+
+pub struct ServerBotsPlugin;
+
+impl Plugin for ServerBotsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Bots::default())
+            .add_system(spawn_bots)
+            .add_system(bots_think);
+    }
+}
+
+/// Something a bot wants to do this turn, sent to the server like a client event.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BotAction {
+    Move(u32),
+}
+
+/// A decision maker for one bot player.
+pub trait BotStrategy: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Called whenever the bot's player is idle, `None` means wait.
+    fn choose_action(&mut self, view: &BotView) -> Option<BotAction>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BotStrategyKind {
+    RandomWalk,
+    Greedy,
+}
+
+impl BotStrategyKind {
+    pub fn build(&self) -> Box<dyn BotStrategy> {
+        match self {
+            BotStrategyKind::RandomWalk => Box::<RandomWalk>::default(),
+            BotStrategyKind::Greedy => Box::<GreedyCollector>::default(),
+        }
+    }
+}
+
+/// What a bot is allowed to know about the map when it makes a decision.
+pub struct BotView<'a> {
+    pub current_vertex: u32,
+    pub graph: &'a map::MapGraph,
+    /// Vertexes a player stands on.
+    pub filled: &'a HashSet<u32>,
+}
+
+impl<'a> BotView<'a> {
+    pub fn neighbours(&self, vertex: u32) -> &[u32] {
+        self.graph.neighbors(vertex)
+    }
+
+    pub fn is_free(&self, vertex: u32) -> bool {
+        self.graph.contains_vertex(vertex) && !self.filled.contains(&vertex)
+    }
+
+    pub fn mana_colors(&self, vertex: u32) -> Vec<u8> {
+        self.graph
+            .materials_of(vertex)
+            .iter()
+            .filter_map(|m| self.graph.material_color(*m))
+            .collect()
+    }
+}
+
+pub struct Bot {
+    pub client_id: ClientId,
+    pub room: RoomId,
+    pub name: String,
+    pub strategy: Box<dyn BotStrategy>,
+}
+
+#[derive(Resource, Default)]
+pub struct Bots {
+    pub bots: Vec<Bot>,
+    think_timer: f32,
+}
+
+/// Adds the configured bots to the main room once its map exists,
+/// so they get start vertexes like anyone else.
+fn spawn_bots(
+    mut bots: ResMut<Bots>,
+    config: Res<ServerConfig>,
+    rooms: Res<lobby::Rooms>,
+    mut room_started: EventReader<RoomStartedEvent>,
+    start_vertexes: Query<(&map::Vertex, &RoomId), With<map::VertexStart>>,
+    mut player_spawn: EventWriter<players::PlayerSpawnEvent>,
+) {
+    if !room_started
+        .iter()
+        .any(|started| started.room == lobby::DEFAULT_ROOM)
+    {
+        return;
+    }
+
+    // A main room that went back to waiting despawned the previous bot players
+    bots.bots.retain(|bot| bot.room != lobby::DEFAULT_ROOM);
+
+    let free_starts = start_vertexes
+        .iter()
+        .filter(|(v, room)| **room == lobby::DEFAULT_ROOM && !v.filled)
+        .count();
+    let count = if config.bots.count > free_starts {
+        warn!(
+            "Only {} start vertexes are free, spawning {} of {} bots",
+            free_starts, free_starts, config.bots.count
+        );
+        free_starts
+    } else {
+        config.bots.count
+    };
+
+    // Bots take the colors the room's players left over
+    let mut colors = PlayerColor::ALL
+        .into_iter()
+        .filter(|color| {
+            !rooms
+                .rooms
+                .get(&lobby::DEFAULT_ROOM)
+                .is_some_and(|room| room.color_taken(*color))
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+        .cycle();
+
+    for i in 0..count {
+        let strategy = config.bots.strategy.build();
+        let bot = Bot {
+            client_id: BOT_CLIENT_ID_START - i as ClientId,
+            room: lobby::DEFAULT_ROOM,
+            name: format!("Bot {} ({})", i + 1, strategy.name()),
+            strategy,
+        };
+        info!("{} joined", bot.name);
+
+        player_spawn.send(players::PlayerSpawnEvent {
+            room: bot.room,
+            color: colors.next().unwrap_or(PlayerColor::ALL[0]),
+            current_vertex: None,
+            x: None,
+            y: None,
+            id: None,
+            client_owner_id: bot.client_id,
+        });
+        bots.bots.push(bot);
+    }
+}
+
+/// Lets every idle bot pick an action and feeds it through the same path as client events.
+fn bots_think(
+    mut bots: ResMut<Bots>,
+    config: Res<ServerConfig>,
+    players: Query<&players::Player>,
+    vertexes: Query<(&map::Vertex, &RoomId)>,
+    graphs: Res<map::MapGraphs>,
+    mut client_event: EventWriter<protocol::ClientEvent>,
+    time: Res<Time>,
+) {
+    if bots.bots.is_empty() {
+        return;
+    }
+
+    bots.think_timer += time.delta_seconds();
+    if bots.think_timer < config.bots.think_interval {
+        return;
+    }
+    bots.think_timer = 0.0;
+
+    let mut filled: HashMap<RoomId, HashSet<u32>> = HashMap::new();
+    for (vertex, room) in vertexes.iter().filter(|(vertex, _)| vertex.filled) {
+        filled.entry(*room).or_default().insert(vertex.id);
+    }
+    let nothing_filled = HashSet::new();
+
+    for bot in bots.bots.iter_mut() {
+        let Some(graph) = graphs.0.get(&bot.room) else {
+            continue;
+        };
+        let Some(player) = players.iter().find(|p| p.client_owner_id == bot.client_id) else {
+            continue;
+        };
+        if player.state != players::States::Idle || !player.next_entity.is_empty() {
+            continue;
+        }
+
+        let view = BotView {
+            current_vertex: player.current_vertex_id,
+            graph,
+            filled: filled.get(&bot.room).unwrap_or(&nothing_filled),
+        };
+
+        match bot.strategy.choose_action(&view) {
+            Some(BotAction::Move(vertex)) => client_event.send(protocol::ClientEvent {
+                request_id: 0,
+                command: protocol::GameCommand::Move { vertex }.into(),
+                client_id: bot.client_id,
+                room: bot.room,
+            }),
+            None => {}
+        }
+    }
+}
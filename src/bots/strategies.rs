@@ -0,0 +1,114 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use super::{BotAction, BotStrategy, BotView};
+
+/// How far the greedy bot looks for a better vertex.
+const GREEDY_SEARCH_DEPTH: usize = 6;
+
+/// Walks to a random free neighbour, the behaviour players used to have on the server.
+pub struct RandomWalk {
+    rng: StdRng,
+}
+
+impl Default for RandomWalk {
+    fn default() -> Self {
+        Self {
+            rng: StdRng::from_entropy(),
+        }
+    }
+}
+
+impl BotStrategy for RandomWalk {
+    fn name(&self) -> &'static str {
+        "random"
+    }
+
+    fn choose_action(&mut self, view: &BotView) -> Option<BotAction> {
+        let free: Vec<u32> = view
+            .neighbours(view.current_vertex)
+            .iter()
+            .copied()
+            .filter(|v| view.is_free(*v))
+            .collect();
+
+        free.choose(&mut self.rng).map(|v| BotAction::Move(*v))
+    }
+}
+
+/// Heads for the reachable vertex touching the most mana it has not collected yet.
+pub struct GreedyCollector {
+    rng: StdRng,
+    collected: HashMap<u8, u32>,
+    last_vertex: Option<u32>,
+}
+
+impl Default for GreedyCollector {
+    fn default() -> Self {
+        Self {
+            rng: StdRng::from_entropy(),
+            collected: HashMap::new(),
+            last_vertex: None,
+        }
+    }
+}
+
+impl GreedyCollector {
+    fn value(&self, view: &BotView, vertex: u32) -> f32 {
+        view.mana_colors(vertex)
+            .iter()
+            .map(|color| 1.0 / (1.0 + *self.collected.get(color).unwrap_or(&0) as f32))
+            .sum()
+    }
+
+    /// Breadth first search over free vertexes, returning the first step towards the best one.
+    fn best_first_step(&mut self, view: &BotView) -> Option<u32> {
+        let mut visited = HashSet::from([view.current_vertex]);
+        let mut queue = VecDeque::new();
+        let mut best: Option<(f32, u32)> = None;
+
+        for next in view.neighbours(view.current_vertex) {
+            if view.is_free(*next) && visited.insert(*next) {
+                queue.push_back((*next, *next, 1));
+            }
+        }
+
+        while let Some((vertex, first_step, depth)) = queue.pop_front() {
+            // Small jitter so bots with equal options don't all pick the same one
+            let score = self.value(view, vertex) / depth as f32 + self.rng.gen_range(0.0..0.01);
+            match best {
+                Some((best_score, _)) if best_score >= score => {}
+                _ => best = Some((score, first_step)),
+            }
+
+            if depth >= GREEDY_SEARCH_DEPTH {
+                continue;
+            }
+            for next in view.neighbours(vertex) {
+                if view.is_free(*next) && visited.insert(*next) {
+                    queue.push_back((*next, first_step, depth + 1));
+                }
+            }
+        }
+
+        best.map(|(_, step)| step)
+    }
+}
+
+impl BotStrategy for GreedyCollector {
+    fn name(&self) -> &'static str {
+        "greedy"
+    }
+
+    fn choose_action(&mut self, view: &BotView) -> Option<BotAction> {
+        if self.last_vertex != Some(view.current_vertex) {
+            self.last_vertex = Some(view.current_vertex);
+            for color in view.mana_colors(view.current_vertex) {
+                *self.collected.entry(color).or_insert(0) += 1;
+            }
+        }
+
+        self.best_first_step(view).map(BotAction::Move)
+    }
+}
@@ -1,10 +1,36 @@
 use bevy::prelude::*;
+use bevy_quinnet::shared::ClientId;
 use leafwing_input_manager::prelude::*;
 
 use crate::players;
 
 use super::{CameraAction, MainCamera};
 
+/// The player a spectator's camera follows, `None` leaves the camera free.
+#[derive(Resource, Default)]
+pub struct FollowedPlayer(pub Option<ClientId>);
+
+/// Steps through the players in the match, then back to the free camera.
+pub fn cycle_followed_player(
+    cam: Query<&ActionState<CameraAction>, With<MainCamera>>,
+    players: Query<&players::Player>,
+    mut followed: ResMut<FollowedPlayer>,
+) {
+    let Ok(action) = cam.get_single() else {
+        return;
+    };
+    if !action.just_pressed(CameraAction::NextPlayer) {
+        return;
+    }
+
+    let mut owners: Vec<ClientId> = players.iter().map(|p| p.client_owner_id).collect();
+    owners.sort();
+    followed.0 = match followed.0 {
+        None => owners.first().copied(),
+        Some(current) => owners.into_iter().find(|owner| *owner > current),
+    };
+}
+
 pub fn track_owned_player(
     mut cam: Query<
         (
@@ -15,8 +41,17 @@ pub fn track_owned_player(
         (With<MainCamera>, Without<players::ControlledPlayer>),
     >,
     player: Query<&Transform, (Without<MainCamera>, With<players::ControlledPlayer>)>,
+    others: Query<(&Transform, &players::Player), Without<MainCamera>>,
+    followed: Res<FollowedPlayer>,
 ) {
-    if let Ok(pos_p) = player.get_single() {
+    let target = player.get_single().ok().or_else(|| {
+        let followed = followed.0?;
+        others
+            .iter()
+            .find(|(_, p)| p.client_owner_id == followed)
+            .map(|(pos, _)| pos)
+    });
+    if let Some(pos_p) = target {
         let (mut pos_c, mut _cam, action) = cam.single_mut();
 
         if action.pressed(CameraAction::Up)
@@ -5,7 +5,7 @@ use bevy_interact_2d::InteractionPlugin;
 use bevy_interact_2d::{Group, InteractionSource};
 use leafwing_input_manager::prelude::*;
 
-use crate::map;
+use crate::{chat, client, map};
 
 mod client_camera;
 pub struct CameraPlugin;
@@ -22,7 +22,11 @@ impl Plugin for CameraPlugin {
             .add_plugin(InputManagerPlugin::<CameraAction>::default())
             .add_startup_system(setup)
             .add_system(zoom_system)
-            .add_system(move_system);
+            .add_system(
+                move_system
+                    .run_if(chat::chat_closed)
+                    .run_if(client::join_prompt_closed),
+            );
     }
 }
 
@@ -39,6 +43,12 @@ pub struct ClientCameraPlugin;
 impl Plugin for ClientCameraPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugin(CameraPlugin)
+            .insert_resource(client_camera::FollowedPlayer::default())
+            .add_system(
+                client_camera::cycle_followed_player
+                    .run_if(chat::chat_closed)
+                    .run_if(client::join_prompt_closed),
+            )
             .add_system(client_camera::track_owned_player);
     }
 }
@@ -50,6 +60,8 @@ pub enum CameraAction {
     Down,
     Left,
     Right,
+    /// Spectators switch which player the camera follows.
+    NextPlayer,
 }
 #[derive(Component)]
 pub struct MainCamera;
@@ -87,6 +99,7 @@ fn setup(mut commands: Commands) {
                 (KeyCode::S, CameraAction::Down),
                 (KeyCode::A, CameraAction::Left),
                 (KeyCode::D, CameraAction::Right),
+                (KeyCode::Tab, CameraAction::NextPlayer),
             ]),
         })
         .insert(MainCamera);
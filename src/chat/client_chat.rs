@@ -0,0 +1,38 @@
+use bevy::prelude::*;
+
+use super::{ChatLineKind, ChatLog};
+use crate::protocol::{self, ServerMessage};
+
+/// Adds chat, whispers and users coming and going to the log, from the server or a replay.
+pub fn receive_chat(
+    mut messages: EventReader<protocol::ServerMessageEvent>,
+    users: Res<protocol::Users>,
+    mut log: ResMut<ChatLog>,
+) {
+    let name = |client_id| users.names.get(&client_id).cloned();
+    for protocol::ServerMessageEvent(message) in messages.iter() {
+        match message {
+            ServerMessage::ChatMessage { client_id, message } => match name(*client_id) {
+                Some(from) => log.push(ChatLineKind::Message { from }, message),
+                None => warn!("Chat message from an unknown client_id: {}", client_id),
+            },
+            ServerMessage::Whisper { from, to, message } => {
+                let (Some(from), Some(to)) = (name(*from), name(*to)) else {
+                    warn!("Whisper between unknown clients: {} > {}", from, to);
+                    continue;
+                };
+                log.push(ChatLineKind::Whisper { from, to }, message);
+            }
+            ServerMessage::ServerNotice { message } => log.system(format!("Server: {}", message)),
+            ServerMessage::ClientConnected { username, .. } => {
+                log.system(format!("{} joined", username))
+            }
+            ServerMessage::ClientDisconnected { client_id } => {
+                if let Some(username) = name(*client_id) {
+                    log.system(format!("{} left", username));
+                }
+            }
+            _ => {}
+        }
+    }
+}
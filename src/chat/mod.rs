@@ -0,0 +1,139 @@
+use std::{
+    collections::VecDeque,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    client,
+    protocol::{ClientMessage, Users},
+};
+
+mod client_chat;
+
+/// Lines kept in the scrollback, older ones are dropped.
+pub const HISTORY_LINES: usize = 200;
+
+pub struct ClientChatPlugin;
+
+impl Plugin for ClientChatPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ChatLog::default())
+            // Names of users who left are only there until `handle_server_messages` drops them
+            .add_system(client_chat::receive_chat.before(client::handle_server_messages));
+    }
+}
+
+/// Everything said where this client can hear it, and what it is typing.
+#[derive(Resource, Default)]
+pub struct ChatLog {
+    pub lines: VecDeque<ChatLine>,
+    /// The message being typed, `None` while the chat input is closed.
+    pub input: Option<String>,
+    /// How many lines up from the newest one the scrollback is.
+    pub scroll: usize,
+}
+
+impl ChatLog {
+    pub fn push(&mut self, kind: ChatLineKind, text: impl Into<String>) {
+        self.lines.push_back(ChatLine {
+            time: clock(),
+            kind,
+            text: text.into(),
+        });
+        if self.lines.len() > HISTORY_LINES {
+            self.lines.pop_front();
+        }
+        // Someone scrolled up to read keeps looking at the same lines
+        if self.scroll > 0 {
+            self.scroll = (self.scroll + 1).min(self.lines.len().saturating_sub(1));
+        }
+    }
+
+    pub fn system(&mut self, text: impl Into<String>) {
+        self.push(ChatLineKind::System, text);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatLine {
+    /// When the line came in, as `HH:MM` UTC.
+    pub time: String,
+    pub kind: ChatLineKind,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChatLineKind {
+    Message {
+        from: String,
+    },
+    Whisper {
+        from: String,
+        to: String,
+    },
+    /// Users joining and leaving, notices from the server and chat input the client could
+    /// not send.
+    System,
+}
+
+impl std::fmt::Display for ChatLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            ChatLineKind::Message { from } => write!(f, "[{}] {}: {}", self.time, from, self.text),
+            ChatLineKind::Whisper { from, to } => {
+                write!(f, "[{}] {} > {}: {}", self.time, from, to, self.text)
+            }
+            ChatLineKind::System => write!(f, "[{}] * {}", self.time, self.text),
+        }
+    }
+}
+
+/// Turns what was typed into the message to send, `/w <name> <message>` whispers to one user
+/// and anything else goes to everyone in the room. `Ok(None)` for nothing to send.
+pub fn parse_input(input: &str, users: &Users) -> Result<Option<ClientMessage>, String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Ok(None);
+    }
+    let Some(whisper) = input.strip_prefix("/w ") else {
+        return Ok(Some(ClientMessage::ChatMessage {
+            message: input.to_string(),
+        }));
+    };
+    let (name, message) = whisper
+        .trim_start()
+        .split_once(' ')
+        .unwrap_or((whisper, ""));
+    let message = message.trim();
+    if message.is_empty() {
+        return Err("Usage: /w <name> <message>".to_string());
+    }
+    let to = users
+        .names
+        .iter()
+        .find(|(_, user)| user.as_str() == name)
+        .map(|(client_id, _)| *client_id)
+        .ok_or_else(|| format!("No user named {}", name))?;
+    Ok(Some(ClientMessage::Whisper {
+        to,
+        message: message.to_string(),
+    }))
+}
+
+/// Run condition for keyboard controls that should not fire while a message is being typed.
+pub fn chat_closed(chat: Option<Res<ChatLog>>) -> bool {
+    match chat {
+        Some(chat) => chat.input.is_none(),
+        None => true,
+    }
+}
+
+fn clock() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default();
+    format!("{:02}:{:02}", seconds / 3600 % 24, seconds / 60 % 60)
+}
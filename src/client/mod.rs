@@ -1,13 +1,175 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::PathBuf,
+};
+
 use bevy::prelude::*;
+use bevy_quinnet::client::{
+    certificate::{CertInteractionEvent, CertVerifierAction},
+    QuinnetClientPlugin,
+};
+
+use crate::{chat, discovery, editor, lobby, map, players, protocol, replay};
+
 mod systems;
 
+pub use systems::handle_server_messages;
+
 #[derive(Component)]
 pub struct ClientAbilityState(String);
 
+/// Where the client connects to, the first command line argument overrides the address and
+/// without one the client browses the LAN for servers. `--replay <file>` plays a recorded
+/// match back and `--edit <file>` opens a level in the editor, both instead of connecting.
+#[derive(Resource, Debug, Clone)]
+pub struct ClientConfig {
+    pub server_addr: SocketAddr,
+    pub replay: Option<PathBuf>,
+    pub edit: Option<PathBuf>,
+    /// Set with `--name <name>`, joining under the same name again gets a restored seat back.
+    pub name: Option<String>,
+    /// Set with `--password <password>` or typed in when the server asks for one.
+    pub password: Option<String>,
+    /// Where server certificate fingerprints are pinned on first connection. `--insecure`
+    /// turns that off and accepts any certificate.
+    pub known_hosts: Option<String>,
+    /// Lists the servers found on the LAN and connects to the one picked, instead of
+    /// connecting to `server_addr` right away.
+    pub browse: bool,
+}
+
+/// The name being typed before joining, open when the server refused the last one.
+#[derive(Resource, Debug, Default)]
+pub struct NameEntry {
+    pub editing: Option<String>,
+    pub rejection: Option<protocol::NameRejection>,
+}
+
+/// The password being typed, open when the server asked for one.
+#[derive(Resource, Debug, Default)]
+pub struct PasswordEntry {
+    pub editing: Option<String>,
+    pub rejection: Option<protocol::PasswordRejection>,
+    /// What to join as once the password is in.
+    pub name: String,
+}
+
+/// A server certificate that doesn't match the pinned fingerprint, connecting waits until the
+/// user decides whether to trust it.
+#[derive(Resource, Default)]
+pub struct CertificatePrompt {
+    pub pending: Option<CertInteractionEvent>,
+}
+
+impl CertificatePrompt {
+    /// Connects or gives up as the user decided, the prompt closes either way.
+    pub fn answer(&mut self, action: CertVerifierAction) {
+        let Some(pending) = self.pending.take() else {
+            return;
+        };
+        if let Err(e) = pending.apply_cert_verifier_action(action) {
+            warn!("Failed to answer the certificate check: {:?}", e);
+        }
+    }
+}
+
+/// Run condition for systems reading the keyboard, which would react to the name or password
+/// being typed.
+pub fn join_prompt_closed(
+    name: Option<Res<NameEntry>>,
+    password: Option<Res<PasswordEntry>>,
+) -> bool {
+    let name_closed = match name {
+        Some(name) => name.editing.is_none(),
+        None => true,
+    };
+    let password_closed = match password {
+        Some(password) => password.editing.is_none(),
+        None => true,
+    };
+    name_closed && password_closed
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            server_addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 6000),
+            replay: None,
+            edit: None,
+            name: None,
+            password: None,
+            known_hosts: Some("known_hosts".to_string()),
+            browse: false,
+        }
+    }
+}
+
+impl ClientConfig {
+    pub fn from_args() -> Self {
+        let mut config = ClientConfig {
+            browse: true,
+            ..default()
+        };
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--replay" => config.replay = args.next().map(PathBuf::from),
+                "--edit" => config.edit = args.next().map(PathBuf::from),
+                "--name" => config.name = args.next(),
+                "--password" => config.password = args.next(),
+                "--insecure" => config.known_hosts = None,
+                addr => {
+                    if let Ok(addr) = addr.parse() {
+                        config.server_addr = addr;
+                        config.browse = false;
+                    }
+                }
+            }
+        }
+        config
+    }
+}
+
+/// Everything the client needs except for rendering, so it can also run headless.
 pub struct ClientPlugin;
 
 impl Plugin for ClientPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(systems::setup);
+        if !app.world.contains_resource::<ClientConfig>() {
+            app.insert_resource(ClientConfig::default());
+        }
+
+        app.insert_resource(protocol::IsServer(false))
+            .add_plugin(map::ClientMapPlugin)
+            .add_plugin(players::ClientPlayersPlugin)
+            .add_plugin(lobby::ClientLobbyPlugin)
+            .add_plugin(chat::ClientChatPlugin)
+            .add_plugin(QuinnetClientPlugin::default())
+            .insert_resource(protocol::Users::default())
+            .init_resource::<NameEntry>()
+            .init_resource::<PasswordEntry>()
+            .init_resource::<CertificatePrompt>()
+            .add_event::<protocol::ServerMessageEvent>()
+            .add_startup_system(systems::setup)
+            .add_system(systems::receive_server_messages.before(systems::handle_server_messages))
+            .add_system(systems::handle_server_messages.before(map::despawn_room))
+            .add_system(systems::handle_join_replies.after(systems::receive_server_messages))
+            .add_system(systems::on_app_exit)
+            .add_system(systems::handle_certificate_events);
+
+        let config = app.world.resource::<ClientConfig>();
+        let (edit, replay) = (config.edit.is_some(), config.replay.is_some());
+        let browse = config.browse;
+        if edit {
+            app.add_plugin(editor::EditorPlugin);
+        } else if replay {
+            app.add_plugin(replay::ClientReplayPlugin);
+        } else if browse {
+            app.add_plugin(discovery::ClientDiscoveryPlugin)
+                .add_system(systems::connect_to_chosen_server);
+        } else {
+            app.add_startup_system(systems::start_connection.in_base_set(StartupSet::PreStartup))
+                .add_startup_system(systems::join_game.in_base_set(StartupSet::Startup));
+        }
     }
 }
@@ -1,9 +1,312 @@
-use bevy::prelude::*;
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    thread::sleep,
+    time::Duration,
+};
 
-use crate::protocol;
+use bevy::{app::AppExit, prelude::*};
+use bevy_quinnet::client::{
+    certificate::{
+        CertConnectionAbortEvent, CertInteractionEvent, CertTrustUpdateEvent,
+        CertVerificationStatus, CertVerifierAction, CertVerifierBehaviour,
+        CertificateVerificationMode, KnownHosts, TrustOnFirstUseConfig,
+    },
+    connection::ConnectionConfiguration,
+    Client,
+};
+
+use crate::{
+    discovery::ServerBrowser,
+    lobby::LobbyState,
+    map,
+    protocol::{self, ClientMessage, ServerMessage},
+};
+
+use super::{CertificatePrompt, ClientConfig, NameEntry, PasswordEntry};
 
 pub fn setup(mut commands: Commands) {
     commands.spawn(protocol::CurrentClientEventTrigger(
-        protocol::ClientEvents::MOVE,
+        protocol::CommandKind::Move,
     ));
 }
+
+pub fn on_app_exit(app_exit_events: EventReader<AppExit>, client: Res<Client>) {
+    if !app_exit_events.is_empty() {
+        // Replays never open a connection
+        let Some(connection) = client.get_connection() else {
+            return;
+        };
+        connection
+            .send_message(ClientMessage::Disconnect {})
+            .unwrap();
+        // TODO Clean: event to let the async client send his last messages.
+        sleep(Duration::from_secs_f32(0.1));
+    }
+}
+
+/// Pins the certificate of servers met for the first time, and asks the user through
+/// `CertificatePrompt` when a known server shows another one.
+fn verification_mode(config: &ClientConfig) -> CertificateVerificationMode {
+    let Some(known_hosts) = &config.known_hosts else {
+        return CertificateVerificationMode::SkipVerification;
+    };
+    let verifier_behaviour = [
+        (
+            CertVerificationStatus::UnknownCertificate,
+            CertVerifierBehaviour::ImmediateAction(CertVerifierAction::TrustAndStore),
+        ),
+        (
+            CertVerificationStatus::UntrustedCertificate,
+            CertVerifierBehaviour::RequestClientAction,
+        ),
+        (
+            CertVerificationStatus::TrustedCertificate,
+            CertVerifierBehaviour::ImmediateAction(CertVerifierAction::TrustOnce),
+        ),
+    ];
+    CertificateVerificationMode::TrustOnFirstUse(TrustOnFirstUseConfig {
+        known_hosts: KnownHosts::HostsFile(known_hosts.clone()),
+        verifier_behaviour: verifier_behaviour.into_iter().collect(),
+    })
+}
+
+pub fn start_connection(mut client: ResMut<Client>, config: Res<ClientConfig>) {
+    connect(&mut client, &config);
+}
+
+fn connect(client: &mut Client, config: &ClientConfig) {
+    match client.open_connection(
+        ConnectionConfiguration::from_addrs(
+            config.server_addr,
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0),
+        ),
+        verification_mode(config),
+    ) {
+        Ok(info) => {
+            println!("started connection {} with ChannelId: {:?}", info.0, info.1);
+        }
+
+        Err(e) => {
+            println!("failed to connect, Error {:?}", e);
+        }
+    }
+    // When trully connected, you will receive a ConnectionEvent
+}
+
+pub fn join_game(mut client: ResMut<Client>, config: Res<ClientConfig>, entry: Res<NameEntry>) {
+    // The name is typed in first, see `ui::name_panel`
+    if entry.editing.is_some() {
+        return;
+    }
+    let name = config.name.clone().unwrap_or_else(|| {
+        let x = rand::random::<u32>();
+        "Test".to_owned() + &x.to_string()
+    });
+    join(&mut client, &config, name);
+}
+
+fn join(client: &mut Client, config: &ClientConfig, name: String) {
+    if let Ok(_temp) = client.connection_mut().send_message(ClientMessage::Join {
+        name,
+        password: config.password.clone(),
+    }) {
+    } else {
+        println!("Failed to Join Game");
+    }
+}
+
+/// Connects to the server picked in the browser, asking for a name first without `--name`.
+pub fn connect_to_chosen_server(
+    browser: Option<ResMut<ServerBrowser>>,
+    mut config: ResMut<ClientConfig>,
+    mut client: ResMut<Client>,
+    mut entry: ResMut<NameEntry>,
+) {
+    let Some(mut browser) = browser else {
+        return;
+    };
+    let Some(addr) = browser.chosen.take() else {
+        return;
+    };
+    browser.browsing = false;
+    info!("Connecting to {}", addr);
+    config.server_addr = addr;
+    connect(&mut client, &config);
+    match config.name.clone() {
+        Some(name) => join(&mut client, &config, name),
+        None => entry.editing = Some(String::new()),
+    }
+}
+
+/// Keeps certificates waiting for the user in `CertificatePrompt`, the events themselves only
+/// last two frames.
+pub fn handle_certificate_events(
+    mut interactions: ResMut<Events<CertInteractionEvent>>,
+    mut trust_updates: EventReader<CertTrustUpdateEvent>,
+    mut aborts: EventReader<CertConnectionAbortEvent>,
+    mut prompt: ResMut<CertificatePrompt>,
+) {
+    for interaction in interactions.drain() {
+        warn!(
+            "The certificate of {:?} changed, was {}, is now {}",
+            interaction.info.server_name,
+            interaction
+                .info
+                .known_fingerprint
+                .as_ref()
+                .map(|fingerprint| fingerprint.to_base64())
+                .unwrap_or_default(),
+            interaction.info.fingerprint.to_base64()
+        );
+        prompt.pending = Some(interaction);
+    }
+    for update in trust_updates.iter() {
+        info!(
+            "Pinned the certificate of {:?}: {}",
+            update.cert_info.server_name,
+            update.cert_info.fingerprint.to_base64()
+        );
+    }
+    for abort in aborts.iter() {
+        warn!(
+            "Did not connect to {:?}, its certificate is not trusted",
+            abort.cert_info.server_name
+        );
+    }
+}
+
+pub fn receive_server_messages(
+    mut client: ResMut<Client>,
+    mut messages: EventWriter<protocol::ServerMessageEvent>,
+) {
+    let Some(connection) = client.get_connection_mut() else {
+        return;
+    };
+    while let Some(message) = connection.try_receive_message::<ServerMessage>() {
+        messages.send(protocol::ServerMessageEvent(message));
+    }
+}
+
+/// Opens the name or password entry when the server refused them, closes both once joined.
+pub fn handle_join_replies(
+    mut messages: EventReader<protocol::ServerMessageEvent>,
+    mut entry: ResMut<NameEntry>,
+    mut password: ResMut<PasswordEntry>,
+) {
+    for protocol::ServerMessageEvent(message) in messages.iter() {
+        match message {
+            ServerMessage::NameRejected { name, reason } => {
+                warn!("Could not join as {:?}: {}", name, reason);
+                entry.editing = Some(name.clone());
+                entry.rejection = Some(reason.clone());
+            }
+            ServerMessage::PasswordRejected { name, reason } => {
+                warn!("Could not join as {:?}: {}", name, reason);
+                password.editing = Some(String::new());
+                password.rejection = Some(reason.clone());
+                password.name = name.clone();
+            }
+            ServerMessage::InitClient { .. } => {
+                *entry = NameEntry::default();
+                *password = PasswordEntry::default();
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn handle_server_messages(
+    mut messages: EventReader<protocol::ServerMessageEvent>,
+    mut users: ResMut<protocol::Users>,
+    mut lobby: ResMut<LobbyState>,
+    mut init_map: EventWriter<map::MapObjectSpawnEvent>,
+    mut despawn_room: EventWriter<map::DespawnRoomEvent>,
+    mut update_map: EventWriter<protocol::ServerUpdateMapEvent>,
+    mut update_player: EventWriter<protocol::ServerUpdatePlayerEvent>,
+) {
+    for protocol::ServerMessageEvent(message) in messages.iter() {
+        match message.clone() {
+            // Names come with `UserListUpdate`, these only say who came and went nearby
+            ServerMessage::ClientConnected { username, .. } => info!("{} joined", username),
+            ServerMessage::ClientDisconnected { client_id } => {
+                if let Some(username) = users.names.get(&client_id) {
+                    println!("{} left", username);
+                } else {
+                    warn!("ClientDisconnected for an unknown client_id: {}", client_id)
+                }
+            }
+            ServerMessage::UserListUpdate {
+                client_id,
+                username: Some(username),
+            } => {
+                users.names.insert(client_id, username);
+            }
+            ServerMessage::UserListUpdate {
+                client_id,
+                username: None,
+            } => {
+                users.names.remove(&client_id);
+            }
+            // Shown in the chat panel, see `chat::ChatLog`
+            ServerMessage::ChatMessage { .. }
+            | ServerMessage::Whisper { .. }
+            | ServerMessage::ServerNotice { .. } => {}
+            ServerMessage::InitClient {
+                client_id,
+                usernames,
+            } => {
+                users.self_id = client_id;
+                users.names = usernames;
+            }
+
+            ServerMessage::InitMap {
+                vertexes,
+                edges,
+                materials,
+            } => {
+                let room = lobby.current_room.unwrap_or_default();
+                // A level edited on the server is sent again, replacing the one shown
+                despawn_room.send(map::DespawnRoomEvent(room));
+                init_map.send_batch(map::init_map_events(room, &vertexes, &edges, &materials));
+            }
+            ServerMessage::UpdateMap {
+                vertexes,
+                edges,
+                materials,
+            } => update_map.send(protocol::ServerUpdateMapEvent {
+                vertexes,
+                edges,
+                materials,
+            }),
+            ServerMessage::UpdatePlayers { players } => {
+                update_player.send(protocol::ServerUpdatePlayerEvent { players })
+            }
+            ServerMessage::RoomList { rooms, levels } => {
+                lobby.rooms = rooms;
+                lobby.levels = levels;
+            }
+            ServerMessage::RoomJoined { room } => {
+                info!("Joined room {}", room.name);
+                lobby.current_room = Some(room.id);
+                lobby.rejection = None;
+                match lobby.rooms.iter_mut().find(|r| r.id == room.id) {
+                    Some(known) => *known = room,
+                    None => lobby.rooms.push(room),
+                }
+            }
+            ServerMessage::JoinRejected { room, reason } => {
+                warn!("Could not join room {:?}: {}", room, reason);
+                lobby.rejection = Some(reason);
+            }
+            // Handled by `handle_join_replies`
+            ServerMessage::NameRejected { .. } | ServerMessage::PasswordRejected { .. } => {}
+            // Replies to this client's actions, see `players::ActionRequests`
+            ServerMessage::ActionAccepted { .. } | ServerMessage::ActionRejected { .. } => {}
+            ServerMessage::RoomLeft {} => {
+                if let Some(room) = lobby.current_room.take() {
+                    despawn_room.send(map::DespawnRoomEvent(room));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+
+use bevy::prelude::*;
+
+use super::{
+    DiscoveryMessage, ServerInfo, DISCOVERY_PORT, DISCOVERY_VERSION, PROBE_INTERVAL, SERVER_TIMEOUT,
+};
+
+/// A server that answered a probe.
+#[derive(Debug, Clone)]
+pub struct DiscoveredServer {
+    /// Where to connect, the game port of the server.
+    pub addr: SocketAddr,
+    pub info: ServerInfo,
+    /// When the last reply came in, in seconds.
+    pub seen: f32,
+}
+
+/// Servers found on the LAN, listed until one is picked.
+#[derive(Resource)]
+pub struct ServerBrowser {
+    socket: Option<UdpSocket>,
+    /// Where probes go: the broadcast address, and loopback for servers on this machine.
+    pub targets: Vec<SocketAddr>,
+    pub servers: Vec<DiscoveredServer>,
+    /// Whether the browser is shown and probing, until a server is picked.
+    pub browsing: bool,
+    /// The server picked, taken by `client::connect_to_chosen_server`.
+    pub chosen: Option<SocketAddr>,
+    last_probe: Option<f32>,
+}
+
+impl ServerBrowser {
+    pub fn open() -> Self {
+        Self::with_port(DISCOVERY_PORT)
+    }
+
+    /// A browser probing servers that listen on `port` instead of the usual one.
+    pub fn with_port(port: u16) -> Self {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).and_then(|socket| {
+            socket.set_broadcast(true)?;
+            socket.set_nonblocking(true)?;
+            Ok(socket)
+        });
+        let socket = match socket {
+            Ok(socket) => Some(socket),
+            Err(e) => {
+                warn!("No server browser, the probe socket failed: {}", e);
+                None
+            }
+        };
+        Self {
+            socket,
+            targets: vec![
+                SocketAddr::from((Ipv4Addr::BROADCAST, port)),
+                SocketAddr::from((Ipv4Addr::LOCALHOST, port)),
+            ],
+            servers: Vec::new(),
+            browsing: true,
+            chosen: None,
+            last_probe: None,
+        }
+    }
+
+    /// Forgets the servers found so far and probes again right away.
+    pub fn refresh(&mut self) {
+        self.servers.clear();
+        self.last_probe = None;
+    }
+}
+
+pub fn probe_servers(mut browser: ResMut<ServerBrowser>, time: Res<Time>) {
+    let now = time.elapsed_seconds();
+    if !browser.browsing {
+        return;
+    }
+    match browser.last_probe {
+        Some(last) if now - last < PROBE_INTERVAL => return,
+        _ => {}
+    }
+    // Probing alone doesn't change what the browser shows
+    let browser = browser.bypass_change_detection();
+    browser.last_probe = Some(now);
+    let Some(socket) = &browser.socket else {
+        return;
+    };
+    let Ok(probe) = bincode::serialize(&DiscoveryMessage::Probe {
+        version: DISCOVERY_VERSION,
+    }) else {
+        return;
+    };
+    for target in browser.targets.iter() {
+        // Broadcasts fail on machines without a network, loopback still works
+        if let Err(e) = socket.send_to(&probe, target) {
+            debug!("Probe to {} failed: {}", target, e);
+        }
+    }
+}
+
+pub fn receive_replies(mut browser: ResMut<ServerBrowser>, time: Res<Time>) {
+    let now = time.elapsed_seconds();
+    let Some(socket) = &browser.socket else {
+        return;
+    };
+    let replies = super::receive(socket);
+
+    let expired = browser
+        .servers
+        .iter()
+        .any(|server| now - server.seen > SERVER_TIMEOUT);
+    if expired {
+        browser
+            .servers
+            .retain(|server| now - server.seen <= SERVER_TIMEOUT);
+    }
+
+    for (message, from) in replies {
+        let DiscoveryMessage::Reply { version, info } = message else {
+            continue;
+        };
+        if version != DISCOVERY_VERSION {
+            continue;
+        }
+        let addr = SocketAddr::new(from.ip(), info.port);
+        // Broadcast and loopback probes both reach servers on this machine
+        let known = browser.servers.iter().position(|server| {
+            server.addr == addr
+                || (server.addr.port() == addr.port()
+                    && server.info.name == info.name
+                    && (server.addr.ip().is_loopback() || addr.ip().is_loopback()))
+        });
+        match known {
+            // Still there, nothing to show again
+            Some(index) if browser.servers[index].info == info => {
+                browser.bypass_change_detection().servers[index].seen = now
+            }
+            Some(index) => {
+                let server = &mut browser.servers[index];
+                server.seen = now;
+                server.info = info;
+            }
+            None => browser.servers.push(DiscoveredServer {
+                addr,
+                info,
+                seen: now,
+            }),
+        }
+    }
+}
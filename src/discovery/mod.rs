@@ -0,0 +1,104 @@
+use std::net::{SocketAddr, UdpSocket};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+mod client_discovery;
+mod server_discovery;
+
+pub use client_discovery::{DiscoveredServer, ServerBrowser};
+pub use server_discovery::ServerDiscovery;
+
+/// Bumped whenever a discovery message changes, servers don't answer other versions.
+pub const DISCOVERY_VERSION: u32 = 1;
+
+/// Where servers listen for probes unless configured otherwise.
+pub const DISCOVERY_PORT: u16 = 6001;
+
+/// Seconds between two probes of the server browser.
+pub const PROBE_INTERVAL: f32 = 2.0;
+
+/// Seconds a server stays listed after its last reply.
+pub const SERVER_TIMEOUT: f32 = 6.0;
+
+/// Datagrams larger than this are not discovery messages.
+const MAX_DATAGRAM: usize = 1024;
+
+/// How servers answer LAN discovery probes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiscoveryConfig {
+    pub enabled: bool,
+    /// UDP port probes are expected on, not the game port.
+    pub port: u16,
+    /// Shown in server browsers.
+    pub name: String,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            port: DISCOVERY_PORT,
+            name: "Catan server".to_string(),
+        }
+    }
+}
+
+/// What a server tells the browsers that probed it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerInfo {
+    pub name: String,
+    /// Game port, the address is the one the reply came from.
+    pub port: u16,
+    /// Connected users, in any room.
+    pub players: usize,
+    /// Seats of the main room.
+    pub max_players: usize,
+    /// Level of the main room.
+    pub level: String,
+    pub password: bool,
+}
+
+/// Sent as single bincode datagrams.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DiscoveryMessage {
+    Probe { version: u32 },
+    Reply { version: u32, info: ServerInfo },
+}
+
+pub struct ServerDiscoveryPlugin;
+
+impl Plugin for ServerDiscoveryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(server_discovery::start_discovery)
+            .add_system(server_discovery::answer_probes);
+    }
+}
+
+/// The server browser, for clients started without a server address.
+pub struct ClientDiscoveryPlugin;
+
+impl Plugin for ClientDiscoveryPlugin {
+    fn build(&self, app: &mut App) {
+        // Tests bring a browser probing the discovery port of their server
+        if !app.world.contains_resource::<ServerBrowser>() {
+            app.insert_resource(ServerBrowser::open());
+        }
+        app.add_system(client_discovery::probe_servers)
+            .add_system(client_discovery::receive_replies.after(client_discovery::probe_servers));
+    }
+}
+
+/// Reads every datagram waiting on a non-blocking `socket`.
+fn receive(socket: &UdpSocket) -> Vec<(DiscoveryMessage, SocketAddr)> {
+    let mut buffer = [0; MAX_DATAGRAM];
+    let mut messages = Vec::new();
+    while let Ok((length, from)) = socket.recv_from(&mut buffer) {
+        // Anything else on the port is ignored
+        if let Ok(message) = bincode::deserialize(&buffer[..length]) {
+            messages.push((message, from));
+        }
+    }
+    messages
+}
@@ -0,0 +1,66 @@
+use std::net::{Ipv4Addr, UdpSocket};
+
+use bevy::prelude::*;
+
+use super::{DiscoveryMessage, ServerInfo, DISCOVERY_VERSION};
+use crate::{lobby, protocol, server::ServerConfig};
+
+/// The socket probes come in on, only there when discovery is on and its port was free.
+#[derive(Resource)]
+pub struct ServerDiscovery {
+    socket: UdpSocket,
+}
+
+pub fn start_discovery(mut commands: Commands, config: Res<ServerConfig>) {
+    if !config.discovery.enabled {
+        return;
+    }
+    let port = config.discovery.port;
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))
+        .and_then(|socket| socket.set_nonblocking(true).map(|_| socket));
+    match socket {
+        Ok(socket) => {
+            info!("Answering LAN discovery probes on port {}", port);
+            commands.insert_resource(ServerDiscovery { socket });
+        }
+        Err(e) => warn!("LAN discovery is off, port {} is unavailable: {}", port, e),
+    }
+}
+
+pub fn answer_probes(
+    discovery: Option<Res<ServerDiscovery>>,
+    config: Res<ServerConfig>,
+    users: Res<protocol::Users>,
+    rooms: Res<lobby::Rooms>,
+) {
+    let Some(discovery) = discovery else {
+        return;
+    };
+    for (message, from) in super::receive(&discovery.socket) {
+        let DiscoveryMessage::Probe { version } = message else {
+            continue;
+        };
+        if version != DISCOVERY_VERSION {
+            continue;
+        }
+        let main_room = rooms.rooms.get(&lobby::DEFAULT_ROOM);
+        let info = ServerInfo {
+            name: config.discovery.name.clone(),
+            port: config.port,
+            players: users.names.len(),
+            max_players: main_room.map(|room| room.capacity()).unwrap_or_default(),
+            level: main_room
+                .map(|room| room.level.clone())
+                .unwrap_or_else(|| config.level.clone()),
+            password: config.access.password.is_some(),
+        };
+        let reply = DiscoveryMessage::Reply {
+            version: DISCOVERY_VERSION,
+            info,
+        };
+        // Best effort, the browser probes again anyway
+        if let Ok(bytes) = bincode::serialize(&reply) {
+            let _ = discovery.socket.send_to(&bytes, from);
+        }
+    }
+}
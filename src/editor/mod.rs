@@ -0,0 +1,365 @@
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+
+use crate::{
+    lobby,
+    map::{
+        self,
+        level::{
+            self, LevelProblem, MapInitData, MapInitMana, MapInitManaConnections, MANA_COLORS,
+        },
+    },
+    protocol::RoomId,
+};
+
+mod systems;
+
+/// The room the edited level is spawned in, the editor never joins a real one.
+pub const EDITOR_ROOM: RoomId = lobby::DEFAULT_ROOM;
+
+/// Edits a level file in the client instead of connecting, see `ClientConfig::edit`.
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(systems::load_level)
+            .add_system(systems::editor_keys)
+            .add_system(systems::edit_map.after(systems::editor_keys))
+            .add_system(
+                systems::spawn_edited_level
+                    .after(systems::edit_map)
+                    .before(map::despawn_room),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditorTool {
+    /// Drags vertexes and mana points around.
+    Move,
+    Vertex,
+    /// Connects two vertexes clicked one after the other.
+    Edge,
+    Mana,
+    /// Links a mana point to a vertex, or unlinks them.
+    Link,
+    Start,
+    Delete,
+}
+
+impl EditorTool {
+    pub const ALL: [EditorTool; 7] = [
+        EditorTool::Move,
+        EditorTool::Vertex,
+        EditorTool::Edge,
+        EditorTool::Mana,
+        EditorTool::Link,
+        EditorTool::Start,
+        EditorTool::Delete,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EditorTool::Move => "Move",
+            EditorTool::Vertex => "Vertex",
+            EditorTool::Edge => "Edge",
+            EditorTool::Mana => "Mana",
+            EditorTool::Link => "Link",
+            EditorTool::Start => "Start",
+            EditorTool::Delete => "Delete",
+        }
+    }
+}
+
+/// What the editor can do from the keyboard or the editor panel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EditorAction {
+    Tool(EditorTool),
+    NextColor,
+    Undo,
+    Redo,
+    Save,
+}
+
+/// A map object of the edited level, by its id in the level file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Picked {
+    Vertex(u32),
+    Edge(u32),
+    Mana(u32),
+}
+
+/// The level being edited, with the history to undo and redo changes.
+#[derive(Resource)]
+pub struct LevelEditor {
+    pub path: PathBuf,
+    pub level: MapInitData,
+    pub tool: EditorTool,
+    pub mana_color: usize,
+    /// The first of the two objects an edge or a link is made of.
+    pub pending: Option<Picked>,
+    /// The outcome of the last save, undo or redo, for the editor panel.
+    pub status: String,
+    undo: Vec<MapInitData>,
+    redo: Vec<MapInitData>,
+    /// The map shown no longer matches the level.
+    changed: bool,
+}
+
+impl LevelEditor {
+    pub fn new(path: PathBuf, level: MapInitData) -> Self {
+        Self {
+            path,
+            level,
+            tool: EditorTool::Move,
+            mana_color: 0,
+            pending: None,
+            status: String::new(),
+            undo: Vec::new(),
+            redo: Vec::new(),
+            changed: true,
+        }
+    }
+
+    /// Opens the level at `path`, or starts an empty one if there is no file yet.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        let level = match std::fs::read(path) {
+            Ok(bytes) => level::read_level(&bytes, &name)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => MapInitData::new(&name),
+            Err(e) => return Err(format!("Could not read {:?}: {}", path, e)),
+        };
+        Ok(Self::new(path.to_path_buf(), level))
+    }
+
+    pub fn apply(&mut self, action: EditorAction) {
+        match action {
+            EditorAction::Tool(tool) => {
+                self.tool = tool;
+                self.pending = None;
+            }
+            EditorAction::NextColor => self.mana_color = (self.mana_color + 1) % MANA_COLORS.len(),
+            EditorAction::Undo => {
+                self.status = if self.undo() {
+                    "Undone"
+                } else {
+                    "Nothing to undo"
+                }
+                .to_string()
+            }
+            EditorAction::Redo => {
+                self.status = if self.redo() {
+                    "Redone"
+                } else {
+                    "Nothing to redo"
+                }
+                .to_string()
+            }
+            EditorAction::Save => {
+                self.status = match self.save() {
+                    Ok(problems) if problems.is_empty() => format!("Saved {:?}", self.path),
+                    Ok(problems) => format!(
+                        "Saved {:?} with {} problems, the first is {}",
+                        self.path,
+                        problems.len(),
+                        problems[0]
+                    ),
+                    Err(e) => e,
+                }
+            }
+        }
+    }
+
+    /// Writes the level, problems don't stop it from being saved but keep it from loading.
+    pub fn save(&self) -> Result<Vec<LevelProblem>, String> {
+        level::write_level(&self.path, &self.level)?;
+        Ok(level::validate(&self.level))
+    }
+
+    /// Whether the level changed since the last call, so its map has to be spawned again.
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    /// Remembers the level as it is now, to come back to it with `undo`.
+    pub fn checkpoint(&mut self) {
+        self.push_undo(self.level.clone());
+    }
+
+    /// Makes `before` the state `undo` goes back to, for changes made over several updates.
+    pub fn push_undo(&mut self, before: MapInitData) {
+        self.undo.push(before);
+        self.redo.clear();
+    }
+
+    pub fn undo(&mut self) -> bool {
+        let Some(level) = self.undo.pop() else {
+            return false;
+        };
+        self.redo.push(std::mem::replace(&mut self.level, level));
+        self.pending = None;
+        self.changed = true;
+        true
+    }
+
+    pub fn redo(&mut self) -> bool {
+        let Some(level) = self.redo.pop() else {
+            return false;
+        };
+        self.undo.push(std::mem::replace(&mut self.level, level));
+        self.pending = None;
+        self.changed = true;
+        true
+    }
+
+    pub fn add_vertex(&mut self, pos: Vec2) -> u32 {
+        self.checkpoint();
+        self.changed = true;
+        self.level
+            .vertex_positions
+            .push(level::MapInitVertexPositions { x: pos.x, y: pos.y });
+        self.level.vertex_positions.len() as u32 - 1
+    }
+
+    pub fn add_mana(&mut self, pos: Vec2) -> u32 {
+        self.checkpoint();
+        self.changed = true;
+        self.level.mana_points.push(MapInitMana {
+            x: pos.x,
+            y: pos.y,
+            color: MANA_COLORS[self.mana_color].to_string(),
+        });
+        self.level.mana_points.len() as u32 - 1
+    }
+
+    /// Moves a vertex or mana point without a checkpoint, dragging calls this every update.
+    pub fn move_to(&mut self, picked: Picked, pos: Vec2) {
+        let target = match picked {
+            Picked::Vertex(id) => self
+                .level
+                .vertex_positions
+                .get_mut(id as usize)
+                .map(|v| (&mut v.x, &mut v.y)),
+            Picked::Mana(id) => self
+                .level
+                .mana_points
+                .get_mut(id as usize)
+                .map(|m| (&mut m.x, &mut m.y)),
+            Picked::Edge(_) => None,
+        };
+        if let Some((x, y)) = target {
+            (*x, *y) = (pos.x, pos.y);
+            self.changed = true;
+        }
+    }
+
+    /// Adds an edge between two vertexes, unless it is a loop or they already have one.
+    pub fn connect(&mut self, a: u32, b: u32) -> bool {
+        let (a, b) = (a as i32, b as i32);
+        let exists = self
+            .level
+            .vertex_connections
+            .iter()
+            .any(|edge| (edge.a, edge.b) == (a, b) || (edge.a, edge.b) == (b, a));
+        if a == b || exists {
+            return false;
+        }
+        self.checkpoint();
+        self.changed = true;
+        self.level
+            .vertex_connections
+            .push(level::MapInitVertexConnections {
+                a,
+                b,
+                metadata: Default::default(),
+            });
+        true
+    }
+
+    /// Links a mana point to a vertex, or removes the link they have.
+    pub fn toggle_link(&mut self, mana: u32, vertex: u32) {
+        self.checkpoint();
+        self.changed = true;
+        let link = MapInitManaConnections {
+            a: mana as i32,
+            b: vertex as i32,
+        };
+        let links = &mut self.level.mana_connections;
+        match links.iter().position(|l| (l.a, l.b) == (link.a, link.b)) {
+            Some(i) => {
+                links.remove(i);
+            }
+            None => links.push(link),
+        }
+    }
+
+    pub fn toggle_start(&mut self, vertex: u32) {
+        self.checkpoint();
+        self.changed = true;
+        let starts = &mut self.level.map_start_vertexes;
+        match starts.iter().position(|start| *start == vertex) {
+            Some(i) => {
+                starts.remove(i);
+            }
+            None => starts.push(vertex),
+        }
+    }
+
+    /// Removes an object and everything attached to it, the ids after it move down by one.
+    pub fn delete(&mut self, picked: Picked) {
+        let exists = match picked {
+            Picked::Vertex(id) => (id as usize) < self.level.vertex_positions.len(),
+            Picked::Edge(id) => (id as usize) < self.level.vertex_connections.len(),
+            Picked::Mana(id) => (id as usize) < self.level.mana_points.len(),
+        };
+        if !exists {
+            return;
+        }
+        self.checkpoint();
+        self.changed = true;
+        self.pending = None;
+        let level = &mut self.level;
+        match picked {
+            Picked::Vertex(id) => {
+                let id = id as i32;
+                let shift = |i: &mut i32| {
+                    if *i > id {
+                        *i -= 1;
+                    }
+                };
+                level.vertex_positions.remove(id as usize);
+                level
+                    .vertex_connections
+                    .retain(|edge| edge.a != id && edge.b != id);
+                for edge in level.vertex_connections.iter_mut() {
+                    shift(&mut edge.a);
+                    shift(&mut edge.b);
+                }
+                level.mana_connections.retain(|link| link.b != id);
+                for link in level.mana_connections.iter_mut() {
+                    shift(&mut link.b);
+                }
+                level.map_start_vertexes.retain(|start| *start != id as u32);
+                for start in level.map_start_vertexes.iter_mut() {
+                    if *start > id as u32 {
+                        *start -= 1;
+                    }
+                }
+            }
+            Picked::Edge(id) => {
+                level.vertex_connections.remove(id as usize);
+            }
+            Picked::Mana(id) => {
+                let id = id as i32;
+                level.mana_points.remove(id as usize);
+                level.mana_connections.retain(|link| link.a != id);
+                for link in level.mana_connections.iter_mut() {
+                    if link.a > id {
+                        link.a -= 1;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use bevy::{app::AppExit, prelude::*};
+use bevy_interact_2d::{Group, InteractionState};
+
+use super::*;
+use crate::{client::ClientConfig, lobby::LobbyState};
+
+/// A vertex or mana point following the cursor while the mouse button is held.
+pub struct Drag {
+    picked: Picked,
+    before: MapInitData,
+    moved: bool,
+}
+
+type PickableObjects<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Option<&'static map::Vertex>,
+        Option<&'static map::Edge>,
+        Option<&'static map::Material>,
+        &'static RoomId,
+    ),
+>;
+
+pub fn load_level(
+    mut commands: Commands,
+    config: Res<ClientConfig>,
+    mut lobby: ResMut<LobbyState>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let Some(path) = &config.edit else {
+        return;
+    };
+    let mut editor = match LevelEditor::open(path) {
+        Ok(editor) => editor,
+        Err(e) => {
+            error!("{}", e);
+            exit.send(AppExit);
+            return;
+        }
+    };
+    info!("Editing {:?}", path);
+
+    let problems = level::validate(&editor.level);
+    if let Some(first) = problems.first() {
+        editor.status = format!("{} problems, the first is {}", problems.len(), first);
+    }
+    lobby.current_room = Some(EDITOR_ROOM);
+    commands.insert_resource(editor);
+}
+
+pub fn editor_keys(keys: Res<Input<KeyCode>>, editor: Option<ResMut<LevelEditor>>) {
+    let Some(mut editor) = editor else {
+        return;
+    };
+    let tools = [
+        KeyCode::Key1,
+        KeyCode::Key2,
+        KeyCode::Key3,
+        KeyCode::Key4,
+        KeyCode::Key5,
+        KeyCode::Key6,
+        KeyCode::Key7,
+    ];
+    for (key, tool) in tools.into_iter().zip(EditorTool::ALL) {
+        if keys.just_pressed(key) {
+            editor.apply(EditorAction::Tool(tool));
+        }
+    }
+    if keys.just_pressed(KeyCode::C) {
+        editor.apply(EditorAction::NextColor);
+    }
+    if keys.just_pressed(KeyCode::Escape) {
+        editor.pending = None;
+    }
+
+    if !keys.any_pressed([KeyCode::LControl, KeyCode::RControl]) {
+        return;
+    }
+    let shift = keys.any_pressed([KeyCode::LShift, KeyCode::RShift]);
+    if keys.just_pressed(KeyCode::Z) && shift || keys.just_pressed(KeyCode::Y) {
+        editor.apply(EditorAction::Redo);
+    } else if keys.just_pressed(KeyCode::Z) {
+        editor.apply(EditorAction::Undo);
+    }
+    if keys.just_pressed(KeyCode::S) {
+        editor.apply(EditorAction::Save);
+    }
+}
+
+/// Applies the current tool to what is clicked, or to the empty spot under the cursor.
+pub fn edit_map(
+    editor: Option<ResMut<LevelEditor>>,
+    interaction_state: Res<InteractionState>,
+    mouse: Res<Input<MouseButton>>,
+    buttons: Query<&Interaction, With<Button>>,
+    objects: PickableObjects,
+    mut drag: Local<Option<Drag>>,
+) {
+    let Some(mut editor) = editor else {
+        return;
+    };
+    let Some(cursor) = interaction_state
+        .cursor_positions
+        .get(&Group(map::MAPCLICKABLE))
+        .copied()
+    else {
+        return;
+    };
+
+    if let Some(dragged) = drag.as_mut() {
+        if mouse.pressed(MouseButton::Left) {
+            editor.move_to(dragged.picked, cursor);
+            dragged.moved = true;
+            return;
+        }
+        let dragged = drag.take().unwrap();
+        if dragged.moved {
+            editor.push_undo(dragged.before);
+        }
+        return;
+    }
+
+    // Clicks on the editor panel are not meant for the map
+    if buttons.iter().any(|button| *button != Interaction::None) {
+        return;
+    }
+    let hovered = interaction_state
+        .get_group(Group(map::MAPCLICKABLE))
+        .iter()
+        .find_map(|(entity, _)| {
+            let (vertex, edge, material, room) = objects.get(*entity).ok()?;
+            if *room != EDITOR_ROOM {
+                return None;
+            }
+            vertex
+                .map(|v| Picked::Vertex(v.id))
+                .or_else(|| edge.map(|e| Picked::Edge(e.id)))
+                .or_else(|| material.map(|m| Picked::Mana(m.0)))
+        });
+
+    if editor.tool == EditorTool::Move && mouse.just_pressed(MouseButton::Left) {
+        if let Some(picked @ (Picked::Vertex(_) | Picked::Mana(_))) = hovered {
+            *drag = Some(Drag {
+                picked,
+                before: editor.level.clone(),
+                moved: false,
+            });
+        }
+        return;
+    }
+    if !mouse.just_released(MouseButton::Left) {
+        return;
+    }
+
+    match (editor.tool, hovered, editor.pending) {
+        (EditorTool::Vertex, None, _) => {
+            editor.add_vertex(cursor);
+        }
+        (EditorTool::Mana, None, _) => {
+            editor.add_mana(cursor);
+        }
+        (EditorTool::Edge, Some(Picked::Vertex(b)), Some(Picked::Vertex(a))) => {
+            editor.connect(a, b);
+            editor.pending = None;
+        }
+        (EditorTool::Link, Some(Picked::Vertex(vertex)), Some(Picked::Mana(mana))) => {
+            editor.toggle_link(mana, vertex);
+            editor.pending = None;
+        }
+        (EditorTool::Edge, Some(picked @ Picked::Vertex(_)), _)
+        | (EditorTool::Link, Some(picked @ Picked::Mana(_)), _) => editor.pending = Some(picked),
+        (EditorTool::Start, Some(Picked::Vertex(vertex)), _) => editor.toggle_start(vertex),
+        (EditorTool::Delete, Some(picked), _) => editor.delete(picked),
+        _ => {}
+    }
+}
+
+/// Spawns the map again whenever the level changed, the same way a server sends it.
+pub fn spawn_edited_level(
+    editor: Option<ResMut<LevelEditor>>,
+    mut despawn_room: EventWriter<map::DespawnRoomEvent>,
+    mut map_spawn: EventWriter<map::MapObjectSpawnEvent>,
+) {
+    let Some(mut editor) = editor else {
+        return;
+    };
+    if !editor.take_changed() {
+        return;
+    }
+    despawn_room.send(map::DespawnRoomEvent(EDITOR_ROOM));
+    map::server_map::spawn_level(EDITOR_ROOM, &editor.level, &mut map_spawn);
+}
@@ -0,0 +1,14 @@
+pub mod bots;
+pub mod camera;
+pub mod chat;
+pub mod client;
+pub mod discovery;
+pub mod editor;
+pub mod lobby;
+pub mod map;
+pub mod players;
+pub mod protocol;
+pub mod replay;
+pub mod save;
+pub mod server;
+pub mod ui;
@@ -0,0 +1,338 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use bevy_quinnet::shared::ClientId;
+
+use crate::{
+    map,
+    protocol::{self, JoinRejection, PlayerColor, RoomId, RoomInfo, RoomPhase, SeatInfo},
+};
+
+pub(crate) mod server_lobby;
+
+/// The room that always exists, created from the server config at startup.
+pub const DEFAULT_ROOM: RoomId = RoomId(0);
+
+pub struct ServerLobbyPlugin;
+
+impl Plugin for ServerLobbyPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Rooms::default())
+            .add_event::<LobbyRequestEvent>()
+            .add_event::<RoomStartedEvent>()
+            .add_event::<RoomCommandEvent>()
+            .add_system(server_lobby::create_default_room)
+            .add_system(server_lobby::handle_lobby_requests)
+            .add_system(server_lobby::finish_loading_rooms)
+            // Has to see the old map go and the new one come in the same update
+            .add_system(
+                server_lobby::reload_changed_levels
+                    .after(server_lobby::finish_loading_rooms)
+                    .before(map::despawn_room),
+            )
+            .add_system(
+                server_lobby::handle_room_commands
+                    .after(server_lobby::finish_loading_rooms)
+                    .before(map::despawn_room),
+            )
+            .add_system(server_lobby::broadcast_room_list);
+    }
+}
+
+pub struct ClientLobbyPlugin;
+
+impl Plugin for ClientLobbyPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(LobbyState::default());
+    }
+}
+
+/// What a client asked the lobby for, forwarded from `handle_client_messages`.
+pub struct LobbyRequestEvent {
+    pub client_id: ClientId,
+    pub request: LobbyRequest,
+}
+
+pub enum LobbyRequest {
+    ListRooms,
+    CreateRoom {
+        name: String,
+    },
+    JoinRoom {
+        room: RoomId,
+    },
+    SpectateRoom {
+        room: RoomId,
+    },
+    LeaveRoom,
+    ChooseColor {
+        color: PlayerColor,
+    },
+    SetReady {
+        ready: bool,
+    },
+    SetLevel {
+        level: String,
+    },
+    StartMatch,
+    /// Takes back a seat restored from a save, if one is kept under this name.
+    Resume {
+        username: String,
+    },
+}
+
+/// Sent once a room's map exists and its players have been spawned.
+pub struct RoomStartedEvent {
+    pub room: RoomId,
+}
+
+/// An operator's change to a room, from the server console or an admin's chat.
+pub struct RoomCommandEvent {
+    /// Who gets told how it went, `None` for the console.
+    pub issuer: Option<ClientId>,
+    pub room: RoomId,
+    pub command: RoomCommand,
+}
+
+pub enum RoomCommand {
+    /// Plays another level, restarting the match if it is running.
+    SetLevel(String),
+    /// Starts the running match over on a fresh map.
+    Restart,
+}
+
+pub struct Seat {
+    pub client_id: ClientId,
+    pub color: PlayerColor,
+    pub ready: bool,
+}
+
+/// A seat restored from a save, kept until its player connects again under the same name.
+pub struct VacantSeat {
+    pub username: String,
+    /// Owns the seat's player in the meantime.
+    pub placeholder: ClientId,
+    pub color: PlayerColor,
+}
+
+pub struct Room {
+    pub id: RoomId,
+    pub name: String,
+    pub level: String,
+    /// In join order, the first seat is the host.
+    pub seats: Vec<Seat>,
+    /// Receive the match like players, but own nothing in it.
+    pub spectators: Vec<ClientId>,
+    pub vacant: Vec<VacantSeat>,
+    /// Start vertexes of the level.
+    pub max_players: usize,
+    /// Start vertexes kept free for bots.
+    pub reserved: usize,
+    pub phase: RoomPhase,
+    /// Persistent rooms stay open when the last member leaves.
+    pub persistent: bool,
+    /// Drop-in rooms start right away and never go back to waiting.
+    pub drop_in: bool,
+}
+
+impl Room {
+    pub fn info(&self) -> RoomInfo {
+        RoomInfo {
+            id: self.id,
+            name: self.name.clone(),
+            level: self.level.clone(),
+            seed: map::generator::seed_of(&self.level),
+            players: self.seats.len() + self.vacant.len(),
+            max_players: self.capacity(),
+            phase: self.phase,
+            host: self.host(),
+            seats: self
+                .seats
+                .iter()
+                .map(|seat| SeatInfo {
+                    client_id: seat.client_id,
+                    color: seat.color,
+                    ready: seat.ready,
+                })
+                .collect(),
+            spectators: self.spectators.clone(),
+        }
+    }
+
+    /// How many clients can take a seat.
+    pub fn capacity(&self) -> usize {
+        self.max_players.saturating_sub(self.reserved)
+    }
+
+    pub fn check_join(&self) -> Result<(), JoinRejection> {
+        if self.seats.len() + self.vacant.len() >= self.capacity() {
+            return Err(JoinRejection::RoomFull {
+                max_players: self.capacity(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn host(&self) -> Option<ClientId> {
+        self.seats.first().map(|seat| seat.client_id)
+    }
+
+    pub fn seat(&self, client_id: ClientId) -> Option<&Seat> {
+        self.seats.iter().find(|seat| seat.client_id == client_id)
+    }
+
+    pub fn seat_mut(&mut self, client_id: ClientId) -> Option<&mut Seat> {
+        self.seats
+            .iter_mut()
+            .find(|seat| seat.client_id == client_id)
+    }
+
+    pub fn is_member(&self, client_id: ClientId) -> bool {
+        self.seat(client_id).is_some() || self.spectators.contains(&client_id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seats.is_empty() && self.spectators.is_empty() && self.vacant.is_empty()
+    }
+
+    pub fn color_taken(&self, color: PlayerColor) -> bool {
+        self.seats.iter().any(|seat| seat.color == color)
+            || self.vacant.iter().any(|seat| seat.color == color)
+    }
+
+    /// The first color nobody in the room uses, or the first color once all are taken.
+    pub fn free_color(&self) -> PlayerColor {
+        PlayerColor::ALL
+            .into_iter()
+            .find(|color| !self.color_taken(*color))
+            .unwrap_or(PlayerColor::ALL[0])
+    }
+
+    pub fn all_ready(&self) -> bool {
+        self.seats.iter().all(|seat| seat.ready)
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Rooms {
+    pub rooms: BTreeMap<RoomId, Room>,
+    next_id: u32,
+    /// Set whenever the room list clients see has changed.
+    pub(crate) dirty: bool,
+}
+
+impl Rooms {
+    pub fn create(
+        &mut self,
+        name: String,
+        level: String,
+        max_players: usize,
+        persistent: bool,
+        drop_in: bool,
+    ) -> RoomId {
+        let id = RoomId(self.next_id);
+        self.next_id += 1;
+        self.rooms.insert(
+            id,
+            Room {
+                id,
+                name,
+                level,
+                seats: Vec::new(),
+                spectators: Vec::new(),
+                vacant: Vec::new(),
+                max_players,
+                reserved: 0,
+                phase: RoomPhase::Waiting,
+                persistent,
+                drop_in,
+            },
+        );
+        self.dirty = true;
+        id
+    }
+
+    /// The id the next room gets, saved so restored rooms keep theirs.
+    pub fn next_id(&self) -> u32 {
+        self.next_id
+    }
+
+    /// Replaces every room with ones restored from a save.
+    pub fn restore(&mut self, rooms: Vec<Room>, next_id: u32) {
+        self.rooms = rooms.into_iter().map(|room| (room.id, room)).collect();
+        self.next_id = next_id;
+        self.dirty = true;
+    }
+
+    /// The room keeping a vacant seat for this name.
+    pub fn vacant_seat_of(&self, username: &str) -> Option<RoomId> {
+        self.rooms
+            .values()
+            .find(|room| room.vacant.iter().any(|seat| seat.username == username))
+            .map(|room| room.id)
+    }
+
+    pub fn room_of(&self, client_id: ClientId) -> Option<RoomId> {
+        self.rooms
+            .values()
+            .find(|room| room.is_member(client_id))
+            .map(|room| room.id)
+    }
+
+    /// The room where the client has a player, unlike `room_of` this excludes spectators.
+    pub fn seat_of(&self, client_id: ClientId) -> Option<RoomId> {
+        self.rooms
+            .values()
+            .find(|room| room.seat(client_id).is_some())
+            .map(|room| room.id)
+    }
+
+    /// Everyone who receives the room's updates, players and spectators.
+    pub fn members(&self, room: RoomId) -> Option<impl Iterator<Item = &ClientId>> {
+        self.rooms.get(&room).map(|room| {
+            room.seats
+                .iter()
+                .map(|seat| &seat.client_id)
+                .chain(room.spectators.iter())
+        })
+    }
+
+    /// Who hears what a client says: its room, or everyone outside of rooms.
+    pub fn audience_of(&self, client_id: ClientId, users: &protocol::Users) -> Vec<ClientId> {
+        match self.room_of(client_id) {
+            Some(room) => self.members(room).unwrap().copied().collect(),
+            None => users
+                .names
+                .keys()
+                .copied()
+                .filter(|user| self.room_of(*user).is_none())
+                .collect(),
+        }
+    }
+
+    pub fn infos(&self) -> Vec<RoomInfo> {
+        self.rooms.values().map(|room| room.info()).collect()
+    }
+}
+
+/// The client's view of the lobby.
+#[derive(Resource, Default)]
+pub struct LobbyState {
+    pub rooms: Vec<RoomInfo>,
+    pub levels: Vec<String>,
+    pub current_room: Option<RoomId>,
+    /// Why the last room we tried to enter turned us away.
+    pub rejection: Option<JoinRejection>,
+}
+
+impl LobbyState {
+    pub fn current_room_info(&self) -> Option<&RoomInfo> {
+        let current = self.current_room?;
+        self.rooms.iter().find(|room| room.id == current)
+    }
+
+    pub fn is_spectating(&self, self_id: ClientId) -> bool {
+        self.current_room_info()
+            .is_some_and(|room| room.spectators.contains(&self_id))
+    }
+}
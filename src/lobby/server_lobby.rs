@@ -0,0 +1,630 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_quinnet::{
+    server::{Endpoint, Server},
+    shared::ClientId,
+};
+
+use super::*;
+use crate::{
+    map::{self, LevelData},
+    players,
+    protocol::{JoinRejection, PlayerColor, RoomPhase, ServerMessage},
+    save::PendingRestore,
+    server::{AdminReplyEvent, ServerConfig},
+};
+
+/// Creates the main room once its level has loaded, unless a save brought the rooms back first.
+pub fn create_default_room(
+    mut rooms: ResMut<Rooms>,
+    config: Res<ServerConfig>,
+    levels: LevelData,
+    restore: Res<PendingRestore>,
+    mut map_spawn: EventWriter<map::MapObjectSpawnEvent>,
+    mut created: Local<bool>,
+) {
+    if *created {
+        return;
+    }
+    if !rooms.rooms.is_empty() || restore.is_restoring() {
+        *created = true;
+        return;
+    }
+    let max_players = match levels.max_players(&config.level) {
+        Ok(max_players) => max_players,
+        Err(e) if levels.failed(&config.level) => panic!("Could not load the default level: {}", e),
+        Err(_) => return,
+    };
+    *created = true;
+
+    let room = rooms.create(
+        "Main".to_string(),
+        config.level.clone(),
+        max_players,
+        true,
+        config.main_room_drop_in,
+    );
+    // Bots take their start vertexes before anyone can join
+    let main = rooms.rooms.get_mut(&room).unwrap();
+    main.reserved = config.bots.count.min(main.max_players);
+
+    if config.main_room_drop_in {
+        if let Err(e) = start_match(&mut rooms, room, &levels, &mut map_spawn) {
+            panic!("Could not load the default level: {}", e);
+        }
+    }
+}
+
+/// Everything that happens to the world when clients enter or leave rooms.
+#[derive(SystemParam)]
+pub struct RoomWriters<'w> {
+    map_spawn: EventWriter<'w, map::MapObjectSpawnEvent>,
+    init_map: EventWriter<'w, map::server_map::InitMapSend>,
+    player_spawn: EventWriter<'w, players::PlayerSpawnEvent>,
+    player_despawn: EventWriter<'w, players::PlayerDespawnEvent>,
+    player_owner: EventWriter<'w, players::PlayerOwnerChangeEvent>,
+    despawn_room: EventWriter<'w, map::DespawnRoomEvent>,
+}
+
+pub fn handle_lobby_requests(
+    mut requests: EventReader<LobbyRequestEvent>,
+    mut rooms: ResMut<Rooms>,
+    mut server: ResMut<Server>,
+    config: Res<ServerConfig>,
+    levels: LevelData,
+    mut writers: RoomWriters,
+) {
+    let endpoint = server.endpoint_mut();
+    for LobbyRequestEvent { client_id, request } in requests.iter() {
+        let client_id = *client_id;
+        match request {
+            LobbyRequest::ListRooms => {
+                endpoint.try_send_message(client_id, room_list(&rooms, &levels));
+            }
+            LobbyRequest::CreateRoom { name } => {
+                let max_players = match levels.max_players(&config.level) {
+                    Ok(max_players) => max_players,
+                    Err(e) => {
+                        warn!("Failed to create room {}: {}", name, e);
+                        continue;
+                    }
+                };
+                let room = rooms.create(
+                    name.clone(),
+                    config.level.clone(),
+                    max_players,
+                    false,
+                    false,
+                );
+                // A level without start vertexes can't hold anyone
+                if let Err(reason) = rooms.rooms[&room].check_join() {
+                    rooms.rooms.remove(&room);
+                    reject(endpoint, client_id, room, reason);
+                    continue;
+                }
+                info!("Room {} created", name);
+                leave_room(&mut rooms, client_id, endpoint, &mut writers);
+                if let Err(reason) = join_room(&mut rooms, room, client_id, endpoint, &mut writers)
+                {
+                    reject(endpoint, client_id, room, reason);
+                }
+            }
+            LobbyRequest::JoinRoom { room } => {
+                let Some(joined) = rooms.rooms.get(room) else {
+                    reject(endpoint, client_id, *room, JoinRejection::UnknownRoom);
+                    continue;
+                };
+                if rooms.seat_of(client_id) == Some(*room) {
+                    continue;
+                }
+                if let Err(reason) = joined.check_join() {
+                    reject(endpoint, client_id, *room, reason);
+                    continue;
+                }
+                if joined.spectators.contains(&client_id) {
+                    // Straight to a seat, leaving first could close the room
+                    let watched = rooms.rooms.get_mut(room).unwrap();
+                    watched
+                        .spectators
+                        .retain(|spectator| *spectator != client_id);
+                } else {
+                    leave_room(&mut rooms, client_id, endpoint, &mut writers);
+                }
+                if let Err(reason) = join_room(&mut rooms, *room, client_id, endpoint, &mut writers)
+                {
+                    reject(endpoint, client_id, *room, reason);
+                }
+            }
+            LobbyRequest::SpectateRoom { room } => {
+                if !rooms.rooms.contains_key(room) {
+                    reject(endpoint, client_id, *room, JoinRejection::UnknownRoom);
+                    continue;
+                }
+                if rooms.rooms[room].spectators.contains(&client_id) {
+                    continue;
+                }
+                leave_room(&mut rooms, client_id, endpoint, &mut writers);
+                spectate_room(&mut rooms, *room, client_id, endpoint, &mut writers);
+            }
+            LobbyRequest::LeaveRoom => {
+                leave_room(&mut rooms, client_id, endpoint, &mut writers);
+            }
+            LobbyRequest::ChooseColor { color } => {
+                let Some(room) = waiting_room_of(&mut rooms, client_id) else {
+                    continue;
+                };
+                if room.color_taken(*color) {
+                    continue;
+                }
+                room.seat_mut(client_id).unwrap().color = *color;
+                rooms.dirty = true;
+            }
+            LobbyRequest::SetReady { ready } => {
+                let Some(room) = waiting_room_of(&mut rooms, client_id) else {
+                    continue;
+                };
+                room.seat_mut(client_id).unwrap().ready = *ready;
+                rooms.dirty = true;
+            }
+            LobbyRequest::SetLevel { level } => {
+                let Some(room) = waiting_room_of(&mut rooms, client_id) else {
+                    continue;
+                };
+                if room.host() != Some(client_id) {
+                    warn!("Client {} is not the host of {}", client_id, room.name);
+                    continue;
+                }
+                if !levels.contains(level) {
+                    warn!("Client {} picked unknown level {}", client_id, level);
+                    continue;
+                }
+                let max_players = match levels.max_players(level) {
+                    Ok(max_players) if max_players >= room.seats.len() => max_players,
+                    Ok(max_players) => {
+                        warn!(
+                            "Level {} only fits {} of the {} players in {}",
+                            level,
+                            max_players,
+                            room.seats.len(),
+                            room.name
+                        );
+                        continue;
+                    }
+                    Err(e) => {
+                        warn!("Client {} picked a broken level: {}", client_id, e);
+                        continue;
+                    }
+                };
+                room.level = level.clone();
+                room.max_players = max_players;
+                rooms.dirty = true;
+            }
+            LobbyRequest::StartMatch => {
+                let Some(room) = waiting_room_of(&mut rooms, client_id) else {
+                    continue;
+                };
+                if room.host() != Some(client_id) || !room.all_ready() {
+                    warn!("Client {} can't start {} yet", client_id, room.name);
+                    continue;
+                }
+                let room = room.id;
+                if let Err(e) = start_match(&mut rooms, room, &levels, &mut writers.map_spawn) {
+                    warn!("Failed to start room {:?}: {}", room, e);
+                }
+            }
+            LobbyRequest::Resume { username } => {
+                let Some(room) = rooms.vacant_seat_of(username) else {
+                    continue;
+                };
+                leave_room(&mut rooms, client_id, endpoint, &mut writers);
+                resume_seat(
+                    &mut rooms,
+                    room,
+                    client_id,
+                    username,
+                    endpoint,
+                    &mut writers,
+                );
+            }
+        }
+    }
+}
+
+fn reject(endpoint: &mut Endpoint, client_id: ClientId, room: RoomId, reason: JoinRejection) {
+    info!("Client {} can't join {:?}: {}", client_id, room, reason);
+    endpoint.try_send_message(client_id, ServerMessage::JoinRejected { room, reason });
+}
+
+fn room_list(rooms: &Rooms, levels: &LevelData) -> ServerMessage {
+    ServerMessage::RoomList {
+        rooms: rooms.infos(),
+        levels: levels.names(),
+    }
+}
+
+/// The client's room, as long as it is still gathering players.
+fn waiting_room_of(rooms: &mut Rooms, client_id: ClientId) -> Option<&mut Room> {
+    let room = rooms.seat_of(client_id)?;
+    rooms
+        .rooms
+        .get_mut(&room)
+        .filter(|room| room.phase == RoomPhase::Waiting)
+}
+
+/// Spawns the room's level, players are placed once it has loaded.
+fn start_match(
+    rooms: &mut Rooms,
+    room: RoomId,
+    levels: &LevelData,
+    map_spawn: &mut EventWriter<map::MapObjectSpawnEvent>,
+) -> Result<(), String> {
+    let started = rooms.rooms.get_mut(&room).unwrap();
+    map::server_map::spawn_level(room, levels.get(&started.level)?, map_spawn);
+    info!("Room {} is starting {}", started.name, started.level);
+    started.phase = RoomPhase::Loading;
+    rooms.dirty = true;
+    Ok(())
+}
+
+fn join_room(
+    rooms: &mut Rooms,
+    room: RoomId,
+    client_id: ClientId,
+    endpoint: &mut Endpoint,
+    writers: &mut RoomWriters,
+) -> Result<(), JoinRejection> {
+    let joined = rooms
+        .rooms
+        .get_mut(&room)
+        .ok_or(JoinRejection::UnknownRoom)?;
+    joined.check_join()?;
+    let color = joined.free_color();
+    joined.seats.push(Seat {
+        client_id,
+        color,
+        ready: false,
+    });
+    rooms.dirty = true;
+
+    let joined = &rooms.rooms[&room];
+    endpoint.try_send_message(
+        client_id,
+        ServerMessage::RoomJoined {
+            room: joined.info(),
+        },
+    );
+    // Players of a match that is about to start are placed together once it has loaded
+    if joined.phase == RoomPhase::Playing {
+        writers
+            .init_map
+            .send(map::server_map::InitMapSend { client_id, room });
+        writers
+            .player_spawn
+            .send(new_player(room, client_id, color, None));
+    }
+    Ok(())
+}
+
+/// Seats the client in its vacant seat and gives it the player kept there.
+fn resume_seat(
+    rooms: &mut Rooms,
+    room: RoomId,
+    client_id: ClientId,
+    username: &str,
+    endpoint: &mut Endpoint,
+    writers: &mut RoomWriters,
+) {
+    let resumed = rooms.rooms.get_mut(&room).unwrap();
+    let Some(index) = resumed
+        .vacant
+        .iter()
+        .position(|seat| seat.username == username)
+    else {
+        return;
+    };
+    let vacant = resumed.vacant.remove(index);
+    resumed.seats.push(Seat {
+        client_id,
+        color: vacant.color,
+        ready: true,
+    });
+    rooms.dirty = true;
+    info!("{} is back in {}", username, resumed.name);
+
+    endpoint.try_send_message(
+        client_id,
+        ServerMessage::RoomJoined {
+            room: resumed.info(),
+        },
+    );
+    writers
+        .init_map
+        .send(map::server_map::InitMapSend { client_id, room });
+    writers.player_owner.send(players::PlayerOwnerChangeEvent {
+        from: vacant.placeholder,
+        to: client_id,
+    });
+}
+
+fn spectate_room(
+    rooms: &mut Rooms,
+    room: RoomId,
+    client_id: ClientId,
+    endpoint: &mut Endpoint,
+    writers: &mut RoomWriters,
+) {
+    let watched = rooms.rooms.get_mut(&room).unwrap();
+    watched.spectators.push(client_id);
+    rooms.dirty = true;
+
+    endpoint.try_send_message(
+        client_id,
+        ServerMessage::RoomJoined {
+            room: watched.info(),
+        },
+    );
+    if watched.phase == RoomPhase::Playing {
+        writers
+            .init_map
+            .send(map::server_map::InitMapSend { client_id, room });
+    }
+}
+
+/// Takes the client out of its room, if any, and tells it so before it enters another one.
+fn leave_room(
+    rooms: &mut Rooms,
+    client_id: ClientId,
+    endpoint: &mut Endpoint,
+    writers: &mut RoomWriters,
+) {
+    let Some(room_id) = rooms.room_of(client_id) else {
+        return;
+    };
+    // The client may already be gone when this comes from a disconnect
+    endpoint.try_send_message(client_id, ServerMessage::RoomLeft {});
+    let room = rooms.rooms.get_mut(&room_id).unwrap();
+    room.seats.retain(|seat| seat.client_id != client_id);
+    room.spectators.retain(|spectator| *spectator != client_id);
+    rooms.dirty = true;
+    writers.player_despawn.send(players::PlayerDespawnEvent {
+        client_owner_id: client_id,
+    });
+
+    // Vacant seats keep the match going for the players coming back
+    if !room.seats.is_empty() || !room.vacant.is_empty() {
+        return;
+    }
+    if !room.persistent && room.is_empty() {
+        info!("Room {} closed", room.name);
+        rooms.rooms.remove(&room_id);
+        writers.despawn_room.send(map::DespawnRoomEvent(room_id));
+    } else if !room.drop_in && room.phase != RoomPhase::Waiting {
+        info!("Room {} is waiting for players again", room.name);
+        room.phase = RoomPhase::Waiting;
+        writers.despawn_room.send(map::DespawnRoomEvent(room_id));
+        // Spectators stay, without the match they were watching
+        for spectator in room.spectators.iter() {
+            endpoint.try_send_message(*spectator, ServerMessage::RoomLeft {});
+            endpoint.try_send_message(*spectator, ServerMessage::RoomJoined { room: room.info() });
+        }
+    }
+}
+
+fn new_player(
+    room: RoomId,
+    client_id: ClientId,
+    color: PlayerColor,
+    start: Option<Entity>,
+) -> players::PlayerSpawnEvent {
+    players::PlayerSpawnEvent {
+        room,
+        current_vertex: start,
+        x: None,
+        y: None,
+        id: None,
+        client_owner_id: client_id,
+        color,
+    }
+}
+
+/// Restarts the matches of a level whose file changed. Finishing to load them sends the new
+/// map to their members and places everyone on it again.
+pub fn reload_changed_levels(
+    mut asset_events: EventReader<AssetEvent<map::MapInitData>>,
+    mut rooms: ResMut<Rooms>,
+    levels: LevelData,
+    mut despawn_room: EventWriter<map::DespawnRoomEvent>,
+    mut map_spawn: EventWriter<map::MapObjectSpawnEvent>,
+) {
+    for event in asset_events.iter() {
+        let AssetEvent::Modified { handle } = event else {
+            continue;
+        };
+        let Some(level) = levels.name_of(handle) else {
+            continue;
+        };
+        let max_players = match levels.max_players(level) {
+            Ok(max_players) => max_players,
+            Err(e) => {
+                warn!("Could not reload {}: {}", level, e);
+                continue;
+            }
+        };
+        info!("Reloading {}", level);
+
+        let reloaded: Vec<RoomId> = rooms
+            .rooms
+            .values()
+            .filter(|room| room.level == level)
+            .map(|room| room.id)
+            .collect();
+        for room_id in reloaded {
+            let room = rooms.rooms.get_mut(&room_id).unwrap();
+            room.max_players = max_players;
+            if room.phase == RoomPhase::Waiting {
+                continue;
+            }
+            let restarted = restart_match(
+                &mut rooms,
+                room_id,
+                &levels,
+                &mut despawn_room,
+                &mut map_spawn,
+            );
+            if let Err(e) = restarted {
+                warn!("Could not reload {}: {}", level, e);
+            }
+        }
+        rooms.dirty = true;
+    }
+}
+
+/// Throws the room's map and players away and starts its level again.
+fn restart_match(
+    rooms: &mut Rooms,
+    room: RoomId,
+    levels: &LevelData,
+    despawn_room: &mut EventWriter<map::DespawnRoomEvent>,
+    map_spawn: &mut EventWriter<map::MapObjectSpawnEvent>,
+) -> Result<(), String> {
+    // The players of vacant seats go away with the old map
+    rooms.rooms.get_mut(&room).unwrap().vacant.clear();
+    despawn_room.send(map::DespawnRoomEvent(room));
+    start_match(rooms, room, levels, map_spawn)
+}
+
+/// Level changes and restarts from the server's operators.
+pub fn handle_room_commands(
+    mut commands: EventReader<RoomCommandEvent>,
+    mut rooms: ResMut<Rooms>,
+    levels: LevelData,
+    mut despawn_room: EventWriter<map::DespawnRoomEvent>,
+    mut map_spawn: EventWriter<map::MapObjectSpawnEvent>,
+    mut replies: EventWriter<AdminReplyEvent>,
+) {
+    for RoomCommandEvent {
+        issuer,
+        room,
+        command,
+    } in commands.iter()
+    {
+        let reply = |message: String| AdminReplyEvent {
+            issuer: *issuer,
+            message,
+        };
+        let Some(changed) = rooms.rooms.get_mut(room) else {
+            replies.send(reply(format!("There is no room {}", room.0)));
+            continue;
+        };
+        if let RoomCommand::SetLevel(level) = command {
+            let max_players = match levels.max_players(level) {
+                Ok(max_players) if max_players >= changed.seats.len() => max_players,
+                Ok(max_players) => {
+                    replies.send(reply(format!(
+                        "{} only fits {} of the {} players in {}",
+                        level,
+                        max_players,
+                        changed.seats.len(),
+                        changed.name
+                    )));
+                    continue;
+                }
+                Err(e) => {
+                    replies.send(reply(format!("Can't play {}: {}", level, e)));
+                    continue;
+                }
+            };
+            changed.level = level.clone();
+            changed.max_players = max_players;
+            rooms.dirty = true;
+        }
+
+        let changed = &rooms.rooms[room];
+        let (name, level) = (changed.name.clone(), changed.level.clone());
+        if changed.phase == RoomPhase::Waiting {
+            replies.send(reply(match command {
+                RoomCommand::SetLevel(_) => format!("{} will play {}", name, level),
+                RoomCommand::Restart => format!("{} has not started yet", name),
+            }));
+            continue;
+        }
+        let restarted = restart_match(
+            &mut rooms,
+            *room,
+            &levels,
+            &mut despawn_room,
+            &mut map_spawn,
+        );
+        replies.send(reply(match restarted {
+            Ok(()) => format!("{} restarted on {}", name, level),
+            Err(e) => format!("Could not restart {}: {}", name, e),
+        }));
+    }
+}
+
+/// Rooms switch to playing once their vertexes exist, which is a frame after the spawn events.
+/// Seats then get the start vertexes in id order, so the same players on the same level
+/// always start in the same places.
+pub fn finish_loading_rooms(
+    mut rooms: ResMut<Rooms>,
+    start_vertexes: Query<(Entity, &map::Vertex, &RoomId), With<map::VertexStart>>,
+    mut init_map: EventWriter<map::server_map::InitMapSend>,
+    mut player_spawn: EventWriter<players::PlayerSpawnEvent>,
+    mut room_started: EventWriter<RoomStartedEvent>,
+) {
+    let mut started = false;
+    for room in rooms.rooms.values_mut() {
+        if room.phase != RoomPhase::Loading {
+            continue;
+        }
+        let mut starts: Vec<(u32, Entity)> = start_vertexes
+            .iter()
+            .filter(|(_, vertex, r)| **r == room.id && !vertex.filled)
+            .map(|(entity, vertex, _)| (vertex.id, entity))
+            .collect();
+        if starts.is_empty() {
+            continue;
+        }
+        starts.sort();
+        room.phase = RoomPhase::Playing;
+        started = true;
+
+        for (i, seat) in room.seats.iter().enumerate() {
+            init_map.send(map::server_map::InitMapSend {
+                client_id: seat.client_id,
+                room: room.id,
+            });
+            player_spawn.send(new_player(
+                room.id,
+                seat.client_id,
+                seat.color,
+                starts.get(i).map(|(_, entity)| *entity),
+            ));
+        }
+        for spectator in room.spectators.iter() {
+            init_map.send(map::server_map::InitMapSend {
+                client_id: *spectator,
+                room: room.id,
+            });
+        }
+        room_started.send(RoomStartedEvent { room: room.id });
+    }
+    if started {
+        rooms.dirty = true;
+    }
+}
+
+pub fn broadcast_room_list(
+    mut rooms: ResMut<Rooms>,
+    server: Res<Server>,
+    users: Res<protocol::Users>,
+    levels: LevelData,
+) {
+    if !rooms.dirty {
+        return;
+    }
+    rooms.dirty = false;
+
+    server
+        .endpoint()
+        .try_send_group_message(users.names.keys(), room_list(&rooms, &levels));
+}
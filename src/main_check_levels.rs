@@ -0,0 +1,29 @@
+use catan::map::{self, level};
+
+/// Command line: `check_levels`, validates every level in `assets/levels`.
+fn main() {
+    let mut failed = false;
+    for name in map::available_levels() {
+        let problems = match std::fs::read(map::level_path(&name)) {
+            Ok(bytes) => match level::read_level(&bytes, &name) {
+                Ok(level) => level::validate(&level)
+                    .iter()
+                    .map(|problem| problem.to_string())
+                    .collect(),
+                Err(e) => vec![e],
+            },
+            Err(e) => vec![e.to_string()],
+        };
+        if problems.is_empty() {
+            println!("{}: ok", name);
+            continue;
+        }
+        failed = true;
+        for problem in problems {
+            eprintln!("{}: {}", name, problem);
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,345 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use bevy::{app::AppExit, app::ScheduleRunnerSettings, log::LogPlugin, prelude::*};
+use bevy_quinnet::{
+    client::{
+        certificate::CertificateVerificationMode,
+        connection::{ConnectionConfiguration, ConnectionId},
+        Client, QuinnetClientPlugin,
+    },
+    shared::ClientId,
+};
+use rand::seq::SliceRandom;
+
+use catan::{
+    lobby,
+    protocol::{self, ClientMessage, ServerMessage},
+};
+
+/// A MOVE that hasn't shown up in `UpdatePlayers` after this long counts as rejected.
+const MOVE_TIMEOUT: Duration = Duration::from_secs(2);
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Command line: `loadtest [clients] [server address] [seconds]`
+#[derive(Resource, Debug, Clone)]
+struct LoadTestConfig {
+    clients: usize,
+    server_addr: SocketAddr,
+    duration: Duration,
+    move_interval: Duration,
+}
+
+impl LoadTestConfig {
+    fn from_args() -> Self {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        Self {
+            clients: args.first().and_then(|a| a.parse().ok()).unwrap_or(10),
+            server_addr: args
+                .get(1)
+                .and_then(|a| a.parse().ok())
+                .unwrap_or(SocketAddr::new(
+                    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                    6000,
+                )),
+            duration: Duration::from_secs(args.get(2).and_then(|a| a.parse().ok()).unwrap_or(60)),
+            move_interval: Duration::from_millis(250),
+        }
+    }
+}
+
+/// One fake player and what it knows from the server.
+struct SimulatedClient {
+    connection_id: ConnectionId,
+    client_id: Option<ClientId>,
+    neighbours: HashMap<u32, Vec<u32>>,
+    filled: HashMap<u32, bool>,
+    current_vertex: Option<u32>,
+    moving: bool,
+    pending_move: Option<(u32, Instant)>,
+    last_move: Instant,
+}
+
+#[derive(Default, Clone, Copy)]
+struct MessageStats {
+    count: u64,
+    bytes: u64,
+}
+
+#[derive(Resource)]
+struct LoadTestStats {
+    started: Instant,
+    last_report: Instant,
+    received: HashMap<&'static str, MessageStats>,
+    window: HashMap<&'static str, MessageStats>,
+    sent: u64,
+    rejected: u64,
+    latencies: Vec<Duration>,
+}
+
+#[derive(Resource, Default)]
+struct SimulatedClients(Vec<SimulatedClient>);
+
+fn open_connections(
+    mut client: ResMut<Client>,
+    mut clients: ResMut<SimulatedClients>,
+    config: Res<LoadTestConfig>,
+) {
+    for i in 0..config.clients {
+        match client.open_connection(
+            ConnectionConfiguration::from_addrs(
+                config.server_addr,
+                SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0),
+            ),
+            CertificateVerificationMode::SkipVerification,
+        ) {
+            Ok((connection_id, _)) => {
+                let connection = client.get_connection_mut_by_id(connection_id).unwrap();
+                let joined = connection
+                    .send_message(ClientMessage::Join {
+                        name: format!("LoadTest{}", i),
+                        password: None,
+                    })
+                    .and_then(|_| {
+                        connection.send_message(ClientMessage::JoinRoom {
+                            room: lobby::DEFAULT_ROOM,
+                        })
+                    });
+                if joined.is_err() {
+                    println!("client {} failed to join", i);
+                }
+                clients.0.push(SimulatedClient {
+                    connection_id,
+                    client_id: None,
+                    neighbours: HashMap::new(),
+                    filled: HashMap::new(),
+                    current_vertex: None,
+                    moving: false,
+                    pending_move: None,
+                    last_move: Instant::now(),
+                });
+            }
+            Err(e) => println!("failed to open connection {}, Error {:?}", i, e),
+        }
+    }
+    println!(
+        "opened {} connections to {}",
+        clients.0.len(),
+        config.server_addr
+    );
+}
+
+fn receive_messages(
+    mut client: ResMut<Client>,
+    mut clients: ResMut<SimulatedClients>,
+    mut stats: ResMut<LoadTestStats>,
+) {
+    let stats = &mut *stats;
+    let now = Instant::now();
+    for sim in clients.0.iter_mut() {
+        let Some(connection) = client.get_connection_mut_by_id(sim.connection_id) else {
+            continue;
+        };
+
+        while let Some(message) = connection.try_receive_message::<ServerMessage>() {
+            let bytes = bincode::serialized_size(&message).unwrap_or(0);
+            for table in [&mut stats.received, &mut stats.window] {
+                let entry = table.entry(message.kind()).or_default();
+                entry.count += 1;
+                entry.bytes += bytes;
+            }
+
+            match message {
+                ServerMessage::InitClient { client_id, .. } => sim.client_id = Some(client_id),
+                ServerMessage::InitMap { vertexes, .. } => {
+                    for vertex in vertexes {
+                        sim.neighbours
+                            .insert(vertex.id, vertex.adjacentices.vertex_list);
+                    }
+                }
+                ServerMessage::UpdateMap { vertexes, .. } => {
+                    for vertex in vertexes {
+                        sim.filled.insert(vertex.id, vertex.filled);
+                    }
+                }
+                ServerMessage::UpdatePlayers { players } => {
+                    let Some(me) = players
+                        .iter()
+                        .find(|p| Some(p.client_owner_id) == sim.client_id)
+                    else {
+                        continue;
+                    };
+                    sim.current_vertex = Some(me.current_vertex);
+                    sim.moving = me.next_vertex.is_some();
+
+                    if let Some((target, sent_at)) = sim.pending_move {
+                        if me.next_vertex == Some(target) || me.current_vertex == target {
+                            stats.latencies.push(now - sent_at);
+                            sim.pending_move = None;
+                        }
+                    }
+                }
+                ServerMessage::JoinRejected { reason, .. } => {
+                    println!("client {:?} was turned away: {}", sim.client_id, reason)
+                }
+                ServerMessage::ActionRejected { .. } if sim.pending_move.is_some() => {
+                    stats.rejected += 1;
+                    sim.pending_move = None;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn send_moves(
+    client: Res<Client>,
+    mut clients: ResMut<SimulatedClients>,
+    mut stats: ResMut<LoadTestStats>,
+    config: Res<LoadTestConfig>,
+) {
+    let now = Instant::now();
+    let mut rng = rand::thread_rng();
+    for sim in clients.0.iter_mut() {
+        if let Some((_, sent_at)) = sim.pending_move {
+            if now - sent_at < MOVE_TIMEOUT {
+                continue;
+            }
+            stats.rejected += 1;
+            sim.pending_move = None;
+        }
+        if sim.moving || now - sim.last_move < config.move_interval {
+            continue;
+        }
+        let Some(current) = sim.current_vertex else {
+            continue;
+        };
+        let Some(neighbours) = sim.neighbours.get(&current) else {
+            continue;
+        };
+
+        let free: Vec<u32> = neighbours
+            .iter()
+            .copied()
+            .filter(|v| !sim.filled.get(v).copied().unwrap_or(false))
+            .collect();
+        let Some(target) = free.choose(&mut rng).copied() else {
+            continue;
+        };
+
+        if let Some(connection) = client.get_connection_by_id(sim.connection_id) {
+            if connection
+                .send_message(ClientMessage::SendCommand {
+                    request_id: stats.sent as u32,
+                    command: protocol::GameCommand::Move { vertex: target }.into(),
+                })
+                .is_ok()
+            {
+                stats.sent += 1;
+                sim.pending_move = Some((target, now));
+                sim.last_move = now;
+            }
+        }
+    }
+}
+
+fn print_table(title: &str, table: &HashMap<&'static str, MessageStats>, seconds: f32) {
+    println!("{}", title);
+    let mut kinds: Vec<_> = table.iter().collect();
+    kinds.sort_by_key(|(kind, _)| *kind);
+    for (kind, stats) in kinds {
+        println!(
+            "  {:<20} {:>10} msgs {:>10.1} msg/s {:>12.1} KiB/s",
+            kind,
+            stats.count,
+            stats.count as f32 / seconds,
+            stats.bytes as f32 / 1024.0 / seconds
+        );
+    }
+}
+
+fn print_latency(latencies: &[Duration]) {
+    if latencies.is_empty() {
+        println!("  round trip: no confirmed moves yet");
+        return;
+    }
+    let mut sorted = latencies.to_vec();
+    sorted.sort();
+    let total: Duration = sorted.iter().sum();
+    println!(
+        "  round trip: min {:?} avg {:?} p95 {:?} max {:?} ({} moves)",
+        sorted[0],
+        total / sorted.len() as u32,
+        sorted[(sorted.len() * 95 / 100).min(sorted.len() - 1)],
+        sorted[sorted.len() - 1],
+        sorted.len()
+    );
+}
+
+fn report(
+    mut stats: ResMut<LoadTestStats>,
+    clients: Res<SimulatedClients>,
+    config: Res<LoadTestConfig>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let now = Instant::now();
+    let finished = now - stats.started >= config.duration;
+    if now - stats.last_report < REPORT_INTERVAL && !finished {
+        return;
+    }
+
+    let joined = clients.0.iter().filter(|c| c.client_id.is_some()).count();
+    let window_seconds = (now - stats.last_report).as_secs_f32();
+    println!(
+        "--- {:.0}s: {}/{} clients joined, {} moves sent, {} rejected or lost",
+        (now - stats.started).as_secs_f32(),
+        joined,
+        config.clients,
+        stats.sent,
+        stats.rejected
+    );
+    print_table("  last interval:", &stats.window, window_seconds);
+    print_latency(&stats.latencies);
+
+    stats.window.clear();
+    stats.last_report = now;
+
+    if finished {
+        print_table(
+            "=== totals:",
+            &stats.received,
+            (now - stats.started).as_secs_f32(),
+        );
+        exit.send(AppExit);
+    }
+}
+
+fn main() {
+    let config = LoadTestConfig::from_args();
+    App::new()
+        .insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_secs_f32(
+            1. / 60.,
+        )))
+        .add_plugins(MinimalPlugins)
+        .add_plugin(LogPlugin::default())
+        .add_plugin(QuinnetClientPlugin::default())
+        .insert_resource(config)
+        .insert_resource(SimulatedClients::default())
+        .insert_resource(LoadTestStats {
+            started: Instant::now(),
+            last_report: Instant::now(),
+            received: HashMap::new(),
+            window: HashMap::new(),
+            sent: 0,
+            rejected: 0,
+            latencies: Vec::new(),
+        })
+        .add_startup_system(open_connections)
+        .add_system(receive_messages)
+        .add_system(send_moves.after(receive_messages))
+        .add_system(report.after(send_moves))
+        .run();
+}
@@ -0,0 +1,67 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use catan::map::level::{self, LEVEL_VERSION};
+
+/// Command line: `migrate_levels [folder]`, `assets/levels` unless given.
+fn main() {
+    let dir = std::env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("assets/levels"));
+
+    let mut files = Vec::new();
+    find_levels(&dir, &mut files);
+    files.sort();
+
+    let mut failed = false;
+    for path in files {
+        match migrate(&path) {
+            Ok(Some(version)) => println!(
+                "{:?}: upgraded from version {} to {}",
+                path, version, LEVEL_VERSION
+            ),
+            Ok(None) => println!("{:?}: up to date", path),
+            Err(e) => {
+                eprintln!("{:?}: {}", path, e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+fn find_levels(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        eprintln!("Could not read {:?}", dir);
+        return;
+    };
+    for path in entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+    {
+        if path.is_dir() {
+            find_levels(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "json") {
+            files.push(path);
+        }
+    }
+}
+
+/// Rewrites the level in the current format, returns the version it had if it was older.
+fn migrate(path: &Path) -> Result<Option<u32>, String> {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    let value = serde_json::from_slice(&bytes).map_err(|e| e.to_string())?;
+    let version = level::file_version(&value);
+    if version == LEVEL_VERSION {
+        return Ok(None);
+    }
+    let name = path.file_stem().unwrap_or_default().to_string_lossy();
+    let level = level::read_level(&bytes, &name)?;
+    level::write_level(path, &level)?;
+    Ok(Some(version))
+}
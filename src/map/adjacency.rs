@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+use bevy::prelude::Entity;
+
+use super::{
+    Adjacencies, EdgeClientServerLookup, EntityAdjacencies, MaterialClientServerLookup,
+    VertexClientServerLookup, EDGE, MATERIAL, VERTEX,
+};
+use crate::protocol::RoomId;
+
+/// A map object spawned this update, linked to its neighbors once every object of the map
+/// has an entity.
+pub struct SpawnedMapObject {
+    pub room: RoomId,
+    pub map_type: u8,
+    pub id: u32,
+    pub entity: Entity,
+    pub adjacencies: Adjacencies,
+    pub material_type: Option<u8>,
+}
+
+/// Turns the ids in the adjacencies of spawned objects into entities, going through the lookups
+/// once per neighbor. Edges are next to the edges they share a vertex with and to the mana
+/// points both their vertexes are linked to.
+pub fn resolve_adjacencies(
+    spawned: &[SpawnedMapObject],
+    vertex_lookup: &VertexClientServerLookup,
+    edge_lookup: &EdgeClientServerLookup,
+    material_lookup: &MaterialClientServerLookup,
+) -> HashMap<Entity, EntityAdjacencies> {
+    let find = |lookup: &HashMap<(RoomId, u32), Entity>, room: RoomId, ids: &[u32]| {
+        ids.iter()
+            .filter_map(|id| lookup.get(&(room, *id)).copied())
+            .collect::<Vec<Entity>>()
+    };
+    let vertexes: HashMap<(RoomId, u32), &Adjacencies> = spawned
+        .iter()
+        .filter(|object| object.map_type == VERTEX)
+        .map(|object| ((object.room, object.id), &object.adjacencies))
+        .collect();
+
+    let mut resolved: HashMap<Entity, EntityAdjacencies> = HashMap::new();
+    // Mana points only know their vertexes, they get their links from the other side
+    let mut material_links: Vec<(Entity, Entity, u8)> = Vec::new();
+
+    for object in spawned.iter() {
+        let (room, ids) = (object.room, &object.adjacencies);
+        let adjacencies = match object.map_type {
+            VERTEX => EntityAdjacencies {
+                vertex_list: find(&vertex_lookup.0, room, &ids.vertex_list),
+                edge_list: find(&edge_lookup.0, room, &ids.edge_list),
+                material_list: find(&material_lookup.0, room, &ids.material_list),
+            },
+            EDGE => {
+                let ends: Vec<&Adjacencies> = ids
+                    .vertex_list
+                    .iter()
+                    .filter_map(|v| vertexes.get(&(room, *v)).copied())
+                    .collect();
+                let mut edges: Vec<u32> = ends
+                    .iter()
+                    .flat_map(|end| end.edge_list.iter().copied())
+                    .filter(|edge| *edge != object.id)
+                    .collect();
+                edges.sort_unstable();
+                edges.dedup();
+                let materials: Vec<u32> = match ends.as_slice() {
+                    [a, b] => a
+                        .material_list
+                        .iter()
+                        .copied()
+                        .filter(|m| b.material_list.contains(m))
+                        .collect(),
+                    _ => Vec::new(),
+                };
+                EntityAdjacencies {
+                    vertex_list: find(&vertex_lookup.0, room, &ids.vertex_list),
+                    edge_list: find(&edge_lookup.0, room, &edges),
+                    material_list: find(&material_lookup.0, room, &materials),
+                }
+            }
+            MATERIAL => {
+                resolved.entry(object.entity).or_default();
+                continue;
+            }
+            _ => continue,
+        };
+        for material in adjacencies.material_list.iter() {
+            material_links.push((*material, object.entity, object.map_type));
+        }
+        resolved.insert(object.entity, adjacencies);
+    }
+
+    for (material, neighbor, map_type) in material_links {
+        let Some(adjacencies) = resolved.get_mut(&material) else {
+            continue;
+        };
+        if map_type == VERTEX {
+            adjacencies.vertex_list.push(neighbor);
+        } else {
+            adjacencies.edge_list.push(neighbor);
+        }
+    }
+    resolved
+}
@@ -1,12 +1,13 @@
 use bevy::prelude::*;
 
 use super::*;
-use crate::protocol;
+use crate::{lobby, protocol};
 
 pub fn update_map(
     mut update_map: EventReader<protocol::ServerUpdateMapEvent>,
     mut query_vertexes: Query<(Entity, &mut Vertex), With<Vertex>>,
     vertex_lookup: Res<VertexClientServerLookup>,
+    lobby: Res<lobby::LobbyState>,
     // query_edges: Query<(Entity, &mut Edge), (With<Edge>, Without<Vertex>, Without<Material>)>,
     // query_materials: Query<
     //     (Entity, &mut Material),
@@ -18,9 +19,12 @@ pub fn update_map(
     if update.is_none() {
         return;
     }
+    let Some(room) = lobby.current_room else {
+        return;
+    };
 
     for vertex in update.unwrap().vertexes.iter() {
-        if let Some(v) = vertex_lookup.0.get(&vertex.id) {
+        if let Some(v) = vertex_lookup.0.get(&(room, vertex.id)) {
             if let Ok((_e, mut vert)) = query_vertexes.get_mut(*v) {
                 vert.filled = vertex.filled;
                 continue;
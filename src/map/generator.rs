@@ -0,0 +1,432 @@
+use std::{
+    collections::{HashMap, HashSet},
+    f32::consts::TAU,
+};
+
+use bevy::prelude::Vec2;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use super::level::{
+    piece_of, MapInitData, MapInitMana, MapInitManaConnections, MapInitVertexConnections,
+    MapInitVertexPositions, MANA_COLORS,
+};
+
+/// Levels with a name starting with this are generated instead of read from a file.
+pub const GENERATED_PREFIX: &str = "generated/";
+
+/// Distance between neighboring vertexes, about what the hand-made levels use.
+const EDGE_LENGTH: f32 = 100.0;
+/// Scattered vertexes are never closer than this.
+const MIN_DISTANCE: f32 = EDGE_LENGTH * 0.8;
+/// Longer edges are only made to connect a map that would fall apart otherwise.
+const MAX_EDGE_LENGTH: f32 = EDGE_LENGTH * 2.0;
+/// How likely an edge that connects nothing new is kept, lower makes sparser maps.
+const EXTRA_EDGE_CHANCE: f32 = 0.6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeneratorKind {
+    /// Hexagonal tiles like a Catan board, `size` rings around the middle one.
+    Hex,
+    /// `size` vertexes scattered at random, with edges that never cross.
+    Planar,
+    /// A random planar wedge of `size` vertexes turned around once per player.
+    Symmetric,
+}
+
+impl GeneratorKind {
+    fn name(&self) -> &'static str {
+        match self {
+            GeneratorKind::Hex => "hex",
+            GeneratorKind::Planar => "planar",
+            GeneratorKind::Symmetric => "symmetric",
+        }
+    }
+}
+
+/// Everything a generated map is made from, the same config always gives the same map.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratorConfig {
+    pub kind: GeneratorKind,
+    pub players: usize,
+    pub size: usize,
+    pub seed: u64,
+}
+
+impl GeneratorConfig {
+    /// Reads a level name like `generated/hex?players=4&size=2&seed=42`, `None` if the
+    /// level is not generated. Settings left out take their defaults, a missing seed is random.
+    pub fn from_level_name(level: &str) -> Option<Result<Self, String>> {
+        let spec = level.strip_prefix(GENERATED_PREFIX)?;
+        Some(Self::parse(spec).map_err(|e| format!("{}: {}", level, e)))
+    }
+
+    fn parse(spec: &str) -> Result<Self, String> {
+        let (kind, settings) = spec.split_once('?').unwrap_or((spec, ""));
+        let kind = match kind {
+            "hex" => GeneratorKind::Hex,
+            "planar" => GeneratorKind::Planar,
+            "symmetric" => GeneratorKind::Symmetric,
+            _ => return Err(format!("unknown generator {:?}", kind)),
+        };
+        let mut config = Self {
+            kind,
+            players: 4,
+            size: match kind {
+                GeneratorKind::Hex => 2,
+                GeneratorKind::Planar => 30,
+                GeneratorKind::Symmetric => 8,
+            },
+            seed: rand::random(),
+        };
+        for setting in settings.split('&').filter(|s| !s.is_empty()) {
+            let (key, value) = setting
+                .split_once('=')
+                .ok_or_else(|| format!("{:?} has no value", setting))?;
+            let number = |value: &str| {
+                value
+                    .parse::<u64>()
+                    .map_err(|_| format!("{} is not a number", value))
+            };
+            match key {
+                "players" => config.players = number(value)? as usize,
+                "size" => config.size = number(value)? as usize,
+                "seed" => config.seed = number(value)?,
+                _ => return Err(format!("unknown setting {:?}", key)),
+            }
+        }
+        Ok(config)
+    }
+
+    /// The level name that generates this map again.
+    pub fn level_name(&self) -> String {
+        format!(
+            "{}{}?players={}&size={}&seed={}",
+            GENERATED_PREFIX,
+            self.kind.name(),
+            self.players,
+            self.size,
+            self.seed
+        )
+    }
+}
+
+/// The seed a generated level was made with, `None` for a level file or a name that leaves
+/// the seed to chance.
+pub fn seed_of(level: &str) -> Option<u64> {
+    let config = GeneratorConfig::from_level_name(level)?.ok()?;
+    let (_, settings) = level.split_once('?')?;
+    settings
+        .split('&')
+        .any(|setting| setting.starts_with("seed="))
+        .then_some(config.seed)
+}
+
+/// A map being generated, turned into a level once done.
+#[derive(Default)]
+struct Board {
+    vertexes: Vec<Vec2>,
+    edges: Vec<(usize, usize)>,
+    /// Position, color and the vertexes it is next to.
+    mana: Vec<(Vec2, &'static str, Vec<usize>)>,
+    starts: Vec<usize>,
+}
+
+pub fn generate(config: &GeneratorConfig) -> Result<MapInitData, String> {
+    if config.players == 0 || config.size == 0 {
+        return Err("players and size have to be at least 1".to_string());
+    }
+    let fits = match config.kind {
+        GeneratorKind::Hex => config.size <= 50,
+        GeneratorKind::Planar => config.size <= 200,
+        GeneratorKind::Symmetric => config.size * config.players <= 200,
+    };
+    if !fits {
+        return Err("the map would be too big, use a smaller size".to_string());
+    }
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let board = match config.kind {
+        GeneratorKind::Hex => hex_board(config.size, config.players, &mut rng),
+        GeneratorKind::Planar => scattered_board(1, config.size, config.players, &mut rng),
+        GeneratorKind::Symmetric => {
+            scattered_board(config.players, config.size, config.players, &mut rng)
+        }
+    };
+    if board.starts.len() < config.players {
+        return Err(format!(
+            "the map only has room for {} players",
+            board.starts.len()
+        ));
+    }
+    Ok(board.into_level(&config.level_name()))
+}
+
+impl Board {
+    fn into_level(self, name: &str) -> MapInitData {
+        let mut level = MapInitData::new(name);
+        level.vertex_positions = self
+            .vertexes
+            .iter()
+            .map(|v| MapInitVertexPositions {
+                x: v.x.round(),
+                y: v.y.round(),
+            })
+            .collect();
+        level.vertex_connections = self
+            .edges
+            .iter()
+            .map(|(a, b)| MapInitVertexConnections {
+                a: *a as i32,
+                b: *b as i32,
+                metadata: Default::default(),
+            })
+            .collect();
+        for (i, (pos, color, vertexes)) in self.mana.iter().enumerate() {
+            level.mana_points.push(MapInitMana {
+                x: pos.x.round(),
+                y: pos.y.round(),
+                color: color.to_string(),
+            });
+            level
+                .mana_connections
+                .extend(vertexes.iter().map(|v| MapInitManaConnections {
+                    a: i as i32,
+                    b: *v as i32,
+                }));
+        }
+        level.map_start_vertexes = self.starts.iter().map(|v| *v as u32).collect();
+        level
+    }
+}
+
+/// `count` colors where no color shows up more than once more than another.
+fn balanced_colors(count: usize, rng: &mut StdRng) -> Vec<&'static str> {
+    let mut order = MANA_COLORS;
+    order.shuffle(rng);
+    (0..count).map(|i| order[i % order.len()]).collect()
+}
+
+/// The vertex furthest out in each of `players` evenly spaced directions.
+fn spread_starts(vertexes: &[Vec2], players: usize) -> Vec<usize> {
+    let center = vertexes.iter().copied().sum::<Vec2>() / vertexes.len() as f32;
+    let mut starts = Vec::new();
+    for k in 0..players {
+        let angle = TAU / 4.0 + TAU * k as f32 / players as f32;
+        let direction = Vec2::from_angle(angle);
+        let furthest = (0..vertexes.len())
+            .filter(|v| !starts.contains(v))
+            .max_by(|a, b| {
+                let reach = |v: usize| (vertexes[v] - center).dot(direction);
+                reach(*a).total_cmp(&reach(*b))
+            });
+        starts.extend(furthest);
+    }
+    starts
+}
+
+fn hex_board(rings: usize, players: usize, rng: &mut StdRng) -> Board {
+    let mut board = Board::default();
+    let mut by_position: HashMap<(i64, i64), usize> = HashMap::new();
+    let mut edges = HashSet::new();
+    let mut tiles = Vec::new();
+
+    let rings = rings as i32;
+    for q in -rings..=rings {
+        for r in (-rings).max(-q - rings)..=rings.min(-q + rings) {
+            let center = Vec2::new(
+                EDGE_LENGTH * 3f32.sqrt() * (q as f32 + r as f32 / 2.0),
+                EDGE_LENGTH * 1.5 * r as f32,
+            );
+            let corners: Vec<usize> = (0..6)
+                .map(|i| {
+                    let corner =
+                        center + EDGE_LENGTH * Vec2::from_angle(TAU / 12.0 + TAU * i as f32 / 6.0);
+                    let key = (
+                        (corner.x * 10.0).round() as i64,
+                        (corner.y * 10.0).round() as i64,
+                    );
+                    *by_position.entry(key).or_insert_with(|| {
+                        board.vertexes.push(corner);
+                        board.vertexes.len() - 1
+                    })
+                })
+                .collect();
+            for i in 0..6 {
+                let (a, b) = (corners[i], corners[(i + 1) % 6]);
+                if edges.insert((a.min(b), a.max(b))) {
+                    board.edges.push((a, b));
+                }
+            }
+            tiles.push((center, corners));
+        }
+    }
+
+    // Every tile is a mana point touching its six corners
+    let colors = balanced_colors(tiles.len(), rng);
+    board.mana = tiles
+        .into_iter()
+        .zip(colors)
+        .map(|((center, corners), color)| (center, color, corners))
+        .collect();
+    board.starts = spread_starts(&board.vertexes, players);
+    board
+}
+
+/// Scatters `size` vertexes in a wedge and turns it `copies` times around the middle,
+/// so every player gets the same surroundings. One copy is a plain random map.
+fn scattered_board(copies: usize, size: usize, players: usize, rng: &mut StdRng) -> Board {
+    let wedge = TAU / copies as f32;
+    let radius = EDGE_LENGTH * ((size * copies) as f32).sqrt();
+    let turn = |p: Vec2, k: usize| Vec2::from_angle(wedge * k as f32).rotate(p);
+
+    let mut points: Vec<Vec2> = Vec::new();
+    for _ in 0..size * 200 {
+        if points.len() == size {
+            break;
+        }
+        let p = Vec2::from_angle(rng.gen_range(0.0..wedge)) * radius * rng.gen::<f32>().sqrt();
+        let too_close = (0..copies).any(|k| {
+            let turned = turn(p, k);
+            (k > 0 && turned.distance(p) < MIN_DISTANCE)
+                || points.iter().any(|q| q.distance(turned) < MIN_DISTANCE)
+        });
+        if !too_close {
+            points.push(p);
+        }
+    }
+
+    let mut board = Board::default();
+    let per_copy = points.len();
+    for k in 0..copies {
+        board.vertexes.extend(points.iter().map(|p| turn(*p, k)));
+    }
+    // The vertex a copy of vertex `v` is, `k` wedges further around
+    let turn_vertex = |v: usize, k: usize| ((v / per_copy + k) % copies) * per_copy + v % per_copy;
+    board.edges = planar_edges(&board.vertexes, copies, &turn_vertex, rng);
+
+    // Mana points sit between a vertex and its neighbors, next to the closest three
+    let mut neighbors = vec![Vec::new(); board.vertexes.len()];
+    for (a, b) in board.edges.iter() {
+        neighbors[*a].push(*b);
+        neighbors[*b].push(*a);
+    }
+    let mut mana_vertexes: Vec<usize> = (0..per_copy).collect();
+    mana_vertexes.shuffle(rng);
+    mana_vertexes.truncate((per_copy / 3).max(1));
+    let colors = balanced_colors(mana_vertexes.len(), rng);
+    for k in 0..copies {
+        for (vertex, color) in mana_vertexes.iter().zip(colors.iter()) {
+            let vertex = turn_vertex(*vertex, k);
+            let around = &neighbors[vertex];
+            let middle = around.iter().map(|v| board.vertexes[*v]).sum::<Vec2>()
+                / around.len().max(1) as f32;
+            let pos = if around.is_empty() {
+                board.vertexes[vertex] + Vec2::new(EDGE_LENGTH / 2.0, 0.0)
+            } else {
+                board.vertexes[vertex].lerp(middle, 0.5)
+            };
+            let mut closest: Vec<usize> = (0..board.vertexes.len()).collect();
+            closest.sort_by(|a, b| {
+                board.vertexes[*a]
+                    .distance(pos)
+                    .total_cmp(&board.vertexes[*b].distance(pos))
+            });
+            closest.truncate(3);
+            board.mana.push((pos, color, closest));
+        }
+    }
+
+    board.starts = if copies > 1 {
+        // The outermost vertex of every wedge, all at the same distance from each other
+        let outermost = (0..per_copy).max_by(|a, b| {
+            board.vertexes[*a]
+                .length()
+                .total_cmp(&board.vertexes[*b].length())
+        });
+        outermost
+            .map(|v| (0..copies).map(|k| turn_vertex(v, k)).collect())
+            .unwrap_or_default()
+    } else {
+        spread_starts(&board.vertexes, players)
+    };
+    board
+}
+
+/// Connects vertexes with edges that never cross, shortest first. Edges are added together
+/// with their copies in the other wedges, and the map always ends up in one piece.
+fn planar_edges(
+    vertexes: &[Vec2],
+    copies: usize,
+    turn_vertex: &dyn Fn(usize, usize) -> usize,
+    rng: &mut StdRng,
+) -> Vec<(usize, usize)> {
+    let mut pairs: Vec<(usize, usize)> = (0..vertexes.len())
+        .flat_map(|a| (a + 1..vertexes.len()).map(move |b| (a, b)))
+        .collect();
+    let length = |(a, b): (usize, usize)| vertexes[a].distance(vertexes[b]);
+    pairs.sort_by(|x, y| length(*x).total_cmp(&length(*y)));
+
+    let mut edges: Vec<(usize, usize)> = Vec::new();
+    let mut present = HashSet::new();
+    let mut pieces: Vec<usize> = (0..vertexes.len()).collect();
+    let crosses = |edges: &[(usize, usize)], (a, b): (usize, usize)| {
+        edges
+            .iter()
+            .any(|(c, d)| segments_cross(vertexes, (a, b), (*c, *d)))
+    };
+
+    for pair in pairs.iter() {
+        let mut orbit: Vec<(usize, usize)> = (0..copies)
+            .map(|k| {
+                let (a, b) = (turn_vertex(pair.0, k), turn_vertex(pair.1, k));
+                (a.min(b), a.max(b))
+            })
+            .collect();
+        orbit.sort();
+        orbit.dedup();
+        if orbit.iter().any(|edge| present.contains(edge)) {
+            continue;
+        }
+        let joins = orbit
+            .iter()
+            .any(|(a, b)| piece_of(&mut pieces, *a) != piece_of(&mut pieces, *b));
+        if !joins && (length(*pair) > MAX_EDGE_LENGTH || rng.gen::<f32>() > EXTRA_EDGE_CHANCE) {
+            continue;
+        }
+        let blocked = orbit
+            .iter()
+            .enumerate()
+            .any(|(i, edge)| crosses(&edges, *edge) || crosses(&orbit[..i], *edge));
+        if blocked {
+            continue;
+        }
+        for (a, b) in orbit {
+            present.insert((a, b));
+            edges.push((a, b));
+            let (a, b) = (piece_of(&mut pieces, a), piece_of(&mut pieces, b));
+            pieces[a] = b;
+        }
+    }
+
+    // A blocked copy can leave wedges apart, single edges always bring them together
+    for (a, b) in pairs {
+        if piece_of(&mut pieces, a) == piece_of(&mut pieces, b) || crosses(&edges, (a, b)) {
+            continue;
+        }
+        edges.push((a, b));
+        let (a, b) = (piece_of(&mut pieces, a), piece_of(&mut pieces, b));
+        pieces[a] = b;
+    }
+    edges
+}
+
+/// Whether two edges cross somewhere other than at a vertex they share.
+pub fn segments_cross(vertexes: &[Vec2], (a, b): (usize, usize), (c, d): (usize, usize)) -> bool {
+    if a == c || a == d || b == c || b == d {
+        return false;
+    }
+    let (p1, p2, p3, p4) = (vertexes[a], vertexes[b], vertexes[c], vertexes[d]);
+    let side = |p: Vec2, q: Vec2, r: Vec2| (q - p).perp_dot(r - p);
+    let (d1, d2) = (side(p3, p4, p1), side(p3, p4, p2));
+    let (d3, d4) = (side(p1, p2, p3), side(p1, p2, p4));
+    d1 * d2 < 0.0 && d3 * d4 < 0.0
+}
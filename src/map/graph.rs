@@ -0,0 +1,226 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use bevy::prelude::*;
+
+use super::{adjacency::SpawnedMapObject, level, MapInitData, EDGE, MATERIAL, VERTEX};
+use crate::protocol::RoomId;
+
+/// The map of one room by the ids of its objects, built once per level. Answers neighbor
+/// questions without going through entities, so it works the same without a `World`.
+#[derive(Debug, Clone, Default)]
+pub struct MapGraph {
+    vertexes: BTreeMap<u32, VertexLinks>,
+    edges: BTreeMap<u32, (u32, u32)>,
+    edge_between: HashMap<(u32, u32), u32>,
+    materials: BTreeMap<u32, MaterialLinks>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct VertexLinks {
+    neighbors: Vec<u32>,
+    edges: Vec<u32>,
+    materials: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct MaterialLinks {
+    color: u8,
+    vertexes: Vec<u32>,
+}
+
+/// The graph of every room with a map, kept up to date as maps spawn and despawn.
+#[derive(Resource, Default)]
+pub struct MapGraphs(pub HashMap<RoomId, MapGraph>);
+
+impl MapGraph {
+    pub fn from_level(level: &MapInitData) -> Self {
+        let mut graph = Self::default();
+        for vertex in 0..level.vertex_positions.len() {
+            graph.add_vertex(vertex as u32);
+        }
+        for (id, edge) in level.vertex_connections.iter().enumerate() {
+            graph.add_edge(id as u32, edge.a as u32, edge.b as u32);
+        }
+        for (id, mana) in level.mana_points.iter().enumerate() {
+            let color = level::mana_color(&mana.color).unwrap_or(super::BLUE);
+            graph.add_material(id as u32, color);
+        }
+        for link in level.mana_connections.iter() {
+            graph.link_material(link.a as u32, link.b as u32);
+        }
+        graph
+    }
+
+    /// The graphs of the rooms the objects were spawned into.
+    pub fn from_spawned(spawned: &[SpawnedMapObject]) -> HashMap<RoomId, Self> {
+        let mut graphs: HashMap<RoomId, Self> = HashMap::new();
+        for object in spawned.iter().filter(|o| o.map_type == VERTEX) {
+            graphs.entry(object.room).or_default().add_vertex(object.id);
+        }
+        for object in spawned.iter() {
+            let graph = graphs.entry(object.room).or_default();
+            match (object.map_type, object.adjacencies.vertex_list.as_slice()) {
+                (EDGE, [a, b]) => {
+                    graph.add_edge(object.id, *a, *b);
+                }
+                (MATERIAL, _) => {
+                    graph.add_material(object.id, object.material_type.unwrap_or(super::BLUE));
+                    // Mana points list their vertexes in the material list
+                    for vertex in object.adjacencies.material_list.iter() {
+                        graph.link_material(object.id, *vertex);
+                    }
+                }
+                _ => {}
+            }
+        }
+        graphs
+    }
+
+    pub fn add_vertex(&mut self, vertex: u32) {
+        self.vertexes.entry(vertex).or_default();
+    }
+
+    /// Connects two vertexes that are in the graph, returns false if either is missing.
+    pub fn add_edge(&mut self, edge: u32, a: u32, b: u32) -> bool {
+        if !self.vertexes.contains_key(&a) || !self.vertexes.contains_key(&b) {
+            return false;
+        }
+        for (from, to) in [(a, b), (b, a)] {
+            let links = self.vertexes.get_mut(&from).unwrap();
+            links.neighbors.push(to);
+            links.edges.push(edge);
+        }
+        self.edges.insert(edge, (a, b));
+        self.edge_between.insert((a.min(b), a.max(b)), edge);
+        true
+    }
+
+    pub fn add_material(&mut self, material: u32, color: u8) {
+        self.materials.entry(material).or_default().color = color;
+    }
+
+    /// Links a mana point to a vertex, both have to be in the graph already.
+    pub fn link_material(&mut self, material: u32, vertex: u32) -> bool {
+        let (Some(links), Some(mana)) = (
+            self.vertexes.get_mut(&vertex),
+            self.materials.get_mut(&material),
+        ) else {
+            return false;
+        };
+        links.materials.push(material);
+        mana.vertexes.push(vertex);
+        true
+    }
+
+    pub fn contains_vertex(&self, vertex: u32) -> bool {
+        self.vertexes.contains_key(&vertex)
+    }
+
+    pub fn vertexes(&self) -> impl Iterator<Item = u32> + '_ {
+        self.vertexes.keys().copied()
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.vertexes.len()
+    }
+
+    pub fn neighbors(&self, vertex: u32) -> &[u32] {
+        self.vertexes
+            .get(&vertex)
+            .map(|v| v.neighbors.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// The edges that end at a vertex.
+    pub fn edges_of(&self, vertex: u32) -> &[u32] {
+        self.vertexes
+            .get(&vertex)
+            .map(|v| v.edges.as_slice())
+            .unwrap_or(&[])
+    }
+
+    pub fn edge_between(&self, a: u32, b: u32) -> Option<u32> {
+        self.edge_between.get(&(a.min(b), a.max(b))).copied()
+    }
+
+    pub fn edge_ends(&self, edge: u32) -> Option<(u32, u32)> {
+        self.edges.get(&edge).copied()
+    }
+
+    /// The mana points linked to a vertex.
+    pub fn materials_of(&self, vertex: u32) -> &[u32] {
+        self.vertexes
+            .get(&vertex)
+            .map(|v| v.materials.as_slice())
+            .unwrap_or(&[])
+    }
+
+    pub fn vertexes_of_material(&self, material: u32) -> &[u32] {
+        self.materials
+            .get(&material)
+            .map(|m| m.vertexes.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// The mana type of a mana point, like `map::RED`.
+    pub fn material_color(&self, material: u32) -> Option<u8> {
+        self.materials.get(&material).map(|m| m.color)
+    }
+
+    /// The fewest edges from one vertex to another, both included, `None` if they are not
+    /// connected. `passable` leaves out vertexes the path can't go through, like filled ones.
+    pub fn shortest_path(
+        &self,
+        from: u32,
+        to: u32,
+        passable: impl Fn(u32) -> bool,
+    ) -> Option<Vec<u32>> {
+        if !self.contains_vertex(from) || !self.contains_vertex(to) {
+            return None;
+        }
+        let mut came_from: HashMap<u32, u32> = HashMap::from([(from, from)]);
+        let mut queue = VecDeque::from([from]);
+        while let Some(vertex) = queue.pop_front() {
+            if vertex == to {
+                let mut path = vec![to];
+                while *path.last().unwrap() != from {
+                    path.push(came_from[path.last().unwrap()]);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for next in self.neighbors(vertex) {
+                if (*next == to || passable(*next)) && !came_from.contains_key(next) {
+                    came_from.insert(*next, vertex);
+                    queue.push_back(*next);
+                }
+            }
+        }
+        None
+    }
+
+    /// The vertexes of every connected piece of the map, biggest first.
+    pub fn components(&self) -> Vec<Vec<u32>> {
+        let mut seen = HashSet::new();
+        let mut components = Vec::new();
+        for start in self.vertexes() {
+            if !seen.insert(start) {
+                continue;
+            }
+            let mut component = vec![start];
+            let mut queue = VecDeque::from([start]);
+            while let Some(vertex) = queue.pop_front() {
+                for next in self.neighbors(vertex) {
+                    if seen.insert(*next) {
+                        component.push(*next);
+                        queue.push_back(*next);
+                    }
+                }
+            }
+            component.sort_unstable();
+            components.push(component);
+        }
+        components.sort_by_key(|component| std::cmp::Reverse(component.len()));
+        components
+    }
+}
@@ -0,0 +1,325 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
+
+use bevy::reflect::TypeUuid;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::{BLUE, GREEN, ORANGE, PURPLE, RED, YELLOW};
+
+/// Bumped whenever the layout of a level file changes, older files are migrated on load.
+/// Files without a version are the ones written before there was one.
+pub const LEVEL_VERSION: u32 = 1;
+
+/// A level file from `assets/levels`, loaded through the asset server.
+#[derive(Debug, Serialize, Deserialize, Clone, TypeUuid)]
+#[uuid = "f7a5361d-4f5d-458e-a65c-76a8f5b69101"]
+pub struct MapInitData {
+    pub version: u32,
+    pub name: String,
+    pub map_start_vertexes: Vec<u32>,
+    pub vertex_positions: Vec<MapInitVertexPositions>,
+    /// The edges, an edge's id is its index.
+    pub vertex_connections: Vec<MapInitVertexConnections>,
+    pub mana_points: Vec<MapInitMana>,
+    /// Mana point `a` next to vertex `b`.
+    pub mana_connections: Vec<MapInitManaConnections>,
+    /// Fields nothing reads yet, like the win conditions of the old levels, kept so that
+    /// upgrading a file loses nothing.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+impl MapInitData {
+    /// A level without anything on it yet.
+    pub fn new(name: &str) -> Self {
+        Self {
+            version: LEVEL_VERSION,
+            name: name.to_string(),
+            map_start_vertexes: Vec::new(),
+            vertex_positions: Vec::new(),
+            vertex_connections: Vec::new(),
+            mana_points: Vec::new(),
+            mana_connections: Vec::new(),
+            extra: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct MapInitVertexPositions {
+    pub x: f32,
+    pub y: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MapInitVertexConnections {
+    pub a: i32,
+    pub b: i32,
+    /// Anything a level wants to say about the edge.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MapInitMana {
+    pub x: f32,
+    pub y: f32,
+    pub color: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct MapInitManaConnections {
+    pub a: i32,
+    pub b: i32,
+}
+
+/// The version a level file was written with.
+pub fn file_version(level: &Value) -> u32 {
+    level
+        .get("version")
+        .and_then(Value::as_u64)
+        .unwrap_or_default() as u32
+}
+
+/// Parses a level of any version up to `LEVEL_VERSION`, `name` is used when it has none.
+pub fn read_level(bytes: &[u8], name: &str) -> Result<MapInitData, String> {
+    let level: Value = serde_json::from_slice(bytes).map_err(|e| e.to_string())?;
+    let version = file_version(&level);
+    if version > LEVEL_VERSION {
+        return Err(format!(
+            "{} is a version {} level, this build loads version {}",
+            name, version, LEVEL_VERSION
+        ));
+    }
+    let Value::Object(mut level) = level else {
+        return Err(format!("{} is not a level", name));
+    };
+    if version < 1 {
+        migrate_unversioned(&mut level, name);
+    }
+    serde_json::from_value(Value::Object(level)).map_err(|e| e.to_string())
+}
+
+pub fn write_level(path: &Path, level: &MapInitData) -> Result<(), String> {
+    let text = serde_json::to_string_pretty(level).map_err(|e| e.to_string())?;
+    fs::write(path, text).map_err(|e| format!("Could not write {:?}: {}", path, e))
+}
+
+/// The levels written before versions used `A`/`B` keys, a single `map_start_vertex`
+/// and could leave out their start vertexes and mana.
+fn migrate_unversioned(level: &mut Map<String, Value>, name: &str) {
+    for connections in ["vertex_connections", "mana_connections"] {
+        let Some(Value::Array(connections)) = level.get_mut(connections) else {
+            continue;
+        };
+        for connection in connections.iter_mut().filter_map(Value::as_object_mut) {
+            for (old, new) in [("A", "a"), ("B", "b")] {
+                if let Some(value) = connection.remove(old) {
+                    connection.entry(new).or_insert(value);
+                }
+            }
+        }
+    }
+
+    let old_starts = match level.remove("map_start_vertex") {
+        Some(Value::Array(starts)) => starts,
+        Some(start) => vec![start],
+        None => Vec::new(),
+    };
+    // Left empty rather than made up, `validate` reports a level nobody can start on
+    level
+        .entry("map_start_vertexes")
+        .or_insert(Value::Array(old_starts));
+
+    level
+        .entry("mana_points")
+        .or_insert(Value::Array(Vec::new()));
+    level
+        .entry("mana_connections")
+        .or_insert(Value::Array(Vec::new()));
+    level
+        .entry("name")
+        .or_insert(Value::String(name.to_string()));
+    level.insert("version".to_string(), LEVEL_VERSION.into());
+}
+
+/// Something wrong with a level, and where in the file it is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelProblem {
+    pub location: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for LevelProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.location, self.message)
+    }
+}
+
+/// The color names a level file can use, in the order of their mana types.
+pub const MANA_COLORS: [&str; 6] = ["blue", "yellow", "red", "green", "purple", "orange"];
+
+/// The mana type of a color name in a level file.
+pub fn mana_color(color: &str) -> Option<u8> {
+    match color {
+        "blue" => Some(BLUE),
+        "yellow" => Some(YELLOW),
+        "red" => Some(RED),
+        "green" => Some(GREEN),
+        "purple" => Some(PURPLE),
+        "orange" => Some(ORANGE),
+        _ => None,
+    }
+}
+
+/// Every problem that would keep the level from spawning or being played, empty if there is none.
+pub fn validate(level: &MapInitData) -> Vec<LevelProblem> {
+    let mut problems = Vec::new();
+    let mut problem =
+        |location: String, message: String| problems.push(LevelProblem { location, message });
+    let vertexes = level.vertex_positions.len();
+    let is_vertex = |id: i64| id >= 0 && (id as usize) < vertexes;
+    if vertexes == 0 {
+        problem(
+            "vertex_positions".to_string(),
+            "the level has no vertexes".to_string(),
+        );
+    }
+
+    // Vertexes joined by the edges seen so far, to find the pieces of the map
+    let mut pieces: Vec<usize> = (0..vertexes).collect();
+
+    let mut edges = HashMap::new();
+    for (i, edge) in level.vertex_connections.iter().enumerate() {
+        let location = format!("vertex_connections[{}]", i);
+        let mut dangling = false;
+        for end in [edge.a, edge.b] {
+            if !is_vertex(end as i64) {
+                problem(location.clone(), format!("vertex {} does not exist", end));
+                dangling = true;
+            }
+        }
+        if dangling {
+            continue;
+        }
+        if edge.a == edge.b {
+            problem(
+                location,
+                format!("vertex {} is connected to itself", edge.a),
+            );
+            continue;
+        }
+        let key = (edge.a.min(edge.b), edge.a.max(edge.b));
+        if let Some(first) = edges.get(&key) {
+            problem(
+                location,
+                format!(
+                    "vertexes {} and {} are already connected by vertex_connections[{}]",
+                    edge.a, edge.b, first
+                ),
+            );
+            continue;
+        }
+        edges.insert(key, i);
+        let (a, b) = (
+            piece_of(&mut pieces, edge.a as usize),
+            piece_of(&mut pieces, edge.b as usize),
+        );
+        pieces[a] = b;
+    }
+
+    let mut starts = HashMap::new();
+    for (i, start) in level.map_start_vertexes.iter().enumerate() {
+        let location = format!("map_start_vertexes[{}]", i);
+        if !is_vertex(*start as i64) {
+            problem(location, format!("vertex {} does not exist", start));
+            continue;
+        }
+        // Two players would be given the same vertex
+        if let Some(first) = starts.get(start) {
+            problem(
+                location,
+                format!("vertex {} is already map_start_vertexes[{}]", start, first),
+            );
+            continue;
+        }
+        starts.insert(*start, i);
+    }
+    if level.map_start_vertexes.is_empty() {
+        problem(
+            "map_start_vertexes".to_string(),
+            "the level has no start vertexes".to_string(),
+        );
+    }
+
+    for (i, mana) in level.mana_points.iter().enumerate() {
+        if mana_color(&mana.color).is_none() {
+            problem(
+                format!("mana_points[{}]", i),
+                format!("unknown color {:?}", mana.color),
+            );
+        }
+    }
+    for (i, connection) in level.mana_connections.iter().enumerate() {
+        let location = format!("mana_connections[{}]", i);
+        if connection.a < 0 || connection.a as usize >= level.mana_points.len() {
+            problem(
+                location.clone(),
+                format!("mana point {} does not exist", connection.a),
+            );
+        }
+        if !is_vertex(connection.b as i64) {
+            problem(location, format!("vertex {} does not exist", connection.b));
+        }
+    }
+
+    // The biggest piece is the map, anything else can't be walked to from it
+    let mut by_piece: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for vertex in 0..vertexes {
+        let piece = piece_of(&mut pieces, vertex);
+        by_piece.entry(piece).or_default().push(vertex);
+    }
+    let main = by_piece
+        .iter()
+        .max_by_key(|(_, piece)| piece.len())
+        .map(|(root, _)| *root);
+    for (root, piece) in by_piece.iter() {
+        if Some(*root) == main {
+            continue;
+        }
+        problem(
+            format!("vertex_positions[{}]", piece[0]),
+            format!(
+                "vertexes {:?} are not connected to the rest of the map",
+                piece
+            ),
+        );
+    }
+    for (i, start) in level.map_start_vertexes.iter().enumerate() {
+        if !is_vertex(*start as i64) {
+            continue;
+        }
+        if main.is_some_and(|main| piece_of(&mut pieces, *start as usize) != main) {
+            problem(
+                format!("map_start_vertexes[{}]", i),
+                format!("vertex {} can't reach the rest of the map", start),
+            );
+        }
+    }
+
+    problems
+}
+
+/// The vertex standing for the piece of the map `vertex` is in.
+pub(crate) fn piece_of(pieces: &mut [usize], vertex: usize) -> usize {
+    let mut root = vertex;
+    while pieces[root] != root {
+        root = pieces[root];
+    }
+    pieces[vertex] = root;
+    root
+}
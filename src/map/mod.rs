@@ -1,13 +1,21 @@
-use crate::protocol;
+use crate::protocol::{self, RoomId};
 use bevy::prelude::*;
 use bevy_interact_2d::{Group, Interactable, InteractionState};
 use bevy_quinnet::server::Server;
 use serde::{Deserialize, Serialize};
 use serde_json;
 use std::collections::HashMap;
+pub mod adjacency;
 mod client_map;
+pub mod generator;
+pub mod graph;
+pub mod level;
 pub(crate) mod server_map;
 
+pub use graph::{MapGraph, MapGraphs};
+pub use level::{MapInitData, LEVEL_VERSION};
+pub use server_map::{available_levels, level_path, level_spawn_events, LevelData, Levels};
+
 pub const MAPCLICKABLE: u8 = 0;
 pub const VERTEX: u8 = 1;
 pub const EDGE: u8 = 2;
@@ -25,9 +33,16 @@ pub struct MapPlugin;
 impl Plugin for MapPlugin {
     fn build(&self, app: &mut App) {
         app.add_startup_system(setup.in_base_set(StartupSet::PreStartup))
-            .add_system(spawn_map_object_system.pipe(setup_entity_adjacencies))
+            // A room whose map is replaced gets despawned and spawned again in the same update
+            .add_system(
+                spawn_map_object_system
+                    .pipe(setup_entity_adjacencies)
+                    .after(despawn_room),
+            )
             .add_system(click_map_object)
+            .add_system(despawn_room)
             .add_event::<MapObjectSpawnEvent>()
+            .add_event::<DespawnRoomEvent>()
             .add_system(animate_map_objects);
     }
 }
@@ -47,7 +62,9 @@ pub struct ServerMapPlugin;
 impl Plugin for ServerMapPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugin(MapPlugin)
-            .add_startup_system(server_map::setup)
+            .add_asset::<MapInitData>()
+            .init_asset_loader::<server_map::LevelLoader>()
+            .add_startup_system(server_map::load_levels.in_base_set(StartupSet::PreStartup))
             .add_event::<server_map::InitMapSend>()
             .add_system(server_map::handle_init_map_send)
             .add_system(server_map::update_map);
@@ -59,11 +76,12 @@ pub struct MapClickable {
     pub selected: bool,
     hover: bool,
     _map_type: u8,
-    mana_type: u8,
+    pub(crate) mana_type: u8,
     animation_timer: f32,
 }
 
 pub struct MapObjectSpawnEvent {
+    pub room: RoomId,
     pub map_type: u8,
     pub map_type_id: u32,
     pub x: f32,
@@ -76,13 +94,72 @@ pub struct MapObjectSpawnEvent {
     pub vertex_start: bool,
 }
 
+/// Removes every map object and player of a room.
+pub struct DespawnRoomEvent(pub RoomId);
+
+/// The spawn events that rebuild a map sent in an `InitMap` message inside `room`.
+pub fn init_map_events(
+    room: RoomId,
+    vertexes: &[protocol::Vertex],
+    edges: &[protocol::Edge],
+    materials: &[protocol::Material],
+) -> Vec<MapObjectSpawnEvent> {
+    let mut events = Vec::new();
+    for vert in vertexes.iter() {
+        events.push(MapObjectSpawnEvent {
+            room,
+            map_type: VERTEX,
+            map_type_id: vert.id,
+            x: vert.x,
+            y: vert.y,
+            roation: 0.0,
+            edge_list: vert.adjacentices.edge_list.clone(),
+            vertex_list: vert.adjacentices.vertex_list.clone(),
+            material_list: vert.adjacentices.material_list.clone(),
+            material_type: None,
+            vertex_start: vert.is_start_vertex,
+        })
+    }
+    for edge in edges.iter() {
+        events.push(MapObjectSpawnEvent {
+            room,
+            map_type: EDGE,
+            map_type_id: edge.id,
+            x: edge.x,
+            y: edge.y,
+            roation: edge.rotation,
+            edge_list: edge.adjacentices.edge_list.clone(),
+            vertex_list: edge.adjacentices.vertex_list.clone(),
+            material_list: edge.adjacentices.material_list.clone(),
+            material_type: None,
+            vertex_start: false,
+        })
+    }
+    for material in materials.iter() {
+        events.push(MapObjectSpawnEvent {
+            room,
+            map_type: MATERIAL,
+            map_type_id: material.id,
+            x: material.x,
+            y: material.y,
+            roation: 0.0,
+            edge_list: material.adjacentices.edge_list.clone(),
+            vertex_list: material.adjacentices.vertex_list.clone(),
+            material_list: material.adjacentices.material_list.clone(),
+            material_type: Some(material.material_type),
+            vertex_start: false,
+        })
+    }
+    events
+}
+
 #[derive(Component, Debug, Clone, Serialize, Deserialize)]
 pub struct Adjacencies {
     pub edge_list: Vec<u32>,
     pub vertex_list: Vec<u32>,
     pub material_list: Vec<u32>,
 }
-#[derive(Component)]
+#[derive(Component, Default)]
 pub struct EntityAdjacencies {
     pub edge_list: Vec<Entity>,
     pub vertex_list: Vec<Entity>,
@@ -91,7 +168,7 @@ pub struct EntityAdjacencies {
 
 #[derive(Component)]
 pub struct Edge {
-    id: u32,
+    pub id: u32,
     roation: f32,
 }
 
@@ -106,7 +183,7 @@ pub struct Vertex {
 pub struct VertexStart;
 
 #[derive(Component)]
-pub struct Material(u32);
+pub struct Material(pub u32);
 
 #[derive(Resource)]
 struct MapTextures {
@@ -128,46 +205,13 @@ struct MapTextures {
     padding_x: f32,
     padding_y: f32,
 }
+// Map ids repeat in every room, so the lookups are keyed by room and id
 #[derive(Resource)]
-pub struct VertexClientServerLookup(pub HashMap<u32, Entity>);
+pub struct VertexClientServerLookup(pub HashMap<(RoomId, u32), Entity>);
 #[derive(Resource)]
-pub struct EdgeClientServerLookup(pub HashMap<u32, Entity>);
+pub struct EdgeClientServerLookup(pub HashMap<(RoomId, u32), Entity>);
 #[derive(Resource)]
-pub struct MaterialClientServerLookup(pub HashMap<u32, Entity>);
-
-#[derive(Debug, Deserialize, Clone)]
-struct MapInitData {
-    map_start_vertexes: Vec<u32>,
-    vertex_positions: Vec<MapInitVertexPositions>,
-    vertex_connections: Vec<MapInitVertexConnections>,
-    mana_points: Vec<MapInitMana>,
-    mana_connections: Vec<MapInitManaConnections>,
-}
-
-#[derive(Debug, Deserialize, Clone, Copy)]
-struct MapInitVertexPositions {
-    x: f32,
-    y: f32,
-}
-
-#[derive(Debug, Deserialize, Clone, Copy)]
-struct MapInitVertexConnections {
-    a: i32,
-    b: i32,
-}
-
-#[derive(Debug, Deserialize, Clone)]
-struct MapInitMana {
-    x: f32,
-    y: f32,
-    color: String,
-}
-
-#[derive(Debug, Deserialize, Clone, Copy)]
-struct MapInitManaConnections {
-    a: i32,
-    b: i32,
-}
+pub struct MaterialClientServerLookup(pub HashMap<(RoomId, u32), Entity>);
 
 fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     let map_textures = MapTextures {
@@ -194,6 +238,7 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands.insert_resource(VertexClientServerLookup(HashMap::new()));
     commands.insert_resource(EdgeClientServerLookup(HashMap::new()));
     commands.insert_resource(MaterialClientServerLookup(HashMap::new()));
+    commands.insert_resource(MapGraphs::default());
 }
 
 fn animate_map_objects(
@@ -289,7 +334,8 @@ fn spawn_map_object_system(
     mut vertex_lookup: ResMut<VertexClientServerLookup>,
     mut edge_lookup: ResMut<EdgeClientServerLookup>,
     mut material_lookup: ResMut<MaterialClientServerLookup>,
-) {
+) -> Vec<adjacency::SpawnedMapObject> {
+    let mut spawned = Vec::new();
     for spawn in spawn_data.iter() {
         if spawn.map_type == VERTEX {
             let texture_atlas = TextureAtlas::from_grid(
@@ -317,6 +363,7 @@ fn spawn_map_object_system(
                         bounding_box: (Vec2::new(-16., -16.), Vec2::new(16., 16.)),
                         ..Default::default()
                     })
+                    .insert(spawn.room)
                     .insert(Vertex {
                         id: spawn.map_type_id,
                         filled: false,
@@ -327,11 +374,6 @@ fn spawn_map_object_system(
                         edge_list: spawn.edge_list.clone(),
                         material_list: spawn.material_list.clone(),
                     })
-                    .insert(EntityAdjacencies {
-                        vertex_list: Vec::new(),
-                        edge_list: Vec::new(),
-                        material_list: Vec::new(),
-                    })
                     .insert(MapClickable {
                         selected: false,
                         hover: false,
@@ -345,7 +387,10 @@ fn spawn_map_object_system(
                     commands.entity(entity).insert(VertexStart);
                 }
 
-                vertex_lookup.0.insert(spawn.map_type_id, entity);
+                vertex_lookup
+                    .0
+                    .insert((spawn.room, spawn.map_type_id), entity);
+                spawned.push(spawned_object(spawn, entity));
             }
         }
         if spawn.map_type == EDGE {
@@ -385,11 +430,7 @@ fn spawn_map_object_system(
                     edge_list: spawn.edge_list.clone(),
                     material_list: spawn.material_list.clone(),
                 })
-                .insert(EntityAdjacencies {
-                    vertex_list: Vec::new(),
-                    edge_list: Vec::new(),
-                    material_list: Vec::new(),
-                })
+                .insert(spawn.room)
                 .insert(Edge {
                     id: spawn.map_type_id,
                     roation: spawn.roation,
@@ -403,7 +444,10 @@ fn spawn_map_object_system(
                 })
                 .id();
 
-            edge_lookup.0.insert(spawn.map_type_id, entity);
+            edge_lookup
+                .0
+                .insert((spawn.room, spawn.map_type_id), entity);
+            spawned.push(spawned_object(spawn, entity));
         }
         if spawn.map_type == MATERIAL {
             let texture_atlas = TextureAtlas::from_grid(
@@ -430,17 +474,13 @@ fn spawn_map_object_system(
                     bounding_box: (Vec2::new(-16., -16.), Vec2::new(16., 16.)),
                     ..Default::default()
                 })
+                .insert(spawn.room)
                 .insert(Material(spawn.map_type_id))
                 .insert(Adjacencies {
                     vertex_list: spawn.vertex_list.clone(),
                     edge_list: spawn.edge_list.clone(),
                     material_list: spawn.material_list.clone(),
                 })
-                .insert(EntityAdjacencies {
-                    vertex_list: Vec::new(),
-                    edge_list: Vec::new(),
-                    material_list: Vec::new(),
-                })
                 .insert(MapClickable {
                     selected: false,
                     hover: false,
@@ -450,56 +490,70 @@ fn spawn_map_object_system(
                 })
                 .id();
 
-            material_lookup.0.insert(spawn.map_type_id, entity);
+            material_lookup
+                .0
+                .insert((spawn.room, spawn.map_type_id), entity);
+            spawned.push(spawned_object(spawn, entity));
         }
     }
+    spawned
 }
 
-fn setup_entity_adjacencies(
-    mut vertexes: Query<
-        (Entity, &mut EntityAdjacencies, &Adjacencies, &Vertex),
-        (With<Vertex>, Without<Edge>, Without<Material>),
-    >,
-    mut edges: Query<
-        (Entity, &mut EntityAdjacencies, &Adjacencies, &Edge),
-        (With<Edge>, Without<Vertex>, Without<Material>),
-    >,
-    mut material: Query<
-        (Entity, &mut EntityAdjacencies, &Adjacencies, &Material),
-        (With<Material>, Without<Vertex>, Without<Edge>),
-    >,
-) {
-    let mut combinations = vertexes.iter_combinations_mut();
-    while let Some(
-        [(entity1, mut entity_adjacencies1, adjacencies1, vertex1), (entity2, mut entity_adjacencies2, adjacencies2, vertex2)],
-    ) = combinations.fetch_next()
-    {
-        if adjacencies1.vertex_list.contains(&vertex2.id) {
-            entity_adjacencies1.vertex_list.push(entity2);
-        }
-        if adjacencies2.vertex_list.contains(&vertex1.id) {
-            entity_adjacencies2.vertex_list.push(entity1);
-        }
+fn spawned_object(spawn: &MapObjectSpawnEvent, entity: Entity) -> adjacency::SpawnedMapObject {
+    adjacency::SpawnedMapObject {
+        room: spawn.room,
+        map_type: spawn.map_type,
+        id: spawn.map_type_id,
+        entity,
+        adjacencies: Adjacencies {
+            vertex_list: spawn.vertex_list.clone(),
+            edge_list: spawn.edge_list.clone(),
+            material_list: spawn.material_list.clone(),
+        },
+        material_type: spawn.material_type,
     }
+}
 
-    for (entity, mut entity_adjacencies, adjacencies, _vertex) in vertexes.iter_mut() {
-        for (edge_entity, mut edge_entity_adj, _edge_adj, edge) in edges.iter_mut() {
-            if adjacencies.edge_list.contains(&edge.id) {
-                entity_adjacencies.edge_list.push(edge_entity);
-                edge_entity_adj.vertex_list.push(entity);
+pub(crate) fn despawn_room(
+    mut despawn_events: EventReader<DespawnRoomEvent>,
+    mut commands: Commands,
+    query: Query<(Entity, &RoomId)>,
+    mut vertex_lookup: ResMut<VertexClientServerLookup>,
+    mut edge_lookup: ResMut<EdgeClientServerLookup>,
+    mut material_lookup: ResMut<MaterialClientServerLookup>,
+    mut graphs: ResMut<MapGraphs>,
+) {
+    for DespawnRoomEvent(despawned) in despawn_events.iter() {
+        for (entity, room) in query.iter() {
+            if room == despawned {
+                commands.entity(entity).despawn_recursive();
             }
         }
+        vertex_lookup.0.retain(|(room, _), _| room != despawned);
+        edge_lookup.0.retain(|(room, _), _| room != despawned);
+        material_lookup.0.retain(|(room, _), _| room != despawned);
+        graphs.0.remove(despawned);
     }
+}
 
-    for (entity, mut entity_adjacencies, adjacencies, _vertex) in vertexes.iter_mut() {
-        for (material_entity, mut material_entity_adj, _material_adj, material) in
-            material.iter_mut()
-        {
-            if adjacencies.material_list.contains(&material.0) {
-                entity_adjacencies.material_list.push(material_entity);
-                material_entity_adj.vertex_list.push(entity);
-            }
-        }
+/// Links the objects spawned this update to their neighbors, once per map load instead of
+/// every update. Their entities are only known through the lookups until the commands apply.
+fn setup_entity_adjacencies(
+    In(spawned): In<Vec<adjacency::SpawnedMapObject>>,
+    mut commands: Commands,
+    vertex_lookup: Res<VertexClientServerLookup>,
+    edge_lookup: Res<EdgeClientServerLookup>,
+    material_lookup: Res<MaterialClientServerLookup>,
+    mut graphs: ResMut<MapGraphs>,
+) {
+    if spawned.is_empty() {
+        return;
+    }
+    graphs.0.extend(MapGraph::from_spawned(&spawned));
+    let resolved =
+        adjacency::resolve_adjacencies(&spawned, &vertex_lookup, &edge_lookup, &material_lookup);
+    for (entity, adjacencies) in resolved {
+        commands.entity(entity).insert(adjacencies);
     }
 }
 
@@ -1,45 +1,225 @@
-use bevy::prelude::*;
+use std::collections::BTreeMap;
+
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadState, LoadedAsset},
+    ecs::system::SystemParam,
+    prelude::*,
+};
 
 use super::*;
+use crate::{lobby, replay, server::ServerConfig};
 
-pub fn setup(mut map_generator: EventWriter<MapObjectSpawnEvent>) {
-    let map_file_name = "./assets/levels/level_3.json";
+/// Levels are named relative to the assets folder, like `levels/level_3.json`.
+pub fn level_path(level: &str) -> String {
+    format!("./assets/{}", level)
+}
 
-    let map_file = {
-        // Load the first file into a string.
-        let text = std::fs::read_to_string(&map_file_name).unwrap();
+/// Every level in `assets/levels` and its folders, named the way `level_path` expects them.
+pub fn available_levels() -> Vec<String> {
+    let mut levels = Vec::new();
+    find_levels("levels", &mut levels);
+    levels.sort();
+    levels
+}
 
-        // Parse the string into a dynamically-typed JSON structure.
-        serde_json::from_str::<MapInitData>(&text).unwrap()
+fn find_levels(dir: &str, levels: &mut Vec<String>) {
+    let Ok(entries) = std::fs::read_dir(level_path(dir)) else {
+        return;
     };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let name = format!("{}/{}", dir, entry.file_name().to_string_lossy());
+        if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+            find_levels(&name, levels);
+        } else if name.ends_with(".json") {
+            levels.push(name);
+        }
+    }
+}
+
+/// Reads level files into `MapInitData` assets, so edits to them get picked up while running.
+/// Levels of older versions are migrated as they load, invalid ones fail to load.
+#[derive(Default)]
+pub struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let path = load_context.path();
+            let name = path.file_stem().unwrap_or_default().to_string_lossy();
+            let level = level::read_level(bytes, &name)
+                .map_err(|e| bevy::asset::Error::msg(format!("{:?}: {}", path, e)))?;
+            let problems = level::validate(&level);
+            if !problems.is_empty() {
+                let problems: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+                return Err(bevy::asset::Error::msg(format!(
+                    "{:?} is not a valid level:\n{}",
+                    path,
+                    problems.join("\n")
+                )));
+            }
+            load_context.set_default_asset(LoadedAsset::new(level));
+            Ok(())
+        })
+    }
 
-    let vertex_positions = map_file.vertex_positions.clone();
-    let vertex_connections = map_file.vertex_connections.clone();
-    let mana_connections = map_file.mana_connections.clone();
+    fn extensions(&self) -> &[&str] {
+        &["json"]
+    }
+}
 
-    for i in 0..map_file.vertex_positions.len() {
-        let mut edge_list = Vec::new();
-        let mut vertex_list = Vec::new();
-        let mut material_list = Vec::new();
+/// Every level rooms can play, by the name `level_path` expects.
+#[derive(Resource, Default)]
+pub struct Levels(pub BTreeMap<String, Handle<MapInitData>>);
 
-        for j in 0..mana_connections.len() {
-            if mana_connections[j].b == i as i32 {
-                material_list.push(mana_connections[j].a as u32);
+/// Starts loading every level in `assets/levels`, and the configured one if it lives elsewhere.
+/// A generated level is made right away, its name then has the seed in it for clients to see.
+pub fn load_levels(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut assets: ResMut<Assets<MapInitData>>,
+    mut config: ResMut<ServerConfig>,
+) {
+    let mut levels: BTreeMap<String, Handle<MapInitData>> = available_levels()
+        .into_iter()
+        .map(|level| {
+            let handle = asset_server.load(level.as_str());
+            (level, handle)
+        })
+        .collect();
+    match generator::GeneratorConfig::from_level_name(&config.level) {
+        Some(Ok(generator)) => match generator::generate(&generator) {
+            Ok(level) => {
+                config.level = generator.level_name();
+                info!("Generated level {}", config.level);
+                levels.insert(config.level.clone(), assets.add(level));
             }
+            Err(e) => error!("Could not generate {}: {}", config.level, e),
+        },
+        Some(Err(e)) => error!("Invalid generated level {}", e),
+        None if !levels.contains_key(&config.level) => {
+            let handle = asset_server.load(config.level.as_str());
+            levels.insert(config.level.clone(), handle);
         }
+        None => {}
+    }
+    commands.insert_resource(Levels(levels));
+}
 
-        for j in 0..vertex_connections.len() {
-            if vertex_connections[j].a == i as i32 {
-                vertex_list.push(vertex_connections[j].b as u32);
-                edge_list.push(j as u32);
-            }
-            if vertex_connections[j].b == i as i32 {
-                vertex_list.push(vertex_connections[j].a as u32);
-                edge_list.push(j as u32);
-            }
+/// The levels and their loaded contents, looked up by name.
+#[derive(SystemParam)]
+pub struct LevelData<'w> {
+    levels: Res<'w, Levels>,
+    assets: Res<'w, Assets<MapInitData>>,
+    asset_server: Res<'w, AssetServer>,
+}
+
+impl<'w> LevelData<'w> {
+    /// The levels rooms can pick, leaving out the ones that failed to load.
+    pub fn names(&self) -> Vec<String> {
+        self.levels
+            .0
+            .keys()
+            .filter(|level| !self.failed(level))
+            .cloned()
+            .collect()
+    }
+
+    pub fn contains(&self, level: &str) -> bool {
+        self.levels.0.contains_key(level)
+    }
+
+    pub fn get(&self, level: &str) -> Result<&MapInitData, String> {
+        let handle = self
+            .levels
+            .0
+            .get(level)
+            .ok_or_else(|| format!("Unknown level {}", level))?;
+        match self.assets.get(handle) {
+            Some(data) => Ok(data),
+            None if self.failed(level) => Err(format!(
+                "Level {} failed to load, see the errors logged for it",
+                level
+            )),
+            None => Err(format!("Level {} is not loaded", level)),
         }
+    }
+
+    /// Whether the level will never load, unlike one that is still loading.
+    pub fn failed(&self, level: &str) -> bool {
+        match self.levels.0.get(level) {
+            Some(handle) => self.asset_server.get_load_state(handle) == LoadState::Failed,
+            None => true,
+        }
+    }
+
+    /// The name of the level behind an asset handle.
+    pub fn name_of(&self, handle: &Handle<MapInitData>) -> Option<&str> {
+        self.levels
+            .0
+            .iter()
+            .find(|(_, level)| *level == handle)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// How many players fit on a level, one per start vertex.
+    pub fn max_players(&self, level: &str) -> Result<usize, String> {
+        let mut starts = self.get(level)?.map_start_vertexes.clone();
+        starts.sort();
+        starts.dedup();
+        Ok(starts.len())
+    }
+}
+
+/// Sends the spawn events for every object of a level into `room`.
+pub fn spawn_level(
+    room: RoomId,
+    map_file: &MapInitData,
+    map_generator: &mut EventWriter<MapObjectSpawnEvent>,
+) {
+    map_generator.send_batch(level_spawn_events(room, map_file));
+}
 
-        let map_spawn = MapObjectSpawnEvent {
+/// The spawn events for every object of a level, vertexes first, then edges and mana points.
+pub fn level_spawn_events(room: RoomId, map_file: &MapInitData) -> Vec<MapObjectSpawnEvent> {
+    let vertex_positions = &map_file.vertex_positions;
+    let vertex_connections = &map_file.vertex_connections;
+
+    // What each vertex is next to, gathered in one pass over the connections
+    let mut vertex_edges = vec![Vec::new(); vertex_positions.len()];
+    let mut vertex_neighbors = vec![Vec::new(); vertex_positions.len()];
+    let mut vertex_materials = vec![Vec::new(); vertex_positions.len()];
+    let mut material_vertexes = vec![Vec::new(); map_file.mana_points.len()];
+    for (j, edge) in vertex_connections.iter().enumerate() {
+        if let Some(edges) = vertex_edges.get_mut(edge.a as usize) {
+            edges.push(j as u32);
+            vertex_neighbors[edge.a as usize].push(edge.b as u32);
+        }
+        if let Some(edges) = vertex_edges.get_mut(edge.b as usize) {
+            edges.push(j as u32);
+            vertex_neighbors[edge.b as usize].push(edge.a as u32);
+        }
+    }
+    for link in map_file.mana_connections.iter() {
+        if let Some(materials) = vertex_materials.get_mut(link.b as usize) {
+            materials.push(link.a as u32);
+        }
+        if let Some(vertexes) = material_vertexes.get_mut(link.a as usize) {
+            vertexes.push(link.b as u32);
+        }
+    }
+
+    let mut events = Vec::new();
+    let vertexes = vertex_edges
+        .into_iter()
+        .zip(vertex_neighbors)
+        .zip(vertex_materials);
+    for (i, ((edge_list, vertex_list), material_list)) in vertexes.enumerate() {
+        events.push(MapObjectSpawnEvent {
+            room,
             map_type: VERTEX,
             map_type_id: i as u32,
             x: vertex_positions[i].x,
@@ -50,24 +230,22 @@ pub fn setup(mut map_generator: EventWriter<MapObjectSpawnEvent>) {
             vertex_list,
             material_list,
             vertex_start: map_file.map_start_vertexes.contains(&(i as u32)),
-        };
-        map_generator.send(map_spawn);
+        });
     }
 
-    for i in 0..map_file.vertex_connections.len() {
-        let edge_list = Vec::new();
-        let mut vertex_list = Vec::new();
-        let material_list = Vec::new();
-
-        vertex_list.push(vertex_connections[i].a as u32);
-        vertex_list.push(vertex_connections[i].b as u32);
-
-        let a = vertex_positions[vertex_connections[i].a as usize];
-        let b = vertex_positions[vertex_connections[i].b as usize];
+    for (i, edge) in vertex_connections.iter().enumerate() {
+        // Only a level open in the editor can have edges to missing vertexes
+        let (Some(a), Some(b)) = (
+            vertex_positions.get(edge.a as usize).copied(),
+            vertex_positions.get(edge.b as usize).copied(),
+        ) else {
+            continue;
+        };
 
         let pos = (Vec2::new(a.x, a.y) + Vec2::new(b.x, b.y)) / 2.0;
 
-        let map_spawn = MapObjectSpawnEvent {
+        events.push(MapObjectSpawnEvent {
+            room,
             map_type: EDGE,
             map_type_id: i as u32,
             x: pos.x,
@@ -75,85 +253,115 @@ pub fn setup(mut map_generator: EventWriter<MapObjectSpawnEvent>) {
             roation: (a.y - b.y).atan2(a.x - b.x),
 
             material_type: None,
-            edge_list,
-            vertex_list,
-            material_list,
+            edge_list: Vec::new(),
+            vertex_list: vec![edge.a as u32, edge.b as u32],
+            material_list: Vec::new(),
             vertex_start: false,
-        };
-        map_generator.send(map_spawn);
+        });
     }
 
-    for (i, mana_positions) in map_file.mana_points.iter().enumerate() {
-        let mut color = 0;
-
-        let edge_list = Vec::new();
-        let vertex_list = Vec::new();
-        let mut material_list = Vec::new();
+    for (i, (mana_positions, material_list)) in map_file
+        .mana_points
+        .iter()
+        .zip(material_vertexes)
+        .enumerate()
+    {
+        // Levels are validated when they load, so the color is known
+        let color = level::mana_color(&mana_positions.color).unwrap_or(BLUE);
 
-        for j in 0..mana_connections.len() {
-            if mana_connections[j].a == i as i32 {
-                material_list.push(mana_connections[j].b as u32);
-            }
-        }
-
-        match mana_positions.color.as_str() {
-            "blue" => color = BLUE,
-            "yellow" => color = YELLOW,
-            "red" => color = RED,
-            "green" => color = GREEN,
-            "purple" => color = PURPLE,
-            "orange" => color = ORANGE,
-            _ => {}
-        }
-
-        let map_spawn = MapObjectSpawnEvent {
+        events.push(MapObjectSpawnEvent {
+            room,
             map_type: MATERIAL,
             map_type_id: i as u32,
             x: mana_positions.x,
             y: mana_positions.y,
             roation: 0.0,
-            edge_list,
-            vertex_list,
+            edge_list: Vec::new(),
+            vertex_list: Vec::new(),
             material_list,
             material_type: Some(color),
             vertex_start: false,
-        };
-        map_generator.send(map_spawn);
+        });
     }
+    events
 }
 
 pub struct InitMapSend {
     pub client_id: u64,
+    pub room: RoomId,
 }
 
-pub fn handle_init_map_send(
-    mut init_map_event: EventReader<InitMapSend>,
-    vertexes: Query<
-        (Entity, &Transform, &Adjacencies, &Vertex),
-        (With<Vertex>, Without<Edge>, Without<super::Material>),
-    >,
-    edges: Query<
-        (Entity, &Transform, &Adjacencies, &Edge),
-        (With<Edge>, Without<Vertex>, Without<super::Material>),
-    >,
-    materials: Query<
-        (
-            Entity,
-            &Transform,
-            &Adjacencies,
-            &super::Material,
-            &MapClickable,
-        ),
-        (With<super::Material>, Without<Vertex>, Without<Edge>),
-    >,
-    mut server: ResMut<Server>,
-) {
-    if init_map_event.len() > 0 {
+type VertexSnapshot = (
+    &'static Transform,
+    &'static Adjacencies,
+    &'static Vertex,
+    &'static RoomId,
+);
+type EdgeSnapshot = (
+    &'static Transform,
+    &'static Adjacencies,
+    &'static Edge,
+    &'static RoomId,
+);
+type MaterialSnapshot = (
+    &'static Transform,
+    &'static Adjacencies,
+    &'static super::Material,
+    &'static MapClickable,
+    &'static RoomId,
+);
+
+type OnlyVertexes = (With<Vertex>, Without<Edge>, Without<super::Material>);
+type OnlyEdges = (With<Edge>, Without<Vertex>, Without<super::Material>);
+type OnlyMaterials = (With<super::Material>, Without<Vertex>, Without<Edge>);
+
+/// Read access to every map object, to describe a room's map to whoever needs it.
+#[derive(SystemParam)]
+pub struct MapSnapshot<'w, 's> {
+    vertexes: Query<'w, 's, VertexSnapshot, OnlyVertexes>,
+    edges: Query<'w, 's, EdgeSnapshot, OnlyEdges>,
+    materials: Query<'w, 's, MaterialSnapshot, OnlyMaterials>,
+}
+
+/// Every object of a room's map, the way `InitMap` carries them.
+pub type MapObjects = (
+    Vec<protocol::Vertex>,
+    Vec<protocol::Edge>,
+    Vec<protocol::Material>,
+);
+
+impl<'w, 's> MapSnapshot<'w, 's> {
+    /// The `InitMap` message for everything in `room`.
+    pub fn init_map(&self, room: RoomId) -> protocol::ServerMessage {
+        let (vertexes, edges, materials) = self.objects(room);
+        protocol::ServerMessage::InitMap {
+            vertexes,
+            edges,
+            materials,
+        }
+    }
+
+    /// Ids of the room's vertexes that someone stands on or is moving to.
+    pub fn filled_vertexes(&self, room: RoomId) -> Vec<u32> {
+        let mut filled: Vec<u32> = self
+            .vertexes
+            .iter()
+            .filter(|(_, _, vert, vert_room)| **vert_room == room && vert.filled)
+            .map(|(_, _, vert, _)| vert.id)
+            .collect();
+        filled.sort();
+        filled
+    }
+
+    pub fn objects(&self, room: RoomId) -> MapObjects {
         let mut vertexes_data = Vec::<protocol::Vertex>::new();
         let mut edges_data = Vec::<protocol::Edge>::new();
         let mut materials_data = Vec::<protocol::Material>::new();
 
-        for (_e, pos, adj, vert) in vertexes.iter() {
+        for (pos, adj, vert, vert_room) in self.vertexes.iter() {
+            if *vert_room != room {
+                continue;
+            }
             vertexes_data.push(protocol::Vertex {
                 id: vert.id,
                 adjacentices: adj.clone(),
@@ -163,7 +371,10 @@ pub fn handle_init_map_send(
             })
         }
 
-        for (_e, pos, adj, edge) in edges.iter() {
+        for (pos, adj, edge, edge_room) in self.edges.iter() {
+            if *edge_room != room {
+                continue;
+            }
             edges_data.push(protocol::Edge {
                 id: edge.id,
                 adjacentices: adj.clone(),
@@ -173,7 +384,10 @@ pub fn handle_init_map_send(
             })
         }
 
-        for (_e, pos, adj, mat, click) in materials.iter() {
+        for (pos, adj, mat, click, mat_room) in self.materials.iter() {
+            if *mat_room != room {
+                continue;
+            }
             materials_data.push(protocol::Material {
                 id: mat.0,
                 adjacentices: adj.clone(),
@@ -183,32 +397,37 @@ pub fn handle_init_map_send(
             })
         }
 
-        for init_map in init_map_event.iter() {
-            if let Ok(_result) = server.endpoint_mut().send_message(
-                init_map.client_id,
-                protocol::ServerMessage::InitMap {
-                    vertexes: vertexes_data.clone(),
-                    edges: edges_data.clone(),
-                    materials: materials_data.clone(),
-                },
-            ) {
-                info!("Sent map to Client");
-            } else {
-                info!("Failed to send map to Client");
-            }
+        (vertexes_data, edges_data, materials_data)
+    }
+}
+
+pub fn handle_init_map_send(
+    mut init_map_event: EventReader<InitMapSend>,
+    map: MapSnapshot,
+    mut server: ResMut<Server>,
+) {
+    for init_map in init_map_event.iter() {
+        if let Ok(_result) = server
+            .endpoint_mut()
+            .send_message(init_map.client_id, map.init_map(init_map.room))
+        {
+            info!("Sent map to Client");
+        } else {
+            info!("Failed to send map to Client");
         }
     }
 }
 
 pub fn update_map(
-    query_vertexes: Query<(Entity, &mut Vertex), With<Vertex>>,
+    query_vertexes: Query<(Entity, &mut Vertex, &RoomId), With<Vertex>>,
     // query_edges: Query<(Entity, &mut Edge), (With<Edge>, Without<Vertex>, Without<Material>)>,
     // query_materials: Query<
     //     (Entity, &mut Material),
     //     (With<Material>, Without<Edge>, Without<Vertex>),
     // >,
     server: ResMut<bevy_quinnet::server::Server>,
-    users: Res<protocol::Users>,
+    rooms: Res<lobby::Rooms>,
+    mut recorder: ResMut<replay::ReplayRecorder>,
     mut timer: Local<f32>,
     time: Res<Time>,
 ) {
@@ -219,29 +438,36 @@ pub fn update_map(
     } else {
         *timer -= 1.0 / 35.0;
 
-        let mut vertexes: Vec<protocol::VertexUpdate> = Vec::new();
-        let mut _edges: Vec<protocol::EdgeUpdate> = Vec::new();
-        let mut _materials: Vec<protocol::MaterialUpdate> = Vec::new();
+        let mut vertexes: HashMap<RoomId, Vec<protocol::VertexUpdate>> = HashMap::new();
 
-        for (_e, vertex) in query_vertexes.iter() {
-            vertexes.push(protocol::VertexUpdate {
-                id: vertex.id,
-                filled: vertex.filled,
-            })
+        for (_e, vertex, room) in query_vertexes.iter() {
+            vertexes
+                .entry(*room)
+                .or_default()
+                .push(protocol::VertexUpdate {
+                    id: vertex.id,
+                    filled: vertex.filled,
+                })
         }
 
-        if let Ok(_temp) = server.endpoint().send_group_message(
-            users.names.keys().into_iter(),
-            //ChannelId::Unreliable,
-            protocol::ServerMessage::UpdateMap {
+        for (room, vertexes) in vertexes {
+            let Some(members) = rooms.members(room) else {
+                continue;
+            };
+            let message = protocol::ServerMessage::UpdateMap {
                 vertexes,
-                edges: _edges,
-                materials: _materials,
-            },
-        ) {
-            // info!("Sent Players")
-        } else {
-            info!("Failed to Update Map")
+                edges: Vec::new(),
+                materials: Vec::new(),
+            };
+            recorder.record(room, &message);
+            if let Ok(_temp) = server.endpoint().send_group_message(
+                members, //ChannelId::Unreliable,
+                message,
+            ) {
+                // info!("Sent Players")
+            } else {
+                info!("Failed to Update Map")
+            }
         }
     }
 }
@@ -1,6 +1,10 @@
 use super::*;
+use crate::lobby;
 
-use bevy_quinnet::client::Client;
+use bevy_quinnet::client::{
+    connection::{ConnectionEvent, ConnectionLostEvent},
+    Client,
+};
 pub fn spawn_players(
     mut commands: Commands,
     mut player_spawn: EventReader<PlayerSpawnEvent>,
@@ -37,10 +41,13 @@ pub fn spawn_players(
                     transform: Transform::from_xyz(pos.translation.x, pos.translation.y, 100.0),
                     sprite: TextureAtlasSprite {
                         anchor: Anchor::Custom(Vec2 { x: 0.0, y: -0.3 }),
+                        color: player.color.tint(),
                         ..Default::default()
                     },
                     ..Default::default()
                 })
+                .insert(player.room)
+                .insert(player.color)
                 .insert(Player {
                     id: player.id.unwrap(),
                     current_vertex: entity,
@@ -67,47 +74,86 @@ pub fn spawn_players(
     }
 }
 
+/// Asks the server to move to the vertex selected on the map. Whether the move is allowed
+/// is up to the server, which says why not in its reply.
 pub fn move_my_player(
     client: ResMut<Client>,
     query_state: Query<&protocol::CurrentClientEventTrigger>,
     my_player: Query<&Player, With<ControlledPlayer>>,
-    mut vertexes: Query<
-        (
-            &map::Vertex,
-            &map::EntityAdjacencies,
-            &mut map::MapClickable,
-        ),
-        With<map::Vertex>,
-    >,
+    vertexes: Query<(Entity, &map::Vertex, &map::MapClickable)>,
+    mut requests: ResMut<ActionRequests>,
 ) {
     let state = query_state.single();
-    if state.0 == protocol::ClientEvents::MOVE {
-        if let Ok(player) = my_player.get_single() {
-            if player.state == super::States::MoveToEntity {
-                return;
-            }
+    if state.0 != protocol::CommandKind::Move || !requests.pending.is_empty() {
+        return;
+    }
+    let Ok(player) = my_player.get_single() else {
+        return;
+    };
+    if player.state == super::States::MoveToEntity {
+        return;
+    }
+    let Some((entity, vertex, _)) = vertexes
+        .iter()
+        .find(|(entity, _, click)| click.selected && *entity != player.current_vertex)
+    else {
+        return;
+    };
+
+    let request_id = requests.next_id();
+    let sent = client
+        .connection()
+        .send_message(protocol::ClientMessage::SendCommand {
+            request_id,
+            command: protocol::GameCommand::Move { vertex: vertex.id }.into(),
+        });
+    if sent.is_err() {
+        println!("Error with sending Move Event");
+        return;
+    }
+    requests.pending.insert(request_id, entity);
+}
 
-            if let Ok((_vertex, adj, _click)) = vertexes.get(player.current_vertex) {
-                let adj_verts = adj.vertex_list.clone();
-
-                for adj_vert in adj_verts {
-                    if let Ok((vertex, _adj, mut click)) = vertexes.get_mut(adj_vert) {
-                        if click.selected && !vertex.filled {
-                            click.selected = false;
-                            let temp = client.connection().send_message(
-                                protocol::ClientMessage::SendEvent {
-                                    name: protocol::ClientEvents::MOVE,
-                                    map_type: map::VERTEX,
-                                    type_id: vertex.id,
-                                },
-                            );
-                            if temp.is_err() {
-                                println!("Error with sending Move Event")
-                            }
-                        }
-                    }
-                }
+/// Clears the selection a request was made from once the server replied, and keeps the
+/// reason of a refusal for the screen. Requests still waiting when the map or the connection
+/// changes will never be answered, so they are forgotten.
+pub fn handle_action_replies(
+    mut messages: EventReader<protocol::ServerMessageEvent>,
+    mut connected: EventReader<ConnectionEvent>,
+    mut lost: EventReader<ConnectionLostEvent>,
+    mut requests: ResMut<ActionRequests>,
+    mut clickables: Query<&mut map::MapClickable>,
+    time: Res<Time>,
+) {
+    requests.rejection_age += time.delta_seconds();
+    if connected.iter().count() + lost.iter().count() > 0 {
+        requests.pending.clear();
+    }
+    for protocol::ServerMessageEvent(message) in messages.iter() {
+        let (request_id, rejection) = match message {
+            protocol::ServerMessage::RoomLeft {} | protocol::ServerMessage::InitMap { .. } => {
+                requests.pending.clear();
+                continue;
+            }
+            protocol::ServerMessage::ActionAccepted { request_id } => (request_id, None),
+            protocol::ServerMessage::ActionRejected { request_id, reason } => {
+                (request_id, Some(reason))
+            }
+            _ => continue,
+        };
+        let Some(entity) = requests.pending.remove(request_id) else {
+            continue;
+        };
+        if let Ok(mut click) = clickables.get_mut(entity) {
+            click.selected = false;
+        }
+        if let Some(reason) = rejection {
+            info!("Move refused: {}", reason);
+            for mut click in clickables.iter_mut() {
+                click.selected = false;
             }
+            requests.rejection = Some(reason.clone());
+            requests.rejection_age = 0.0;
         }
     }
 }
@@ -119,6 +165,7 @@ pub fn update_players(
         (With<Player>, Without<map::Vertex>),
     >,
     vertex_lookup: Res<map::VertexClientServerLookup>,
+    lobby: Res<lobby::LobbyState>,
     mut init_player: EventWriter<PlayerSpawnEvent>,
     mut commands: Commands,
 ) {
@@ -127,13 +174,16 @@ pub fn update_players(
     if players.is_none() {
         return;
     }
+    let Some(room) = lobby.current_room else {
+        return;
+    };
 
     for play in players.unwrap().players.iter() {
-        let vert = vertex_lookup.0.get(&play.current_vertex);
+        let vert = vertex_lookup.0.get(&(room, play.current_vertex));
 
         let mut next_vert = None;
         if play.next_vertex.is_some() {
-            next_vert = vertex_lookup.0.get(&play.next_vertex.unwrap());
+            next_vert = vertex_lookup.0.get(&(room, play.next_vertex.unwrap()));
         }
 
         if vert.is_some() {
@@ -168,11 +218,13 @@ pub fn update_players(
 
             if !player_found {
                 init_player.send(PlayerSpawnEvent {
+                    room,
                     current_vertex: vert.copied(),
                     x: Some(play.x),
                     y: Some(play.y),
                     id: Some(play.id),
                     client_owner_id: play.client_owner_id,
+                    color: play.color,
                 });
                 info!("Created New Player");
             }
@@ -180,17 +232,18 @@ pub fn update_players(
             info!("Can't find vertex to spawn player")
         }
     }
-    // for (e, _pos, player) in players_query.iter() {
-    //     let mut player_found = false;
-    //     for play in players.unwrap().players.iter() {
-    //         if play.id == player.id {
-    //             player_found = true;
-    //         }
-    //     }
-
-    //     if !player_found {
-    //         commands.entity(e).despawn();
-    //         info!("Deleted Player")
-    //     }
-    // }
+    // Players that left the room are no longer part of the update
+    for (e, _pos, player) in players_query.iter() {
+        let mut player_found = false;
+        for play in players.unwrap().players.iter() {
+            if play.id == player.id {
+                player_found = true;
+            }
+        }
+
+        if !player_found {
+            commands.entity(e).despawn();
+            info!("Deleted Player")
+        }
+    }
 }
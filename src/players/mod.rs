@@ -1,6 +1,11 @@
-use crate::{map, protocol};
+use crate::{
+    map, protocol,
+    protocol::{PlayerColor, RoomId},
+    server::RegisterCommand,
+};
 use bevy::{prelude::*, sprite::Anchor};
 use bevy_quinnet::shared::ClientId;
+use std::collections::HashMap;
 
 mod client_players;
 mod server_players;
@@ -23,9 +28,20 @@ pub struct ServerPlayersPlugin;
 impl Plugin for ServerPlayersPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugin(PlayersPlugin)
+            .insert_resource(NextPlayerId::default())
+            .add_event::<PlayerDespawnEvent>()
+            .add_event::<PlayerRestoreEvent>()
+            .add_event::<PlayerOwnerChangeEvent>()
             .add_system(server_players::spawn_players)
+            .add_system(server_players::restore_players)
+            .add_system(server_players::change_player_owners)
+            .add_system(server_players::despawn_players)
             .add_system(server_players::send_game_state)
-            .add_system(server_players::handle_client_move_player);
+            .register_command(
+                protocol::CommandKind::Move,
+                server_players::validate_move,
+                server_players::handle_client_move_player,
+            );
     }
 }
 
@@ -34,13 +50,33 @@ pub struct ClientPlayersPlugin;
 impl Plugin for ClientPlayersPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugin(PlayersPlugin)
+            .insert_resource(ActionRequests::default())
             .add_system(client_players::spawn_players)
             .add_system(client_players::move_my_player)
+            .add_system(client_players::handle_action_replies)
             .add_system(client_players::update_players)
             .add_event::<protocol::ServerUpdatePlayerEvent>();
     }
 }
 
+/// Actions this client sent and is waiting to hear back about, and the last one refused.
+#[derive(Resource, Default)]
+pub struct ActionRequests {
+    last_id: u32,
+    /// The vertex selected for each request without a reply yet.
+    pub pending: HashMap<u32, Entity>,
+    pub rejection: Option<protocol::ActionRejection>,
+    /// Seconds since `rejection` came in.
+    pub rejection_age: f32,
+}
+
+impl ActionRequests {
+    pub fn next_id(&mut self) -> u32 {
+        self.last_id = self.last_id.wrapping_add(1);
+        self.last_id
+    }
+}
+
 #[derive(Resource)]
 pub struct PlayerTextures {
     player: Handle<Image>,
@@ -53,13 +89,44 @@ pub struct PlayerTextures {
 }
 
 pub struct PlayerSpawnEvent {
+    pub room: RoomId,
     pub current_vertex: Option<Entity>,
     pub x: Option<f32>,
     pub y: Option<f32>,
     pub id: Option<u32>,
     pub client_owner_id: ClientId,
+    pub color: PlayerColor,
+}
+
+/// Removes the player owned by a client, freeing the vertexes it held.
+pub struct PlayerDespawnEvent {
+    pub client_owner_id: ClientId,
+}
+
+/// Puts a saved player back where it was, wherever that is on the map.
+pub struct PlayerRestoreEvent {
+    pub room: RoomId,
+    pub id: u32,
+    pub client_owner_id: ClientId,
+    pub color: PlayerColor,
+    pub current_vertex: Entity,
+    /// Vertexes the player was still going to walk to, in order.
+    pub next_entity: Vec<Entity>,
+    pub x: f32,
+    pub y: f32,
+    pub rotation: i32,
+}
+
+/// Hands a player over to another client, like a restored one to whoever reclaimed its seat.
+pub struct PlayerOwnerChangeEvent {
+    pub from: ClientId,
+    pub to: ClientId,
 }
 
+/// Id of the next player the server spawns, shared with restored players so ids stay unique.
+#[derive(Resource, Default)]
+pub struct NextPlayerId(pub u32);
+
 #[derive(Clone, Component)]
 pub struct Player {
     pub id: u32,
@@ -163,21 +230,6 @@ fn move_players(
         } else {
             player.next_entity_id = None;
             player.state = States::Idle;
-            // if let Ok((_e, _vert_pos, adj, _vert)) = vertexes.get_mut(player.current_vertex) {
-            //     let mut rng = rand::thread_rng();
-            //     let y: f32 = rng.gen();
-
-            //     let index = (y * adj.vertex_list.len() as f32) as usize;
-
-            //     let next_vert = adj.vertex_list[index];
-
-            //     if let Ok((_e, _vert_pos, _adj, mut vert)) = vertexes.get_mut(next_vert) {
-            //         if !vert.filled {
-            //             player.next_entity.push(next_vert);
-            //             vert.filled = true;
-            //         }
-            //     }
-            // }
         }
     }
 }
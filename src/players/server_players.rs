@@ -1,75 +1,114 @@
+use std::collections::HashMap;
+
 use bevy::prelude::*;
 
 use super::*;
+use crate::{lobby, replay};
 use map;
+
+fn player_sprite(
+    player_textures: &PlayerTextures,
+    texture_atlases: &mut Assets<TextureAtlas>,
+    color: PlayerColor,
+    x: f32,
+    y: f32,
+) -> SpriteSheetBundle {
+    let texture_atlas = TextureAtlas::from_grid(
+        player_textures.player.clone(),
+        Vec2::new(player_textures.player_x, player_textures.player_y),
+        player_textures.player_col,
+        player_textures.player_row,
+        Some(Vec2::new(
+            player_textures.padding_x,
+            player_textures.padding_y,
+        )),
+        None,
+    );
+    SpriteSheetBundle {
+        texture_atlas: texture_atlases.add(texture_atlas),
+        transform: Transform::from_xyz(x, y, 100.0),
+        sprite: TextureAtlasSprite {
+            anchor: Anchor::Custom(Vec2 { x: 0.0, y: -0.3 }),
+            color: color.tint(),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
 pub fn spawn_players(
     mut commands: Commands,
     mut player_spawn: EventReader<PlayerSpawnEvent>,
     mut texture_atlases: ResMut<Assets<TextureAtlas>>,
     player_textures: Res<PlayerTextures>,
-    mut query: Query<(Entity, &mut Transform, &mut map::Vertex), With<map::VertexStart>>,
-    mut total_players: Local<u32>,
+    mut query: Query<(Entity, &mut Transform, &mut map::Vertex, &RoomId), With<map::VertexStart>>,
+    mut next_id: ResMut<NextPlayerId>,
 ) {
-    let mut start_spot: Vec<Entity> = Vec::new();
-    let mut x: Vec<f32> = Vec::new();
-    let mut y: Vec<f32> = Vec::new();
-    for (entity, pos, vertex) in query.iter() {
+    // Free start spots in id order, so who starts where doesn't depend on entity order
+    let mut start_spots: HashMap<RoomId, Vec<(u32, Entity)>> = HashMap::new();
+    for (entity, _pos, vertex, room) in query.iter() {
         if !vertex.filled && vertex.is_start {
-            start_spot.push(entity);
-            x.push(pos.translation.x);
-            y.push(pos.translation.y);
+            start_spots
+                .entry(*room)
+                .or_default()
+                .push((vertex.id, entity));
         }
     }
-    if start_spot.len() < player_spawn.len() {
-        panic!("Not enough room for players to join server");
+    for spots in start_spots.values_mut() {
+        spots.sort();
     }
-    for (i, _player) in player_spawn.iter().enumerate() {
-        let texture_atlas = TextureAtlas::from_grid(
-            player_textures.player.clone(),
-            Vec2::new(player_textures.player_x, player_textures.player_y),
-            player_textures.player_col,
-            player_textures.player_row,
-            Some(Vec2::new(
-                player_textures.padding_x,
-                player_textures.padding_y,
-            )),
-            None,
+    for spawn in player_spawn.iter() {
+        let spots = start_spots.entry(spawn.room).or_default();
+        // The lobby places players of a starting match itself
+        let chosen = spawn
+            .current_vertex
+            .and_then(|entity| spots.iter().position(|(_, spot)| *spot == entity));
+        let Some((_, start_spot)) = chosen
+            .or((!spots.is_empty()).then_some(0))
+            .map(|index| spots.remove(index))
+        else {
+            // The lobby keeps rooms from filling past their start vertexes, so this is a bug
+            warn!(
+                "No free start vertex for client {} in {:?}",
+                spawn.client_owner_id, spawn.room
+            );
+            continue;
+        };
+        debug!(
+            "Spawning client {} on {:?}",
+            spawn.client_owner_id, start_spot
         );
-        let texture_atlas_handle = texture_atlases.add(texture_atlas);
-
-        println!("{:?}", start_spot[i]);
-        if let Ok((entity, pos, mut vertex)) = query.get_mut(start_spot[i]) {
+        if let Ok((entity, pos, mut vertex, room)) = query.get_mut(start_spot) {
             vertex.filled = true;
 
-            let _entity = commands
-                .spawn(SpriteSheetBundle {
-                    texture_atlas: texture_atlas_handle,
-                    transform: Transform::from_xyz(pos.translation.x, pos.translation.y, 100.0),
-                    sprite: TextureAtlasSprite {
-                        anchor: Anchor::Custom(Vec2 { x: 0.0, y: -0.3 }),
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                })
+            commands
+                .spawn(player_sprite(
+                    &player_textures,
+                    &mut texture_atlases,
+                    spawn.color,
+                    pos.translation.x,
+                    pos.translation.y,
+                ))
+                .insert(*room)
+                .insert(spawn.color)
                 .insert(Player {
-                    id: *total_players,
+                    id: next_id.0,
                     current_vertex: entity,
                     current_vertex_id: vertex.id,
                     next_entity: Vec::new(),
                     next_entity_id: None,
                     animation_timer: 0.0,
                     roation_index: 0,
-                    client_owner_id: _player.client_owner_id,
+                    client_owner_id: spawn.client_owner_id,
                     state: super::States::Idle,
-                })
-                // .insert(
-                //     StateMachine::new(Idle)
-                //         .trans::<AnyState>(GoToTrigger, GoToSelection { speed: 65.0 })
-                //         .trans::<AnyState>(NotTrigger(GoToTrigger), Idle),
-                // )
-                .id();
-
-            *total_players += 1;
+                });
+            // .insert(
+            //     StateMachine::new(Idle)
+            //         .trans::<AnyState>(GoToTrigger, GoToSelection { speed: 65.0 })
+            //         .trans::<AnyState>(NotTrigger(GoToTrigger), Idle),
+            // )
+
+            next_id.0 += 1;
         } else {
             println!("Failed to spawn player on server")
         }
@@ -83,8 +122,9 @@ pub fn spawn_players(
 pub fn send_game_state(
     // mut client_event: EventReader<protocol::ClientEvent>,
     server: ResMut<bevy_quinnet::server::Server>,
-    players: Query<(Entity, &mut Transform, &mut Player), With<Player>>,
-    users: Res<protocol::Users>,
+    players: Query<(&Transform, &Player, &RoomId, &PlayerColor)>,
+    rooms: Res<lobby::Rooms>,
+    mut recorder: ResMut<replay::ReplayRecorder>,
     mut timer: Local<f32>,
     time: Res<Time>,
 ) {
@@ -95,71 +135,197 @@ pub fn send_game_state(
     } else {
         *timer -= 1.0 / 20.0;
 
-        let mut players_data = Vec::<protocol::Player>::new();
-
-        for (_e, pos, player) in players.iter() {
-            players_data.push(protocol::Player {
-                id: player.id,
-                x: pos.translation.x,
-                y: pos.translation.y,
-                rotation: player.roation_index,
-                current_vertex: player.current_vertex_id,
-                next_vertex: player.next_entity_id,
-                client_owner_id: player.client_owner_id,
-            })
+        let mut players_data = HashMap::<RoomId, Vec<protocol::Player>>::new();
+
+        for (pos, player, room, color) in players.iter() {
+            players_data
+                .entry(*room)
+                .or_default()
+                .push(protocol::Player {
+                    id: player.id,
+                    x: pos.translation.x,
+                    y: pos.translation.y,
+                    rotation: player.roation_index,
+                    current_vertex: player.current_vertex_id,
+                    next_vertex: player.next_entity_id,
+                    client_owner_id: player.client_owner_id,
+                    color: *color,
+                })
         }
-        // println!("{:?}", users.names.keys().into_iter());
-
-        if let Ok(_temp) = server.endpoint().send_group_message(
-            users.names.keys().into_iter(),
-            //ChannelId::Unreliable,
-            protocol::ServerMessage::UpdatePlayers {
-                players: players_data.clone(),
-            },
-        ) {
-            // info!("Sent Players")
-        } else {
-            info!("Failed to Update Players")
+
+        for (room, players_data) in players_data {
+            let Some(members) = rooms.members(room) else {
+                continue;
+            };
+            let message = protocol::ServerMessage::UpdatePlayers {
+                players: players_data,
+            };
+            recorder.record(room, &message);
+            if let Ok(_temp) = server.endpoint().send_group_message(
+                members, //ChannelId::Unreliable,
+                message,
+            ) {
+                // info!("Sent Players")
+            } else {
+                info!("Failed to Update Players")
+            }
         }
     }
 }
 
-pub(crate) fn handle_client_move_player(
-    mut client_event: EventReader<protocol::ClientEvent>,
-    mut players: Query<&mut Player, Without<map::Vertex>>,
-    vertexes: Query<
-        (
-            Entity,
-            &map::Vertex,
-            &map::EntityAdjacencies,
-            &map::MapClickable,
-        ),
-        (With<map::Vertex>, Without<Player>),
-    >,
-    vertex_lookup: Res<map::VertexClientServerLookup>,
+pub fn despawn_players(
+    mut commands: Commands,
+    mut player_despawn: EventReader<PlayerDespawnEvent>,
+    players: Query<(Entity, &Player)>,
+    mut vertexes: Query<&mut map::Vertex>,
 ) {
-    for event in client_event.iter() {
-        if event.name != protocol::ClientEvents::MOVE {
-            continue;
+    for despawn in player_despawn.iter() {
+        for (entity, player) in players.iter() {
+            if player.client_owner_id != despawn.client_owner_id {
+                continue;
+            }
+            for vertex in player.next_entity.iter().chain([&player.current_vertex]) {
+                if let Ok(mut vertex) = vertexes.get_mut(*vertex) {
+                    vertex.filled = false;
+                }
+            }
+            commands.entity(entity).despawn_recursive();
         }
+    }
+}
 
-        let target_vert = vertex_lookup.0.get(&event.type_id);
-
-        if target_vert.is_none() {
+/// Spawns saved players where they stood, rather than on a start vertex like `spawn_players`.
+pub fn restore_players(
+    mut commands: Commands,
+    mut player_restore: EventReader<PlayerRestoreEvent>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    player_textures: Res<PlayerTextures>,
+    mut vertexes: Query<&mut map::Vertex>,
+    mut next_id: ResMut<NextPlayerId>,
+) {
+    for restored in player_restore.iter() {
+        let Ok(mut vertex) = vertexes.get_mut(restored.current_vertex) else {
+            warn!("Restored player {} has no vertex to stand on", restored.id);
             continue;
-        }
+        };
+        vertex.filled = true;
 
+        commands
+            .spawn(player_sprite(
+                &player_textures,
+                &mut texture_atlases,
+                restored.color,
+                restored.x,
+                restored.y,
+            ))
+            .insert(restored.room)
+            .insert(restored.color)
+            .insert(Player {
+                id: restored.id,
+                current_vertex: restored.current_vertex,
+                current_vertex_id: vertex.id,
+                // `move_players` picks the walk back up from here
+                next_entity: restored.next_entity.clone(),
+                next_entity_id: None,
+                animation_timer: 0.0,
+                roation_index: restored.rotation,
+                client_owner_id: restored.client_owner_id,
+                state: super::States::Idle,
+            });
+        next_id.0 = next_id.0.max(restored.id + 1);
+    }
+}
+
+pub fn change_player_owners(
+    mut owner_changes: EventReader<PlayerOwnerChangeEvent>,
+    mut players: Query<&mut Player>,
+) {
+    for change in owner_changes.iter() {
         for mut player in players.iter_mut() {
-            if player.client_owner_id == event.client_id {
-                let vert = vertexes.get(*target_vert.unwrap()).unwrap();
-
-                if vert.2.vertex_list.contains(&player.current_vertex) && !vert.1.filled {
-                    if player.state == super::States::Idle {
-                        player.next_entity.push(vert.0);
-                        break;
-                    }
-                }
+            if player.client_owner_id == change.from {
+                player.client_owner_id = change.to;
             }
         }
     }
 }
+
+/// A move has to target a vertex of the room's map.
+pub(crate) fn validate_move(
+    command: &protocol::GameCommand,
+    graph: Option<&map::MapGraph>,
+) -> Result<(), protocol::ActionRejection> {
+    let protocol::GameCommand::Move { vertex } = command else {
+        return Err(protocol::ActionRejection::UnknownCommand);
+    };
+    match graph {
+        Some(graph) if graph.contains_vertex(*vertex) => Ok(()),
+        _ => Err(protocol::ActionRejection::UnknownTarget),
+    }
+}
+
+/// Moves players to the vertex their client asked for, and tells the client why not otherwise.
+pub(crate) fn handle_client_move_player(
+    mut commands: EventReader<protocol::CommandEvent>,
+    mut players: Query<&mut Player, Without<map::Vertex>>,
+    vertexes: Query<&map::Vertex, Without<Player>>,
+    vertex_lookup: Res<map::VertexClientServerLookup>,
+    graphs: Res<map::MapGraphs>,
+    mut results: EventWriter<protocol::ActionResultEvent>,
+) {
+    for event in commands.iter() {
+        // Other kinds are answered by their own handler
+        let protocol::GameCommand::Move { vertex } = event.command else {
+            continue;
+        };
+        let result = move_player(
+            event,
+            vertex,
+            &mut players,
+            &vertexes,
+            &vertex_lookup,
+            &graphs,
+        );
+        results.send(protocol::ActionResultEvent {
+            client_id: event.client_id,
+            request_id: event.request_id,
+            result,
+        });
+    }
+}
+
+fn move_player(
+    event: &protocol::CommandEvent,
+    target: u32,
+    players: &mut Query<&mut Player, Without<map::Vertex>>,
+    vertexes: &Query<&map::Vertex, Without<Player>>,
+    vertex_lookup: &map::VertexClientServerLookup,
+    graphs: &map::MapGraphs,
+) -> Result<(), protocol::ActionRejection> {
+    use protocol::ActionRejection;
+
+    let mut player = players
+        .iter_mut()
+        .find(|player| player.client_owner_id == event.client_id)
+        .ok_or(ActionRejection::NotPlaying)?;
+    let (Some(entity), Some(graph)) = (
+        vertex_lookup.0.get(&(event.room, target)),
+        graphs.0.get(&event.room),
+    ) else {
+        return Err(ActionRejection::UnknownTarget);
+    };
+    let vertex = vertexes
+        .get(*entity)
+        .map_err(|_| ActionRejection::UnknownTarget)?;
+
+    if !graph.neighbors(player.current_vertex_id).contains(&target) {
+        return Err(ActionRejection::NotAdjacent);
+    }
+    if vertex.filled {
+        return Err(ActionRejection::Filled);
+    }
+    if player.state != super::States::Idle || !player.next_entity.is_empty() {
+        return Err(ActionRejection::Busy);
+    }
+    player.next_entity.push(*entity);
+    Ok(())
+}
@@ -6,18 +6,80 @@ use serde::{Deserialize, Serialize};
 
 use crate::map;
 
+/// A command from a seated client or a bot, checked against the `CommandRegistry` before
+/// its handler sees it.
 pub struct ClientEvent {
-    pub name: ClientEvents,
-    pub map_type: u8,
-    pub type_id: u32,
+    /// Echoed back in the `ActionAccepted` or `ActionRejected` reply, bots use 0.
+    pub request_id: u32,
+    pub command: CommandEnvelope,
     pub client_id: ClientId,
+    pub room: RoomId,
 }
-#[derive(PartialEq, Debug, Clone, Deserialize, Serialize)]
-pub enum ClientEvents {
-    MOVE,
+
+/// A command that passed validation, for the handler registered for its kind.
+pub struct CommandEvent {
+    pub request_id: u32,
+    pub command: GameCommand,
+    pub client_id: ClientId,
+    pub room: RoomId,
+}
+
+/// What a client asks its player to do, with what the command needs to know.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameCommand {
+    Move {
+        vertex: u32,
+    },
+    /// Changes nothing, the reply only measures the round trip.
+    Ping {},
+}
+
+impl GameCommand {
+    pub fn kind(&self) -> CommandKind {
+        match self {
+            GameCommand::Move { .. } => CommandKind::Move,
+            GameCommand::Ping { .. } => CommandKind::Ping,
+        }
+    }
+}
+
+/// A `GameCommand` as it is sent. The server opens it itself, so a command it can't decode is
+/// answered with `ActionRejection::Malformed` instead of dropped along with its message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommandEnvelope(pub Vec<u8>);
+
+impl CommandEnvelope {
+    pub fn open(&self) -> Result<GameCommand, ActionRejection> {
+        bincode::deserialize(&self.0).map_err(|_| ActionRejection::Malformed)
+    }
+}
+
+impl From<GameCommand> for CommandEnvelope {
+    fn from(command: GameCommand) -> Self {
+        Self(bincode::serialize(&command).unwrap_or_default())
+    }
+}
+
+/// A `GameCommand` without its payload, what handlers are registered under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CommandKind {
+    /// Accepted once the player set off, refused with the reason otherwise.
+    Move,
+    /// Accepted right away.
+    Ping,
 }
+
+/// The command clicking on the map sends.
 #[derive(Component)]
-pub struct CurrentClientEventTrigger(pub ClientEvents);
+pub struct CurrentClientEventTrigger(pub CommandKind);
+
+/// The outcome of a client action, sent back to the client as `ActionAccepted` or
+/// `ActionRejected`.
+pub struct ActionResultEvent {
+    pub client_id: ClientId,
+    pub request_id: u32,
+    pub result: Result<(), ActionRejection>,
+}
 
 pub struct ServerUpdateMapEvent {
     pub vertexes: Vec<VertexUpdate>,
@@ -29,6 +91,9 @@ pub struct ServerUpdatePlayerEvent {
     pub players: Vec<Player>,
 }
 
+/// A message from the server, or from a replay playing back a recorded one.
+pub struct ServerMessageEvent(pub ServerMessage);
+
 #[derive(bevy::prelude::Resource, Debug, Clone, Default)]
 pub struct Users {
     pub self_id: ClientId,
@@ -38,37 +103,310 @@ pub struct Users {
 #[derive(bevy::prelude::Resource, Debug, Clone, Default)]
 pub struct IsServer(pub bool);
 
+/// Map and player ids are only unique inside a room, so map entities and players carry one.
+#[derive(
+    Component,
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+)]
+pub struct RoomId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RoomPhase {
+    /// Players pick colors and ready up, there is no map yet.
+    Waiting,
+    Loading,
+    Playing,
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PlayerColor {
+    Red,
+    Blue,
+    Green,
+    Yellow,
+    Purple,
+    Orange,
+}
+
+impl PlayerColor {
+    pub const ALL: [PlayerColor; 6] = [
+        PlayerColor::Red,
+        PlayerColor::Blue,
+        PlayerColor::Green,
+        PlayerColor::Yellow,
+        PlayerColor::Purple,
+        PlayerColor::Orange,
+    ];
+
+    /// The tint applied to the player's sprite.
+    pub fn tint(&self) -> bevy::prelude::Color {
+        use bevy::prelude::Color;
+        match self {
+            PlayerColor::Red => Color::rgb(1.0, 0.5, 0.5),
+            PlayerColor::Blue => Color::rgb(0.5, 0.6, 1.0),
+            PlayerColor::Green => Color::rgb(0.5, 1.0, 0.5),
+            PlayerColor::Yellow => Color::rgb(1.0, 1.0, 0.5),
+            PlayerColor::Purple => Color::rgb(0.8, 0.5, 1.0),
+            PlayerColor::Orange => Color::rgb(1.0, 0.7, 0.4),
+        }
+    }
+
+    pub fn next(&self) -> PlayerColor {
+        let index = PlayerColor::ALL.iter().position(|c| c == self).unwrap();
+        PlayerColor::ALL[(index + 1) % PlayerColor::ALL.len()]
+    }
+}
+
+/// Why the server did not let a client into a room.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JoinRejection {
+    UnknownRoom,
+    /// Every start vertex of the room's level is taken.
+    RoomFull {
+        max_players: usize,
+    },
+}
+
+impl std::fmt::Display for JoinRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JoinRejection::UnknownRoom => write!(f, "That room no longer exists"),
+            JoinRejection::RoomFull { max_players } => {
+                write!(f, "The room is full ({} players max)", max_players)
+            }
+        }
+    }
+}
+
+/// ASCII letters, digits, `_`, `-` and `.`; no spaces, so a name is one word in `/w <name>`,
+/// and no look-alikes from other scripts that would slip past the reserved and taken checks.
+pub fn allowed_name_character(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')
+}
+
+/// Why the server did not take the name a client joined with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NameRejection {
+    TooShort {
+        min: usize,
+    },
+    TooLong {
+        max: usize,
+    },
+    InvalidCharacters,
+    Reserved,
+    /// Someone connected has it, maybe with another case.
+    Taken,
+}
+
+impl std::fmt::Display for NameRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NameRejection::TooShort { min } => write!(f, "Names need at least {} characters", min),
+            NameRejection::TooLong { max } => write!(f, "Names can't be over {} characters", max),
+            NameRejection::InvalidCharacters => {
+                write!(f, "Names can only use ASCII letters, digits, _, - and .")
+            }
+            NameRejection::Reserved => write!(f, "That name is reserved"),
+            NameRejection::Taken => write!(f, "Someone already has that name"),
+        }
+    }
+}
+
+/// Why the server asked for the password again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PasswordRejection {
+    Required,
+    Wrong {
+        attempts_left: u32,
+    },
+    /// The client is disconnected.
+    TooManyAttempts,
+    /// Too many wrong passwords from everyone lately, each wrong one disconnects for a while.
+    Locked,
+}
+
+impl std::fmt::Display for PasswordRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PasswordRejection::Required => write!(f, "This server needs a password"),
+            PasswordRejection::Wrong { attempts_left } => {
+                write!(f, "Wrong password, {} attempts left", attempts_left)
+            }
+            PasswordRejection::TooManyAttempts => write!(f, "Too many wrong passwords"),
+            PasswordRejection::Locked => write!(f, "Too many wrong passwords, try again later"),
+        }
+    }
+}
+
+/// Why the server refused a client action.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActionRejection {
+    /// The client has no player, like a spectator or someone still in the lobby.
+    NotPlaying,
+    /// The target is not part of the room's map.
+    UnknownTarget,
+    NotAdjacent,
+    /// Another player stands on the target.
+    Filled,
+    /// The player is still moving.
+    Busy,
+    /// The server has no handler for this kind of command.
+    UnknownCommand,
+    /// The command didn't decode, the client and server disagree on the protocol.
+    Malformed,
+    /// The client sent commands too fast, this one was dropped.
+    RateLimited,
+}
+
+impl std::fmt::Display for ActionRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ActionRejection::NotPlaying => write!(f, "You don't have a player in this room"),
+            ActionRejection::UnknownTarget => write!(f, "That is not on the map"),
+            ActionRejection::NotAdjacent => write!(f, "You can only move to a neighboring vertex"),
+            ActionRejection::Filled => write!(f, "Someone is already standing there"),
+            ActionRejection::Busy => write!(f, "Wait until your move is done"),
+            ActionRejection::UnknownCommand => write!(f, "The server doesn't know that command"),
+            ActionRejection::Malformed => write!(f, "The server couldn't read that command"),
+            ActionRejection::RateLimited => write!(f, "Slow down, that was too fast"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeatInfo {
+    pub client_id: ClientId,
+    pub color: PlayerColor,
+    pub ready: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomInfo {
+    pub id: RoomId,
+    pub name: String,
+    pub level: String,
+    /// The seed of a generated level, which generates the same map again.
+    pub seed: Option<u64>,
+    pub players: usize,
+    pub max_players: usize,
+    pub phase: RoomPhase,
+    /// The first seat, who picks the level and starts the match.
+    pub host: Option<ClientId>,
+    pub seats: Vec<SeatInfo>,
+    pub spectators: Vec<ClientId>,
+}
+
 // Messages from clients
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ClientMessage {
     Join {
         name: String,
+        /// Only checked on servers with a password, and never logged.
+        password: Option<String>,
     },
     Disconnect {},
     ChatMessage {
         message: String,
     },
-    SendEvent {
-        name: ClientEvents,
-        map_type: u8,
-        type_id: u32,
+    /// A chat message only `to` gets, it comes back to the sender as a `Whisper` too.
+    Whisper {
+        to: ClientId,
+        message: String,
+    },
+    /// Answered with `ActionAccepted` or `ActionRejected` carrying the same `request_id`.
+    SendCommand {
+        request_id: u32,
+        command: CommandEnvelope,
+    },
+    ListRooms {},
+    CreateRoom {
+        name: String,
+    },
+    JoinRoom {
+        room: RoomId,
+    },
+    LeaveRoom {},
+    /// Watch a room without a player, this doesn't count against its max players.
+    SpectateRoom {
+        room: RoomId,
+    },
+    ChooseColor {
+        color: PlayerColor,
+    },
+    SetReady {
+        ready: bool,
     },
+    /// Host only, while the room is waiting.
+    SetLevel {
+        level: String,
+    },
+    /// Host only, once every seat is ready.
+    StartMatch {},
+}
+
+impl ClientMessage {
+    /// Name of the variant, what rate limits are set for.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ClientMessage::Join { .. } => "Join",
+            ClientMessage::Disconnect { .. } => "Disconnect",
+            ClientMessage::ChatMessage { .. } => "ChatMessage",
+            ClientMessage::Whisper { .. } => "Whisper",
+            ClientMessage::SendCommand { .. } => "SendCommand",
+            ClientMessage::ListRooms { .. } => "ListRooms",
+            ClientMessage::CreateRoom { .. } => "CreateRoom",
+            ClientMessage::JoinRoom { .. } => "JoinRoom",
+            ClientMessage::LeaveRoom { .. } => "LeaveRoom",
+            ClientMessage::SpectateRoom { .. } => "SpectateRoom",
+            ClientMessage::ChooseColor { .. } => "ChooseColor",
+            ClientMessage::SetReady { .. } => "SetReady",
+            ClientMessage::SetLevel { .. } => "SetLevel",
+            ClientMessage::StartMatch { .. } => "StartMatch",
+        }
+    }
 }
 
 // Messages from the server
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ServerMessage {
+    /// Told to the lobby, where new users start.
     ClientConnected {
         client_id: ClientId,
         username: String,
     },
+    /// Told to the room the user was in, or to the lobby.
     ClientDisconnected {
         client_id: ClientId,
     },
+    /// Told to everyone so names resolve across rooms, `None` once the user is gone.
+    UserListUpdate {
+        client_id: ClientId,
+        username: Option<String>,
+    },
     ChatMessage {
         client_id: ClientId,
         message: String,
     },
+    Whisper {
+        from: ClientId,
+        to: ClientId,
+        message: String,
+    },
+    /// From the server's operators, like announcements and the answers to admin commands.
+    ServerNotice {
+        message: String,
+    },
     InitClient {
         client_id: ClientId,
         usernames: HashMap<ClientId, String>,
@@ -87,7 +425,64 @@ pub enum ServerMessage {
         edges: Vec<EdgeUpdate>,
         materials: Vec<MaterialUpdate>,
     },
+    RoomList {
+        rooms: Vec<RoomInfo>,
+        /// Levels a host can pick from.
+        levels: Vec<String>,
+    },
+    RoomJoined {
+        room: RoomInfo,
+    },
+    RoomLeft {},
+    JoinRejected {
+        room: RoomId,
+        reason: JoinRejection,
+    },
+    /// The client stays connected and can `Join` again with another name.
+    NameRejected {
+        name: String,
+        reason: NameRejection,
+    },
+    /// Like `NameRejected`, `name` is the one the client tried to join with.
+    PasswordRejected {
+        name: String,
+        reason: PasswordRejection,
+    },
+    ActionAccepted {
+        request_id: u32,
+    },
+    ActionRejected {
+        request_id: u32,
+        reason: ActionRejection,
+    },
 }
+
+impl ServerMessage {
+    /// Name of the variant, used to label per message statistics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ServerMessage::ClientConnected { .. } => "ClientConnected",
+            ServerMessage::ClientDisconnected { .. } => "ClientDisconnected",
+            ServerMessage::UserListUpdate { .. } => "UserListUpdate",
+            ServerMessage::ChatMessage { .. } => "ChatMessage",
+            ServerMessage::Whisper { .. } => "Whisper",
+            ServerMessage::ServerNotice { .. } => "ServerNotice",
+            ServerMessage::InitClient { .. } => "InitClient",
+            ServerMessage::InitMap { .. } => "InitMap",
+            ServerMessage::UpdatePlayers { .. } => "UpdatePlayers",
+            ServerMessage::UpdateMap { .. } => "UpdateMap",
+            ServerMessage::RoomList { .. } => "RoomList",
+            ServerMessage::RoomJoined { .. } => "RoomJoined",
+            ServerMessage::RoomLeft { .. } => "RoomLeft",
+            ServerMessage::JoinRejected { .. } => "JoinRejected",
+            ServerMessage::NameRejected { .. } => "NameRejected",
+            ServerMessage::PasswordRejected { .. } => "PasswordRejected",
+            ServerMessage::ActionAccepted { .. } => "ActionAccepted",
+            ServerMessage::ActionRejected { .. } => "ActionRejected",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Vertex {
     pub id: u32,
@@ -141,6 +536,7 @@ pub struct Player {
     pub current_vertex: u32,
     pub next_vertex: Option<u32>,
     pub client_owner_id: ClientId,
+    pub color: PlayerColor,
 }
 // #[derive(Debug, Clone, Serialize, Deserialize)]
 // pub struct Adjacencies {
@@ -0,0 +1,146 @@
+use bevy::{app::AppExit, prelude::*};
+
+use super::*;
+use crate::{client::ClientConfig, lobby::LobbyState, protocol};
+
+const SEEK_SECONDS: f32 = 10.0;
+const MIN_SPEED: f32 = 0.25;
+const MAX_SPEED: f32 = 8.0;
+
+/// A loaded replay and how far into it the client is.
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    pub header: ReplayHeader,
+    pub frames: Vec<ReplayFrame>,
+    pub next_frame: usize,
+    pub time: f32,
+    pub speed: f32,
+    pub paused: bool,
+}
+
+/// What the viewer can do to a replay, from the keyboard or the replay panel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayControl {
+    TogglePause,
+    /// Jumps by that many seconds, backwards when negative.
+    Seek(f32),
+    Faster,
+    Slower,
+}
+
+impl ReplayPlayback {
+    pub fn new(header: ReplayHeader, frames: Vec<ReplayFrame>) -> Self {
+        Self {
+            header,
+            frames,
+            next_frame: 0,
+            time: 0.0,
+            speed: 1.0,
+            paused: false,
+        }
+    }
+
+    /// Seconds from the start of the recording to its last message.
+    pub fn duration(&self) -> f32 {
+        self.frames.last().map(|f| f.time).unwrap_or_default()
+    }
+
+    pub fn apply(&mut self, control: ReplayControl) {
+        match control {
+            ReplayControl::TogglePause => self.paused = !self.paused,
+            ReplayControl::Seek(delta) => {
+                let target = (self.time + delta).clamp(0.0, self.duration());
+                // Messages can't be undone, so going back replays everything from the start,
+                // where the map replaces the one shown
+                if target < self.time {
+                    self.next_frame = 0;
+                }
+                self.time = target;
+            }
+            ReplayControl::Faster => self.speed = (self.speed * 2.0).min(MAX_SPEED),
+            ReplayControl::Slower => self.speed = (self.speed / 2.0).max(MIN_SPEED),
+        }
+    }
+}
+
+/// Reads the replay file and sets the client up as a spectator of the recorded room.
+pub fn load_replay(
+    mut commands: Commands,
+    config: Res<ClientConfig>,
+    mut users: ResMut<protocol::Users>,
+    mut lobby: ResMut<LobbyState>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let Some(path) = &config.replay else {
+        return;
+    };
+    let (header, frames) = match read_replay(path) {
+        Ok(replay) => replay,
+        Err(e) => {
+            error!("{}", e);
+            exit.send(AppExit);
+            return;
+        }
+    };
+    info!(
+        "Playing {} on {} ({} messages)",
+        header.room.name,
+        header.room.level,
+        frames.len()
+    );
+
+    users.self_id = REPLAY_VIEWER_ID;
+    users.names = header.usernames.clone();
+    lobby.current_room = Some(header.room.id);
+    lobby.rooms = vec![header.room.clone()];
+    commands.insert_resource(ReplayPlayback::new(header, frames));
+}
+
+pub fn replay_keys(keys: Res<Input<KeyCode>>, playback: Option<ResMut<ReplayPlayback>>) {
+    let Some(mut playback) = playback else {
+        return;
+    };
+    let bindings = [
+        (KeyCode::Space, ReplayControl::TogglePause),
+        (KeyCode::Left, ReplayControl::Seek(-SEEK_SECONDS)),
+        (KeyCode::Right, ReplayControl::Seek(SEEK_SECONDS)),
+        (KeyCode::Up, ReplayControl::Faster),
+        (KeyCode::Down, ReplayControl::Slower),
+    ];
+    for (key, control) in bindings {
+        if keys.just_pressed(key) {
+            playback.apply(control);
+        }
+    }
+}
+
+/// Hands the recorded messages to the client as if the server had just sent them.
+pub fn play_replay(
+    playback: Option<ResMut<ReplayPlayback>>,
+    time: Res<Time>,
+    mut messages: EventWriter<protocol::ServerMessageEvent>,
+) {
+    let Some(mut playback) = playback else {
+        return;
+    };
+
+    if !playback.paused {
+        playback.time =
+            (playback.time + time.delta_seconds() * playback.speed).min(playback.duration());
+    }
+
+    while let Some(frame) = playback.frames.get(playback.next_frame) {
+        if frame.time > playback.time {
+            break;
+        }
+        let message = frame.message.clone();
+        playback.next_frame += 1;
+
+        // The map has to be spawned before anything standing on it
+        let init_map = matches!(message, protocol::ServerMessage::InitMap { .. });
+        messages.send(protocol::ServerMessageEvent(message));
+        if init_map {
+            break;
+        }
+    }
+}
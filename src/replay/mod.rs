@@ -0,0 +1,90 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, ErrorKind},
+    path::Path,
+};
+
+use bevy::prelude::*;
+use bevy_quinnet::shared::ClientId;
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::{RoomInfo, ServerMessage};
+
+mod client_replay;
+mod server_replay;
+
+pub use client_replay::{ReplayControl, ReplayPlayback};
+pub use server_replay::ReplayRecorder;
+
+/// Bumped whenever the file layout or a recorded message changes.
+pub const REPLAY_VERSION: u32 = 1;
+
+/// The id a replay viewer takes, so it never owns one of the recorded players.
+pub const REPLAY_VIEWER_ID: ClientId = ClientId::MAX / 2;
+
+pub struct ServerReplayPlugin;
+
+impl Plugin for ServerReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ReplayRecorder::default())
+            .add_system(server_replay::count_ticks)
+            .add_system(server_replay::start_recordings)
+            .add_system(server_replay::stop_recordings);
+    }
+}
+
+pub struct ClientReplayPlugin;
+
+impl Plugin for ClientReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(client_replay::load_replay)
+            .add_system(client_replay::replay_keys)
+            .add_system(client_replay::play_replay);
+    }
+}
+
+/// Written once at the start of a replay file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayHeader {
+    pub version: u32,
+    pub room: RoomInfo,
+    pub usernames: HashMap<ClientId, String>,
+}
+
+/// One message the room's members received, followed by the next until the end of the file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayFrame {
+    /// Server update the message was sent in, counted from server start.
+    pub tick: u64,
+    /// Seconds since the recording started.
+    pub time: f32,
+    pub message: ServerMessage,
+}
+
+pub fn read_replay(path: &Path) -> Result<(ReplayHeader, Vec<ReplayFrame>), String> {
+    let file = File::open(path).map_err(|e| format!("Could not open {:?}: {}", path, e))?;
+    let mut reader = BufReader::new(file);
+
+    let header: ReplayHeader = bincode::deserialize_from(&mut reader)
+        .map_err(|e| format!("Could not read the header of {:?}: {}", path, e))?;
+    if header.version != REPLAY_VERSION {
+        return Err(format!(
+            "{:?} is a version {} replay, this build plays version {}",
+            path, header.version, REPLAY_VERSION
+        ));
+    }
+
+    let mut frames = Vec::new();
+    loop {
+        match bincode::deserialize_from::<_, ReplayFrame>(&mut reader) {
+            Ok(frame) => frames.push(frame),
+            Err(e) => match *e {
+                // A file cut off by a server stop still plays up to there
+                bincode::ErrorKind::Io(ref io) if io.kind() == ErrorKind::UnexpectedEof => break,
+                _ => return Err(format!("Could not read {:?}: {}", path, e)),
+            },
+        }
+    }
+    Ok((header, frames))
+}
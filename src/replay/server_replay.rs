@@ -0,0 +1,136 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::prelude::*;
+
+use super::*;
+use crate::{
+    lobby::{self, RoomStartedEvent},
+    map::{self, server_map::MapSnapshot},
+    protocol::{self, RoomId},
+    server::ServerConfig,
+};
+
+struct Recording {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    started: f64,
+}
+
+/// Open replay files, one per room with a match going on.
+#[derive(Resource, Default)]
+pub struct ReplayRecorder {
+    tick: u64,
+    now: f64,
+    recordings: HashMap<RoomId, Recording>,
+}
+
+impl ReplayRecorder {
+    /// Appends a message the room's members were sent, if the room is being recorded.
+    pub fn record(&mut self, room: RoomId, message: &ServerMessage) {
+        let Some(recording) = self.recordings.get_mut(&room) else {
+            return;
+        };
+        let frame = ReplayFrame {
+            tick: self.tick,
+            time: (self.now - recording.started) as f32,
+            message: message.clone(),
+        };
+        let written = bincode::serialize_into(&mut recording.writer, &frame)
+            .map_err(|e| e.to_string())
+            .and_then(|_| recording.writer.flush().map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            warn!("Stopped recording {:?}: {}", recording.path, e);
+            self.recordings.remove(&room);
+        }
+    }
+
+    /// Appends a message every client was sent to all recordings.
+    pub fn record_all(&mut self, message: &ServerMessage) {
+        let rooms: Vec<RoomId> = self.recordings.keys().copied().collect();
+        for room in rooms {
+            self.record(room, message);
+        }
+    }
+
+    fn start(&mut self, path: PathBuf, header: &ReplayHeader) -> Result<Recording, String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let file = File::create(&path).map_err(|e| e.to_string())?;
+        let mut writer = BufWriter::new(file);
+        bincode::serialize_into(&mut writer, header).map_err(|e| e.to_string())?;
+        Ok(Recording {
+            path,
+            writer,
+            started: self.now,
+        })
+    }
+}
+
+pub fn count_ticks(mut recorder: ResMut<ReplayRecorder>, time: Res<Time>) {
+    recorder.tick += 1;
+    recorder.now = time.elapsed_seconds_f64();
+}
+
+/// Opens a replay file for every room whose match just started, beginning with its map.
+pub fn start_recordings(
+    mut room_started: EventReader<RoomStartedEvent>,
+    mut recorder: ResMut<ReplayRecorder>,
+    config: Res<ServerConfig>,
+    rooms: Res<lobby::Rooms>,
+    users: Res<protocol::Users>,
+    map: MapSnapshot,
+) {
+    for RoomStartedEvent { room } in room_started.iter() {
+        if !config.replays.record {
+            continue;
+        }
+        let Some(started) = rooms.rooms.get(room) else {
+            continue;
+        };
+
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let name: String = started
+            .name
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '_' })
+            .collect();
+        let path = PathBuf::from(&config.replays.dir)
+            .join(format!("{}-{}-{}.replay", seconds, room.0, name));
+        let header = ReplayHeader {
+            version: REPLAY_VERSION,
+            room: started.info(),
+            usernames: users.names.clone(),
+        };
+
+        match recorder.start(path.clone(), &header) {
+            Ok(recording) => {
+                info!("Recording {} to {:?}", started.name, path);
+                recorder.recordings.insert(*room, recording);
+                recorder.record(*room, &map.init_map(*room));
+            }
+            Err(e) => warn!("Could not record {} to {:?}: {}", started.name, path, e),
+        }
+    }
+}
+
+/// Closes the replay file of rooms whose map went away.
+pub fn stop_recordings(
+    mut despawn_room: EventReader<map::DespawnRoomEvent>,
+    mut recorder: ResMut<ReplayRecorder>,
+) {
+    for map::DespawnRoomEvent(room) in despawn_room.iter() {
+        if let Some(recording) = recorder.recordings.remove(room) {
+            info!("Finished recording {:?}", recording.path);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::{fs, path::Path};
+
+use bevy::prelude::*;
+use bevy_quinnet::shared::ClientId;
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::{self, PlayerColor, RoomId};
+
+mod server_save;
+
+pub use server_save::PendingRestore;
+
+/// Bumped whenever the layout of a save file changes.
+pub const SAVE_VERSION: u32 = 1;
+
+/// Restored players are owned by ids counting up from here until their seat is reclaimed.
+pub const RESTORED_CLIENT_ID_START: ClientId = ClientId::MAX / 4;
+
+pub struct ServerSavePlugin;
+
+impl Plugin for ServerSavePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PendingRestore::default())
+            .add_event::<SaveGameEvent>()
+            .add_event::<LoadGameEvent>()
+            .add_startup_system(server_save::load_on_start)
+            .add_system(server_save::autosave)
+            .add_system(server_save::save_game)
+            .add_system(server_save::load_game)
+            .add_system(server_save::restore_game);
+    }
+}
+
+/// Writes every room to a save file.
+pub struct SaveGameEvent {
+    pub path: String,
+}
+
+/// Replaces every room with the ones of a save file, members go back to the lobby
+/// and get their seats again as soon as the match is restored.
+pub struct LoadGameEvent {
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveFile {
+    pub version: u32,
+    pub next_room_id: u32,
+    pub rooms: Vec<SavedRoom>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedRoom {
+    pub id: RoomId,
+    pub name: String,
+    pub level: String,
+    pub max_players: usize,
+    pub reserved: usize,
+    pub persistent: bool,
+    pub drop_in: bool,
+    pub seats: Vec<SavedSeat>,
+    /// Only rooms with a match going on have one, the others come back waiting.
+    pub map: Option<SavedMap>,
+    /// Players of the seats, bots join again like after a fresh start.
+    pub players: Vec<SavedPlayer>,
+}
+
+/// Seats are kept by name, since clients get new ids when they connect again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedSeat {
+    pub username: String,
+    pub color: PlayerColor,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedMap {
+    pub vertexes: Vec<protocol::Vertex>,
+    pub edges: Vec<protocol::Edge>,
+    pub materials: Vec<protocol::Material>,
+    pub filled: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedPlayer {
+    pub id: u32,
+    pub username: String,
+    pub color: PlayerColor,
+    pub current_vertex: u32,
+    /// Vertexes the player was still going to walk to, in order.
+    pub next_vertexes: Vec<u32>,
+    pub x: f32,
+    pub y: f32,
+    pub rotation: i32,
+}
+
+pub fn write_save(path: &Path, save: &SaveFile) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Could not create {:?}: {}", dir, e))?;
+    }
+    let text = serde_json::to_string_pretty(save).map_err(|e| e.to_string())?;
+    fs::write(path, text).map_err(|e| format!("Could not write {:?}: {}", path, e))
+}
+
+#[derive(Deserialize)]
+struct SaveVersion {
+    version: u32,
+}
+
+pub fn read_save(path: &Path) -> Result<SaveFile, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Could not read {:?}: {}", path, e))?;
+    // Checked first, a save of another version may not parse at all
+    let SaveVersion { version } =
+        serde_json::from_str(&text).map_err(|e| format!("Could not parse {:?}: {}", path, e))?;
+    if version != SAVE_VERSION {
+        return Err(format!(
+            "{:?} is a version {} save, this build loads version {}",
+            path, version, SAVE_VERSION
+        ));
+    }
+    serde_json::from_str(&text).map_err(|e| format!("Could not parse {:?}: {}", path, e))
+}
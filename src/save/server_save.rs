@@ -0,0 +1,398 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_quinnet::{server::Server, shared::ClientId};
+
+use super::*;
+use crate::{
+    lobby::{LobbyRequest, LobbyRequestEvent, Room, RoomStartedEvent, Rooms, VacantSeat},
+    map::{self, server_map::MapSnapshot, LevelData},
+    players::{self, Player},
+    protocol::{RoomPhase, ServerMessage},
+    server::ServerConfig,
+};
+
+/// Updates from a load to spawning the saved rooms. The old rooms are despawned an update
+/// in, so maps that were still spawning when the load came are gone too before ids get reused.
+const RESTORE_FRAMES: u8 = 4;
+
+enum RestoreStep {
+    Despawning {
+        old_rooms: Vec<RoomId>,
+        frames: u8,
+    },
+    /// The saved maps are spawning, players are placed once their vertexes exist.
+    Placing {
+        placeholders: HashMap<(RoomId, String), ClientId>,
+    },
+}
+
+/// A save being loaded, which takes a few updates.
+#[derive(Resource, Default)]
+pub struct PendingRestore(Option<(SaveFile, RestoreStep)>);
+
+impl PendingRestore {
+    pub fn is_restoring(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+type SavablePlayers<'w, 's> = Query<
+    'w,
+    's,
+    (
+        &'static Player,
+        &'static Transform,
+        &'static RoomId,
+        &'static PlayerColor,
+    ),
+>;
+
+pub fn load_on_start(config: Res<ServerConfig>, mut load: EventWriter<LoadGameEvent>) {
+    if let Some(path) = &config.saves.load {
+        load.send(LoadGameEvent { path: path.clone() });
+    }
+}
+
+pub fn autosave(
+    config: Res<ServerConfig>,
+    time: Res<Time>,
+    mut timer: Local<f32>,
+    mut save: EventWriter<SaveGameEvent>,
+) {
+    if config.saves.autosave_interval <= 0.0 {
+        return;
+    }
+    *timer += time.delta_seconds();
+    if *timer < config.saves.autosave_interval {
+        return;
+    }
+    *timer = 0.0;
+    save.send(SaveGameEvent {
+        path: config.saves.path.clone(),
+    });
+}
+
+pub fn save_game(
+    mut save_events: EventReader<SaveGameEvent>,
+    rooms: Res<Rooms>,
+    users: Res<protocol::Users>,
+    snapshot: MapSnapshot,
+    players: SavablePlayers,
+    vertexes: Query<&map::Vertex>,
+) {
+    for SaveGameEvent { path } in save_events.iter() {
+        let save = SaveFile {
+            version: SAVE_VERSION,
+            next_room_id: rooms.next_id(),
+            rooms: rooms
+                .rooms
+                .values()
+                .filter_map(|room| save_room(room, &users, &snapshot, &players, &vertexes))
+                .collect(),
+        };
+        match write_save(Path::new(path), &save) {
+            Ok(()) => info!("Saved {} rooms to {}", save.rooms.len(), path),
+            Err(e) => warn!("Failed to save the game: {}", e),
+        }
+    }
+}
+
+fn save_room(
+    room: &Room,
+    users: &protocol::Users,
+    snapshot: &MapSnapshot,
+    players: &SavablePlayers,
+    vertexes: &Query<&map::Vertex>,
+) -> Option<SavedRoom> {
+    let playing = room.phase == RoomPhase::Playing;
+    // Other rooms without a match would close anyway once their members are gone
+    if !playing && !room.persistent {
+        return None;
+    }
+
+    let mut seats = Vec::new();
+    let mut usernames: HashMap<ClientId, String> = HashMap::new();
+    if playing {
+        for seat in room.seats.iter() {
+            if let Some(username) = users.names.get(&seat.client_id) {
+                seats.push(SavedSeat {
+                    username: username.clone(),
+                    color: seat.color,
+                });
+                usernames.insert(seat.client_id, username.clone());
+            }
+        }
+        for seat in room.vacant.iter() {
+            seats.push(SavedSeat {
+                username: seat.username.clone(),
+                color: seat.color,
+            });
+            usernames.insert(seat.placeholder, seat.username.clone());
+        }
+    }
+
+    let mut saved_players = Vec::new();
+    let mut unsaved_vertexes = HashSet::new();
+    for (player, pos, player_room, color) in players.iter() {
+        if *player_room != room.id {
+            continue;
+        }
+        let next_vertexes = player
+            .next_entity
+            .iter()
+            .filter_map(|entity| vertexes.get(*entity).ok())
+            .map(|vertex| vertex.id);
+        let Some(username) = usernames.get(&player.client_owner_id) else {
+            unsaved_vertexes.extend(next_vertexes.chain([player.current_vertex_id]));
+            continue;
+        };
+        saved_players.push(SavedPlayer {
+            id: player.id,
+            username: username.clone(),
+            color: *color,
+            current_vertex: player.current_vertex_id,
+            next_vertexes: next_vertexes.collect(),
+            x: pos.translation.x,
+            y: pos.translation.y,
+            rotation: player.roation_index,
+        });
+    }
+
+    let map = playing.then(|| {
+        let (vertexes, edges, materials) = snapshot.objects(room.id);
+        SavedMap {
+            vertexes,
+            edges,
+            materials,
+            // Bots come back elsewhere, so what they stood on is free again
+            filled: snapshot
+                .filled_vertexes(room.id)
+                .into_iter()
+                .filter(|id| !unsaved_vertexes.contains(id))
+                .collect(),
+        }
+    });
+
+    Some(SavedRoom {
+        id: room.id,
+        name: room.name.clone(),
+        level: room.level.clone(),
+        max_players: room.max_players,
+        reserved: room.reserved,
+        persistent: room.persistent,
+        drop_in: room.drop_in,
+        seats,
+        map,
+        players: saved_players,
+    })
+}
+
+/// Sends everyone back to the lobby and starts replacing the rooms with the saved ones.
+pub fn load_game(
+    mut load_events: EventReader<LoadGameEvent>,
+    mut rooms: ResMut<Rooms>,
+    mut server: ResMut<Server>,
+    mut pending: ResMut<PendingRestore>,
+) {
+    let Some(LoadGameEvent { path }) = load_events.iter().last() else {
+        return;
+    };
+    let save = match read_save(Path::new(path)) {
+        Ok(save) => save,
+        Err(e) => {
+            warn!("Failed to load the game: {}", e);
+            return;
+        }
+    };
+    info!("Loading {} rooms from {}", save.rooms.len(), path);
+
+    let endpoint = server.endpoint_mut();
+    let old_rooms: Vec<RoomId> = rooms.rooms.keys().copied().collect();
+    for room in old_rooms.iter() {
+        if let Some(members) = rooms.members(*room) {
+            endpoint.try_send_group_message(members, ServerMessage::RoomLeft {});
+        }
+    }
+    rooms.restore(Vec::new(), save.next_room_id);
+    pending.0 = Some((
+        save,
+        RestoreStep::Despawning {
+            old_rooms,
+            frames: 0,
+        },
+    ));
+}
+
+/// Everything a restore sends out.
+#[derive(SystemParam)]
+pub struct RestoreWriters<'w> {
+    despawn_room: EventWriter<'w, map::DespawnRoomEvent>,
+    map_spawn: EventWriter<'w, map::MapObjectSpawnEvent>,
+    player_restore: EventWriter<'w, players::PlayerRestoreEvent>,
+    room_started: EventWriter<'w, RoomStartedEvent>,
+    lobby: EventWriter<'w, LobbyRequestEvent>,
+}
+
+pub fn restore_game(
+    mut pending: ResMut<PendingRestore>,
+    mut rooms: ResMut<Rooms>,
+    users: Res<protocol::Users>,
+    levels: LevelData,
+    vertex_lookup: Res<map::VertexClientServerLookup>,
+    mut vertexes: Query<&mut map::Vertex>,
+    mut writers: RestoreWriters,
+) {
+    let Some((save, step)) = &mut pending.0 else {
+        return;
+    };
+    match step {
+        RestoreStep::Despawning { old_rooms, frames } => {
+            *frames += 1;
+            if *frames == 2 {
+                for room in old_rooms.drain(..) {
+                    writers.despawn_room.send(map::DespawnRoomEvent(room));
+                }
+            }
+            if *frames < RESTORE_FRAMES {
+                return;
+            }
+            let placeholders = rebuild_rooms(save, &mut rooms, &levels, &mut writers.map_spawn);
+            *step = RestoreStep::Placing { placeholders };
+        }
+        RestoreStep::Placing { placeholders } => {
+            let vertex_of = |room: RoomId, id: u32| {
+                vertex_lookup
+                    .0
+                    .get(&(room, id))
+                    .copied()
+                    .filter(|entity| vertexes.contains(*entity))
+            };
+            let spawned = save
+                .rooms
+                .iter()
+                .filter_map(|room| Some((room.id, room.map.as_ref()?)))
+                .all(|(room, map)| {
+                    map.vertexes
+                        .iter()
+                        .all(|vertex| vertex_of(room, vertex.id).is_some())
+                });
+            if !spawned {
+                return;
+            }
+
+            let mut filled = Vec::new();
+            for room in save.rooms.iter() {
+                let Some(map) = &room.map else {
+                    continue;
+                };
+                filled.extend(map.filled.iter().filter_map(|id| vertex_of(room.id, *id)));
+                for player in room.players.iter() {
+                    let owner = placeholders.get(&(room.id, player.username.clone()));
+                    let (Some(owner), Some(current_vertex)) =
+                        (owner, vertex_of(room.id, player.current_vertex))
+                    else {
+                        warn!("Could not restore the player of {}", player.username);
+                        continue;
+                    };
+                    writers.player_restore.send(players::PlayerRestoreEvent {
+                        room: room.id,
+                        id: player.id,
+                        client_owner_id: *owner,
+                        color: player.color,
+                        current_vertex,
+                        next_entity: player
+                            .next_vertexes
+                            .iter()
+                            .filter_map(|id| vertex_of(room.id, *id))
+                            .collect(),
+                        x: player.x,
+                        y: player.y,
+                        rotation: player.rotation,
+                    });
+                }
+                writers
+                    .room_started
+                    .send(RoomStartedEvent { room: room.id });
+            }
+            for entity in filled {
+                if let Ok(mut vertex) = vertexes.get_mut(entity) {
+                    vertex.filled = true;
+                }
+            }
+
+            // Whoever is still connected gets their seat back right away
+            for (client_id, username) in users.names.iter() {
+                writers.lobby.send(LobbyRequestEvent {
+                    client_id: *client_id,
+                    request: LobbyRequest::Resume {
+                        username: username.clone(),
+                    },
+                });
+            }
+            info!("Restored {} rooms", save.rooms.len());
+            pending.0 = None;
+        }
+    }
+}
+
+/// Rooms with a match come back playing, with a vacant seat for each of their players.
+fn rebuild_rooms(
+    save: &SaveFile,
+    rooms: &mut Rooms,
+    levels: &LevelData,
+    map_spawn: &mut EventWriter<map::MapObjectSpawnEvent>,
+) -> HashMap<(RoomId, String), ClientId> {
+    let mut placeholders = HashMap::new();
+    let mut restored = Vec::new();
+    for saved in save.rooms.iter() {
+        let mut room = Room {
+            id: saved.id,
+            name: saved.name.clone(),
+            level: saved.level.clone(),
+            seats: Vec::new(),
+            spectators: Vec::new(),
+            vacant: Vec::new(),
+            max_players: saved.max_players,
+            reserved: saved.reserved,
+            phase: RoomPhase::Waiting,
+            persistent: saved.persistent,
+            drop_in: saved.drop_in,
+        };
+        match &saved.map {
+            Some(map) => {
+                map_spawn.send_batch(map::init_map_events(
+                    saved.id,
+                    &map.vertexes,
+                    &map.edges,
+                    &map.materials,
+                ));
+                room.phase = RoomPhase::Playing;
+                for seat in saved.seats.iter() {
+                    let placeholder = RESTORED_CLIENT_ID_START + placeholders.len() as ClientId;
+                    placeholders.insert((saved.id, seat.username.clone()), placeholder);
+                    room.vacant.push(VacantSeat {
+                        username: seat.username.clone(),
+                        placeholder,
+                        color: seat.color,
+                    });
+                }
+            }
+            // Drop-in rooms never wait, so one saved while loading starts over
+            None if saved.drop_in => match levels.get(&saved.level) {
+                Ok(data) => {
+                    map::server_map::spawn_level(saved.id, data, map_spawn);
+                    room.phase = RoomPhase::Loading;
+                }
+                Err(e) => warn!("Could not restart {}: {}", saved.name, e),
+            },
+            None => {}
+        }
+        restored.push(room);
+    }
+    rooms.restore(restored, save.next_room_id);
+    placeholders
+}
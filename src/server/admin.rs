@@ -0,0 +1,438 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    io::BufRead,
+    sync::{
+        mpsc::{self, Receiver},
+        Mutex,
+    },
+};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_quinnet::{
+    server::{Endpoint, Server},
+    shared::ClientId,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    lobby::{self, LobbyRequestEvent, RoomCommand, RoomCommandEvent, Rooms},
+    protocol::{self, PasswordRejection, RoomId, ServerMessage},
+    replay::ReplayRecorder,
+};
+
+use super::{
+    names::name_key,
+    password::{AccessConfig, PasswordGate},
+    rate_limit::RateLimiter,
+    systems, AdminConfig,
+};
+
+pub const ADMIN_HELP: &str = "Commands: users, kick <name> [reason], ban <name>, unban <name>, \
+bans, level <level> [room], restart [room], say <message>, rates";
+
+/// A line of the console or an admin's chat message, without its `/`.
+pub struct AdminCommandEvent {
+    /// Who typed it, `None` for the console.
+    pub issuer: Option<ClientId>,
+    pub line: String,
+}
+
+/// The answer to an admin command, printed on the console or sent back to the admin.
+pub struct AdminReplyEvent {
+    pub issuer: Option<ClientId>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdminCommand {
+    Help,
+    Users,
+    Kick {
+        name: String,
+        reason: String,
+    },
+    Ban {
+        name: String,
+    },
+    Unban {
+        name: String,
+    },
+    Bans,
+    Level {
+        level: String,
+        room: RoomId,
+    },
+    Restart {
+        room: RoomId,
+    },
+    Say {
+        message: String,
+    },
+    /// The rate limit counters.
+    Rates,
+}
+
+impl AdminCommand {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        let (word, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.trim();
+        let mut args = rest.split_whitespace();
+        let room = |arg: Option<&str>| match arg {
+            None => Ok(lobby::DEFAULT_ROOM),
+            Some(room) => room
+                .parse()
+                .map(RoomId)
+                .map_err(|_| format!("{} is not a room id", room)),
+        };
+        let name = |arg: Option<&str>| {
+            arg.map(str::to_string)
+                .ok_or_else(|| format!("Usage: {} <name>", word))
+        };
+        match word {
+            "help" => Ok(AdminCommand::Help),
+            "users" => Ok(AdminCommand::Users),
+            "kick" => {
+                let name = name(args.next())?;
+                let reason = rest[name.len()..].trim();
+                Ok(AdminCommand::Kick {
+                    name,
+                    reason: reason.to_string(),
+                })
+            }
+            "ban" => Ok(AdminCommand::Ban {
+                name: name(args.next())?,
+            }),
+            "unban" => Ok(AdminCommand::Unban {
+                name: name(args.next())?,
+            }),
+            "bans" => Ok(AdminCommand::Bans),
+            "level" => Ok(AdminCommand::Level {
+                level: args
+                    .next()
+                    .ok_or("Usage: level <level> [room]")?
+                    .to_string(),
+                room: room(args.next())?,
+            }),
+            "restart" => Ok(AdminCommand::Restart {
+                room: room(args.next())?,
+            }),
+            "say" if !rest.is_empty() => Ok(AdminCommand::Say {
+                message: rest.to_string(),
+            }),
+            "say" => Err("Usage: say <message>".to_string()),
+            "rates" => Ok(AdminCommand::Rates),
+            _ => Err(format!("Unknown command {}. {}", word, ADMIN_HELP)),
+        }
+    }
+}
+
+/// Names that can't join the server whatever their case, kept in `AdminConfig::ban_list`.
+#[derive(Resource, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BanList {
+    pub names: BTreeSet<String>,
+    #[serde(skip)]
+    path: String,
+}
+
+impl BanList {
+    pub fn load(path: &str) -> Self {
+        let mut bans = match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|e| {
+                warn!("Invalid ban list {}: {}, starting empty", path, e);
+                BanList::default()
+            }),
+            Err(_) => BanList::default(),
+        };
+        bans.names = bans.names.iter().map(|name| name_key(name)).collect();
+        bans.path = path.to_string();
+        bans
+    }
+
+    pub fn is_banned(&self, name: &str) -> bool {
+        self.names.contains(&name_key(name))
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let text = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(&self.path, text).map_err(|e| format!("{}: {}", self.path, e))
+    }
+}
+
+/// Lines typed on the server's standard input, read on their own thread.
+#[derive(Resource)]
+pub struct AdminConsole(Mutex<Receiver<String>>);
+
+impl AdminConsole {
+    pub fn start() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Self(Mutex::new(receiver))
+    }
+}
+
+pub fn read_console(
+    console: Option<Res<AdminConsole>>,
+    mut commands: EventWriter<AdminCommandEvent>,
+) {
+    let Some(console) = console else {
+        return;
+    };
+    let receiver = console.0.lock().unwrap();
+    while let Ok(line) = receiver.try_recv() {
+        if !line.trim().is_empty() {
+            commands.send(AdminCommandEvent { issuer: None, line });
+        }
+    }
+}
+
+/// Clients that logged in with the admin password, they run admin commands from the chat.
+#[derive(Resource)]
+pub struct AdminSessions {
+    /// `None` without an admin password, nobody logs in then.
+    gate: Option<PasswordGate>,
+    admins: HashSet<ClientId>,
+}
+
+impl AdminSessions {
+    pub fn new(config: &AdminConfig) -> Self {
+        let gate = config.password.clone().map(|password| {
+            PasswordGate::new(AccessConfig {
+                password: Some(password),
+                ..default()
+            })
+        });
+        Self {
+            gate,
+            admins: HashSet::new(),
+        }
+    }
+
+    pub fn is_admin(&self, client_id: ClientId) -> bool {
+        self.admins.contains(&client_id)
+    }
+
+    /// Makes `client_id` an admin if `given` is the admin password, at `now` in seconds.
+    pub fn login(
+        &mut self,
+        client_id: ClientId,
+        given: &str,
+        now: f32,
+    ) -> Result<(), PasswordRejection> {
+        let Some(gate) = self.gate.as_mut() else {
+            return Err(PasswordRejection::Locked);
+        };
+        gate.check(client_id, Some(given), now)?;
+        self.admins.insert(client_id);
+        Ok(())
+    }
+
+    /// Forgets the clients that are gone.
+    pub fn retain(&mut self, connected: &[ClientId]) {
+        self.admins
+            .retain(|client_id| connected.contains(client_id));
+        if let Some(gate) = self.gate.as_mut() {
+            gate.retain(connected);
+        }
+    }
+}
+
+/// What admin commands report on.
+#[derive(SystemParam)]
+pub struct AdminViews<'w> {
+    rooms: Res<'w, Rooms>,
+    rates: Res<'w, RateLimiter>,
+}
+
+#[derive(SystemParam)]
+pub struct AdminWriters<'w> {
+    lobby: EventWriter<'w, LobbyRequestEvent>,
+    room_commands: EventWriter<'w, RoomCommandEvent>,
+    replies: EventWriter<'w, AdminReplyEvent>,
+}
+
+pub fn run_admin_commands(
+    mut commands: EventReader<AdminCommandEvent>,
+    mut server: ResMut<Server>,
+    mut users: ResMut<protocol::Users>,
+    views: AdminViews,
+    mut bans: ResMut<BanList>,
+    mut recorder: ResMut<ReplayRecorder>,
+    mut writers: AdminWriters,
+) {
+    let endpoint = server.endpoint_mut();
+    for AdminCommandEvent { issuer, line } in commands.iter() {
+        let issuer = *issuer;
+        info!("Admin command from {:?}: {}", issuer, line);
+        let command = match AdminCommand::parse(line) {
+            Ok(command) => command,
+            Err(e) => {
+                writers.replies.send(AdminReplyEvent { issuer, message: e });
+                continue;
+            }
+        };
+        let ids_of = |users: &protocol::Users, name: &str| -> Vec<ClientId> {
+            users
+                .names
+                .iter()
+                .filter(|(_, user)| name_key(user) == name_key(name))
+                .map(|(client_id, _)| *client_id)
+                .collect()
+        };
+
+        let message = match command {
+            AdminCommand::Help => ADMIN_HELP.to_string(),
+            AdminCommand::Users => {
+                let mut lines: Vec<String> = users
+                    .names
+                    .iter()
+                    .map(|(client_id, name)| match views.rooms.room_of(*client_id) {
+                        Some(room) => format!("{} {} in room {}", client_id, name, room.0),
+                        None => format!("{} {} in the lobby", client_id, name),
+                    })
+                    .collect();
+                lines.sort();
+                format!("{} users\n{}", lines.len(), lines.join("\n"))
+            }
+            AdminCommand::Kick { name, reason } => {
+                let kicked = ids_of(&users, &name);
+                let notice = match reason.as_str() {
+                    "" => "You were kicked".to_string(),
+                    reason => format!("You were kicked: {}", reason),
+                };
+                for client_id in kicked.iter() {
+                    let (users, recorder) = (&mut users, &mut recorder);
+                    kick(
+                        endpoint,
+                        users,
+                        &views.rooms,
+                        recorder,
+                        &mut writers.lobby,
+                        *client_id,
+                        &notice,
+                    );
+                }
+                match kicked.len() {
+                    0 => format!("Nobody named {} is connected", name),
+                    _ => format!("Kicked {}", name),
+                }
+            }
+            AdminCommand::Ban { name } => {
+                bans.names.insert(name_key(&name));
+                for client_id in ids_of(&users, &name) {
+                    let (users, recorder) = (&mut users, &mut recorder);
+                    let notice = "You are banned from this server";
+                    kick(
+                        endpoint,
+                        users,
+                        &views.rooms,
+                        recorder,
+                        &mut writers.lobby,
+                        client_id,
+                        notice,
+                    );
+                }
+                match bans.save() {
+                    Ok(()) => format!("Banned the name {}", name),
+                    Err(e) => format!(
+                        "Banned {} until the server stops, saving failed: {}",
+                        name, e
+                    ),
+                }
+            }
+            AdminCommand::Unban { name } => {
+                if !bans.names.remove(&name_key(&name)) {
+                    writers.replies.send(AdminReplyEvent {
+                        issuer,
+                        message: format!("{} is not banned", name),
+                    });
+                    continue;
+                }
+                match bans.save() {
+                    Ok(()) => format!("Unbanned {}", name),
+                    Err(e) => format!(
+                        "Unbanned {} until the server stops, saving failed: {}",
+                        name, e
+                    ),
+                }
+            }
+            AdminCommand::Bans if bans.names.is_empty() => "Nobody is banned".to_string(),
+            AdminCommand::Bans => {
+                let names: Vec<&str> = bans.names.iter().map(String::as_str).collect();
+                format!("Banned: {}", names.join(", "))
+            }
+            AdminCommand::Level { level, room } => {
+                writers.room_commands.send(RoomCommandEvent {
+                    issuer,
+                    room,
+                    command: RoomCommand::SetLevel(level),
+                });
+                continue;
+            }
+            AdminCommand::Restart { room } => {
+                writers.room_commands.send(RoomCommandEvent {
+                    issuer,
+                    room,
+                    command: RoomCommand::Restart,
+                });
+                continue;
+            }
+            AdminCommand::Say { message } => {
+                let notice = ServerMessage::ServerNotice { message };
+                recorder.record_all(&notice);
+                endpoint.try_send_group_message(users.names.keys(), notice);
+                format!("Sent to {} users", users.names.len())
+            }
+            AdminCommand::Rates => format!("Rate limits: {}", views.rates.stats),
+        };
+        writers.replies.send(AdminReplyEvent { issuer, message });
+    }
+}
+
+/// Tells the client why it has to go, then drops it like a disconnect.
+pub(super) fn kick(
+    endpoint: &mut Endpoint,
+    users: &mut ResMut<protocol::Users>,
+    rooms: &Rooms,
+    recorder: &mut ReplayRecorder,
+    lobby: &mut EventWriter<LobbyRequestEvent>,
+    client_id: ClientId,
+    notice: &str,
+) {
+    info!("Kicking {}: {}", client_id, notice);
+    // Best effort, the connection may close before the notice is out
+    endpoint.try_send_message(
+        client_id,
+        ServerMessage::ServerNotice {
+            message: notice.to_string(),
+        },
+    );
+    if let Err(e) = endpoint.disconnect_client(client_id) {
+        warn!("Failed to disconnect {}: {:?}", client_id, e);
+    }
+    systems::handle_disconnect(endpoint, users, rooms, recorder, lobby, client_id);
+}
+
+/// Prints replies to the console, or sends them to the admin who asked.
+pub fn send_admin_replies(mut replies: EventReader<AdminReplyEvent>, server: Res<Server>) {
+    for AdminReplyEvent { issuer, message } in replies.iter() {
+        match issuer {
+            None => println!("{}", message),
+            Some(client_id) => server.endpoint().try_send_message(
+                *client_id,
+                ServerMessage::ServerNotice {
+                    message: message.clone(),
+                },
+            ),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::{
+    map::{MapGraph, MapGraphs},
+    protocol::{
+        ActionRejection, ActionResultEvent, ClientEvent, CommandEvent, CommandKind, GameCommand,
+    },
+};
+
+/// Checks the payload of a command against the map of the room it was sent in, before its
+/// handler runs.
+pub type CommandValidator = fn(&GameCommand, Option<&MapGraph>) -> Result<(), ActionRejection>;
+
+/// The commands gameplay plugins handle, with how to validate each.
+#[derive(Resource, Default)]
+pub struct CommandRegistry {
+    validators: HashMap<CommandKind, CommandValidator>,
+}
+
+impl CommandRegistry {
+    /// Whether a command may go to its handler, commands nobody registered are refused.
+    pub fn validate(
+        &self,
+        command: &GameCommand,
+        graph: Option<&MapGraph>,
+    ) -> Result<(), ActionRejection> {
+        let validator = self
+            .validators
+            .get(&command.kind())
+            .ok_or(ActionRejection::UnknownCommand)?;
+        validator(command, graph)
+    }
+}
+
+pub trait RegisterCommand {
+    /// Adds `handler` to read the `CommandEvent`s that passed `validate`. Every handler reads
+    /// all of them: it skips the ones of other kinds and replies to each of `kind` with
+    /// exactly one `ActionResultEvent`.
+    fn register_command<M>(
+        &mut self,
+        kind: CommandKind,
+        validate: CommandValidator,
+        handler: impl IntoSystemConfig<M>,
+    ) -> &mut Self;
+}
+
+impl RegisterCommand for App {
+    fn register_command<M>(
+        &mut self,
+        kind: CommandKind,
+        validate: CommandValidator,
+        handler: impl IntoSystemConfig<M>,
+    ) -> &mut Self {
+        self.init_resource::<CommandRegistry>()
+            .add_event::<CommandEvent>()
+            .add_event::<ActionResultEvent>();
+        let mut registry = self.world.resource_mut::<CommandRegistry>();
+        if registry.validators.insert(kind, validate).is_some() {
+            warn!("Command {:?} was registered twice", kind);
+        }
+        self.add_system(handler.after(dispatch_commands))
+    }
+}
+
+/// Sends commands on to their handler, or refuses them here if they are unknown or malformed.
+pub fn dispatch_commands(
+    mut client_events: EventReader<ClientEvent>,
+    registry: Res<CommandRegistry>,
+    graphs: Res<MapGraphs>,
+    mut commands: EventWriter<CommandEvent>,
+    mut results: EventWriter<ActionResultEvent>,
+) {
+    for event in client_events.iter() {
+        let graph = graphs.0.get(&event.room);
+        let command = event
+            .command
+            .open()
+            .and_then(|command| registry.validate(&command, graph).map(|()| command));
+        match command {
+            Ok(command) => commands.send(CommandEvent {
+                request_id: event.request_id,
+                command,
+                client_id: event.client_id,
+                room: event.room,
+            }),
+            Err(reason) => results.send(ActionResultEvent {
+                client_id: event.client_id,
+                request_id: event.request_id,
+                result: Err(reason),
+            }),
+        }
+    }
+}
+
+/// Accepts pings right away, they only measure the round trip.
+pub fn answer_pings(
+    mut commands: EventReader<CommandEvent>,
+    mut results: EventWriter<ActionResultEvent>,
+) {
+    for event in commands.iter() {
+        if event.command.kind() != CommandKind::Ping {
+            continue;
+        }
+        results.send(ActionResultEvent {
+            client_id: event.client_id,
+            request_id: event.request_id,
+            result: Ok(()),
+        });
+    }
+}
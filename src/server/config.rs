@@ -0,0 +1,192 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    names::NamesConfig,
+    password::{AccessConfig, Password},
+    rate_limit::RateLimitsConfig,
+};
+use crate::{bots::BotStrategyKind, discovery::DiscoveryConfig};
+
+pub const SERVER_CONFIG_FILE: &str = "./server_config.json";
+
+/// Server settings, read from `server_config.json` next to the executable.
+/// Every field is optional in the file and falls back to its default.
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// UDP port the server listens on.
+    pub port: u16,
+    /// Level of the main room, relative to the assets folder. A name like
+    /// `generated/hex?players=4&size=2&seed=42` generates it instead, see `map::generator`.
+    pub level: String,
+    /// Whether the main room plays right away and lets players drop in,
+    /// instead of waiting for its players to ready up like other rooms.
+    pub main_room_drop_in: bool,
+    pub bots: BotsConfig,
+    pub replays: ReplaysConfig,
+    pub saves: SavesConfig,
+    pub admin: AdminConfig,
+    pub rate_limits: RateLimitsConfig,
+    pub names: NamesConfig,
+    pub access: AccessConfig,
+    pub certificate: CertificateConfig,
+    pub discovery: DiscoveryConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BotsConfig {
+    /// How many bot players the server adds to the map.
+    pub count: usize,
+    pub strategy: BotStrategyKind,
+    /// Seconds between two decisions of the same bot.
+    pub think_interval: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReplaysConfig {
+    /// Record every match to a replay file the client can play back.
+    pub record: bool,
+    /// Folder the replay files go to, relative to the working directory.
+    pub dir: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SavesConfig {
+    /// Where autosaves go, relative to the working directory.
+    pub path: String,
+    /// Seconds between two autosaves, 0 turns them off.
+    pub autosave_interval: f32,
+    /// A save to restore at startup instead of starting fresh, `--load <file>` sets it too.
+    pub load: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdminConfig {
+    /// Users who sent `/login <password>` in the chat run admin commands with a leading `/`.
+    /// Without it admin commands only come from the console.
+    pub password: Option<Password>,
+    /// Reads admin commands from the server's standard input.
+    pub console: bool,
+    /// Where banned names are kept, relative to the working directory.
+    pub ban_list: String,
+}
+
+/// The TLS certificate clients see. Keeping the same one across restarts lets clients that
+/// pinned its fingerprint connect without a warning.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CertificateConfig {
+    /// PEM files, relative to the working directory.
+    pub cert_file: String,
+    pub key_file: String,
+    /// Generate a self-signed certificate when the files are missing, instead of failing.
+    pub generate: bool,
+    /// Write the generated certificate to the files, otherwise a new one is made every start
+    /// until they exist.
+    pub save: bool,
+    /// Hostname of generated certificates.
+    pub hostname: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            port: 6000,
+            level: "levels/level_3.json".to_string(),
+            main_room_drop_in: true,
+            bots: BotsConfig::default(),
+            replays: ReplaysConfig::default(),
+            saves: SavesConfig::default(),
+            admin: AdminConfig::default(),
+            rate_limits: RateLimitsConfig::default(),
+            names: NamesConfig::default(),
+            access: AccessConfig::default(),
+            certificate: CertificateConfig::default(),
+            discovery: DiscoveryConfig::default(),
+        }
+    }
+}
+
+impl Default for BotsConfig {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            strategy: BotStrategyKind::RandomWalk,
+            think_interval: 0.5,
+        }
+    }
+}
+
+impl Default for ReplaysConfig {
+    fn default() -> Self {
+        Self {
+            record: false,
+            dir: "replays".to_string(),
+        }
+    }
+}
+
+impl Default for SavesConfig {
+    fn default() -> Self {
+        Self {
+            path: "saves/world.json".to_string(),
+            autosave_interval: 0.0,
+            load: None,
+        }
+    }
+}
+
+impl Default for CertificateConfig {
+    fn default() -> Self {
+        Self {
+            cert_file: "server_cert.pem".to_string(),
+            key_file: "server_key.pem".to_string(),
+            generate: true,
+            save: true,
+            hostname: "TheMainServer".to_string(),
+        }
+    }
+}
+
+impl Default for AdminConfig {
+    fn default() -> Self {
+        Self {
+            password: None,
+            console: true,
+            ban_list: "bans.json".to_string(),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn load(path: &str) -> Self {
+        match std::fs::read_to_string(path) {
+            Ok(text) => match serde_json::from_str::<ServerConfig>(&text) {
+                Ok(config) => config,
+                Err(e) => {
+                    warn!("Invalid server config {}: {}, using defaults", path, e);
+                    ServerConfig::default()
+                }
+            },
+            Err(_) => {
+                info!("No server config at {}, using defaults", path);
+                ServerConfig::default()
+            }
+        }
+    }
+
+    /// The config file, with `--load <file>` from the command line on top.
+    pub fn from_args() -> Self {
+        let mut config = Self::load(SERVER_CONFIG_FILE);
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        if let Some(index) = args.iter().position(|arg| arg == "--load") {
+            config.saves.load = args.get(index + 1).cloned();
+        }
+        config
+    }
+}
@@ -1,11 +1,73 @@
 use bevy::prelude::*;
+use bevy_quinnet::server::QuinnetServerPlugin;
 
+use crate::{bots, discovery, lobby, map, players, protocol, replay, save};
+
+/// Everything the server needs except for rendering, so it can also run headless.
 pub struct ServerPlugin;
 
+mod admin;
+mod commands;
+mod config;
+mod names;
+mod password;
+mod rate_limit;
 mod systems;
 
+pub use admin::{AdminCommand, AdminReplyEvent, AdminSessions, BanList};
+pub use commands::{dispatch_commands, CommandRegistry, CommandValidator, RegisterCommand};
+pub use config::{AdminConfig, CertificateConfig, ServerConfig, SERVER_CONFIG_FILE};
+pub use names::{name_key, validate_name, NamesConfig};
+pub use password::{AccessConfig, Password, PasswordGate};
+pub use rate_limit::{BucketConfig, RateLimitStats, RateLimiter, RateLimitsConfig, Verdict};
+
 impl Plugin for ServerPlugin {
-    fn build(&self, _app: &mut App) {
-        //If this is even needed
+    fn build(&self, app: &mut App) {
+        if !app.world.contains_resource::<ServerConfig>() {
+            app.insert_resource(ServerConfig::load(SERVER_CONFIG_FILE));
+        }
+
+        let config = app.world.resource::<ServerConfig>();
+        let bans = BanList::load(&config.admin.ban_list);
+        let rates = RateLimiter::new(config.rate_limits.clone());
+        let passwords = PasswordGate::new(config.access.clone());
+        let admins = AdminSessions::new(&config.admin);
+        if config.admin.console {
+            app.insert_resource(admin::AdminConsole::start());
+        }
+
+        app.insert_resource(protocol::IsServer(true))
+            .insert_resource(bans)
+            .insert_resource(rates)
+            .insert_resource(passwords)
+            .insert_resource(admins)
+            .add_plugin(QuinnetServerPlugin::default())
+            .add_plugin(map::ServerMapPlugin)
+            .add_plugin(players::ServerPlayersPlugin)
+            .add_plugin(lobby::ServerLobbyPlugin)
+            .add_plugin(replay::ServerReplayPlugin)
+            .add_plugin(save::ServerSavePlugin)
+            .add_plugin(bots::ServerBotsPlugin)
+            .add_plugin(discovery::ServerDiscoveryPlugin)
+            .insert_resource(protocol::Users::default())
+            .add_event::<protocol::ClientEvent>()
+            .add_event::<protocol::CommandEvent>()
+            .add_event::<protocol::ActionResultEvent>()
+            .init_resource::<CommandRegistry>()
+            .register_command(
+                protocol::CommandKind::Ping,
+                |_, _| Ok(()),
+                commands::answer_pings,
+            )
+            .add_startup_system(systems::start_listening)
+            .add_system(systems::handle_client_messages)
+            .add_system(commands::dispatch_commands.after(systems::handle_client_messages))
+            .add_system(systems::handle_server_events)
+            .add_system(systems::send_action_results)
+            .add_event::<admin::AdminCommandEvent>()
+            .add_event::<AdminReplyEvent>()
+            .add_system(admin::read_console.before(admin::run_admin_commands))
+            .add_system(admin::run_admin_commands.after(systems::handle_client_messages))
+            .add_system(admin::send_admin_replies.after(admin::run_admin_commands));
     }
 }
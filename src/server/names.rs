@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::{allowed_name_character, NameRejection, Users};
+
+/// What a username has to look like to join.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NamesConfig {
+    pub min_length: usize,
+    pub max_length: usize,
+    /// Names nobody can take, whatever their case.
+    pub reserved: Vec<String>,
+}
+
+impl Default for NamesConfig {
+    fn default() -> Self {
+        Self {
+            min_length: 2,
+            max_length: 20,
+            reserved: vec!["server".to_string(), "admin".to_string()],
+        }
+    }
+}
+
+/// What names are compared by: two names with the same key belong to the same user.
+pub fn name_key(name: &str) -> String {
+    name.to_lowercase()
+}
+
+/// Whether `name` may join next to the users already connected, names differing only in case
+/// count as taken.
+pub fn validate_name(name: &str, config: &NamesConfig, users: &Users) -> Result<(), NameRejection> {
+    let length = name.chars().count();
+    if length < config.min_length {
+        return Err(NameRejection::TooShort {
+            min: config.min_length,
+        });
+    }
+    if length > config.max_length {
+        return Err(NameRejection::TooLong {
+            max: config.max_length,
+        });
+    }
+    if !name.chars().all(allowed_name_character) {
+        return Err(NameRejection::InvalidCharacters);
+    }
+    let key = name_key(name);
+    if config
+        .reserved
+        .iter()
+        .any(|reserved| name_key(reserved) == key)
+    {
+        return Err(NameRejection::Reserved);
+    }
+    if users.names.values().any(|user| name_key(user) == key) {
+        return Err(NameRejection::Taken);
+    }
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_quinnet::shared::ClientId;
+use serde::{Deserialize, Serialize};
+
+use super::rate_limit::BucketConfig;
+use crate::protocol::PasswordRejection;
+
+/// Who gets to join, and how many wrong passwords it takes to be shown the door.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessConfig {
+    /// Asked for in every `Join` when set.
+    pub password: Option<Password>,
+    /// Wrong passwords a client may send before it is disconnected.
+    pub attempts: u32,
+    /// Wrong passwords the whole server takes, past them every wrong one disconnects.
+    pub failures: BucketConfig,
+}
+
+impl Default for AccessConfig {
+    fn default() -> Self {
+        Self {
+            password: None,
+            attempts: 3,
+            failures: BucketConfig {
+                burst: 10.0,
+                per_second: 0.2,
+            },
+        }
+    }
+}
+
+/// A secret that stays out of logs, `Debug` doesn't show it.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Password(pub String);
+
+impl std::fmt::Debug for Password {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Password(..)")
+    }
+}
+
+impl Password {
+    /// Compares every byte whatever the first difference, so timing says nothing about it.
+    fn matches(&self, given: &str) -> bool {
+        let (expected, given) = (self.0.as_bytes(), given.as_bytes());
+        let difference = expected
+            .iter()
+            .zip(given)
+            .fold(0, |difference, (a, b)| difference | (a ^ b));
+        difference == 0 && expected.len() == given.len()
+    }
+}
+
+/// Wrong passwords so far, by client and for the whole server.
+#[derive(Resource, Default)]
+pub struct PasswordGate {
+    config: AccessConfig,
+    wrong: HashMap<ClientId, u32>,
+    /// Failures the server still takes and when they were last refilled.
+    tokens: Option<(f32, f32)>,
+}
+
+impl PasswordGate {
+    pub fn new(config: AccessConfig) -> Self {
+        Self {
+            config,
+            ..default()
+        }
+    }
+
+    /// Whether `client_id` may join with `given`, at `now` in seconds.
+    pub fn check(
+        &mut self,
+        client_id: ClientId,
+        given: Option<&str>,
+        now: f32,
+    ) -> Result<(), PasswordRejection> {
+        let Some(password) = &self.config.password else {
+            return Ok(());
+        };
+        let Some(given) = given else {
+            return Err(PasswordRejection::Required);
+        };
+
+        // Guessers running the server out of failures don't lock out those who know it
+        if password.matches(given) {
+            self.wrong.remove(&client_id);
+            return Ok(());
+        }
+
+        let bucket = self.config.failures;
+        let (tokens, refilled) = self.tokens.get_or_insert((bucket.burst, now));
+        *tokens = (*tokens + (now - *refilled) * bucket.per_second).min(bucket.burst);
+        *refilled = now;
+        if *tokens < 1.0 {
+            return Err(PasswordRejection::Locked);
+        }
+        *tokens -= 1.0;
+        let wrong = self.wrong.entry(client_id).or_default();
+        *wrong += 1;
+        match self.config.attempts.checked_sub(*wrong) {
+            Some(attempts_left) if attempts_left > 0 => {
+                Err(PasswordRejection::Wrong { attempts_left })
+            }
+            _ => Err(PasswordRejection::TooManyAttempts),
+        }
+    }
+
+    /// Forgets the attempts of clients that are gone.
+    pub fn retain(&mut self, connected: &[ClientId]) {
+        self.wrong
+            .retain(|client_id, _| connected.contains(client_id));
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::{BTreeMap, HashMap};
+
+use bevy::prelude::*;
+use bevy_quinnet::shared::ClientId;
+use serde::{Deserialize, Serialize};
+
+/// Seconds between two violations of the same client, messages dropped in between only count
+/// in the stats. Keeps one burst from going from a warning to a disconnect in a single frame.
+const VIOLATION_SPACING: f32 = 1.0;
+
+/// How fast clients may send each kind of `ClientMessage`, and what happens when they don't
+/// keep to it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitsConfig {
+    pub enabled: bool,
+    /// For the kinds not in `kinds`.
+    pub default: BucketConfig,
+    /// By `ClientMessage::kind`, like `"ChatMessage"`.
+    pub kinds: BTreeMap<String, BucketConfig>,
+    /// Violations that only get a warning, the next ones get the client throttled.
+    pub warnings: u32,
+    /// Seconds every message of a throttled client is ignored for.
+    pub throttle_seconds: f32,
+    /// Violations after which the client is disconnected.
+    pub disconnect_after: u32,
+    /// Seconds without a violation after which a client starts over with a clean slate.
+    pub forgive_seconds: f32,
+}
+
+/// A token bucket: `burst` messages at once, refilled with `per_second` messages a second.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BucketConfig {
+    pub burst: f32,
+    pub per_second: f32,
+}
+
+impl Default for RateLimitsConfig {
+    fn default() -> Self {
+        let kinds = [
+            ("ChatMessage", 5.0, 1.0),
+            ("Whisper", 5.0, 1.0),
+            ("SendCommand", 20.0, 10.0),
+            ("CreateRoom", 3.0, 0.2),
+        ];
+        Self {
+            enabled: true,
+            default: BucketConfig {
+                burst: 20.0,
+                per_second: 5.0,
+            },
+            kinds: kinds
+                .into_iter()
+                .map(|(kind, burst, per_second)| {
+                    (kind.to_string(), BucketConfig { burst, per_second })
+                })
+                .collect(),
+            warnings: 2,
+            throttle_seconds: 10.0,
+            disconnect_after: 5,
+            forgive_seconds: 60.0,
+        }
+    }
+}
+
+/// What to do with a message, and with its sender.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    /// Dropped without telling the client.
+    Drop,
+    /// Dropped, and the client is told to slow down.
+    Warn,
+    /// Dropped, and every other message of the client too for a while.
+    Throttle,
+    Disconnect,
+}
+
+/// How often the rate limits kicked in since the server started.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimitStats {
+    /// Messages dropped, by kind.
+    pub dropped: BTreeMap<&'static str, u64>,
+    pub warnings: u64,
+    pub throttles: u64,
+    pub disconnects: u64,
+}
+
+#[derive(Default)]
+struct ClientLimits {
+    /// Tokens left and when they were last refilled, by message kind.
+    buckets: HashMap<&'static str, (f32, f32)>,
+    violations: u32,
+    last_violation: Option<f32>,
+    throttled_until: f32,
+}
+
+/// The token buckets of every connected client.
+#[derive(Resource, Default)]
+pub struct RateLimiter {
+    config: RateLimitsConfig,
+    clients: HashMap<ClientId, ClientLimits>,
+    pub stats: RateLimitStats,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitsConfig) -> Self {
+        Self {
+            config,
+            ..default()
+        }
+    }
+
+    /// Takes a token for a message of `kind` sent by `client_id` at `now`, in seconds.
+    pub fn check(&mut self, client_id: ClientId, kind: &'static str, now: f32) -> Verdict {
+        if !self.config.enabled {
+            return Verdict::Allow;
+        }
+        let config = &self.config;
+        let bucket = *config.kinds.get(kind).unwrap_or(&config.default);
+        let client = self.clients.entry(client_id).or_default();
+
+        let verdict = if now < client.throttled_until {
+            Verdict::Drop
+        } else {
+            let (tokens, refilled) = client.buckets.entry(kind).or_insert((bucket.burst, now));
+            *tokens = (*tokens + (now - *refilled) * bucket.per_second).min(bucket.burst);
+            *refilled = now;
+            if *tokens >= 1.0 {
+                *tokens -= 1.0;
+                return Verdict::Allow;
+            }
+            client.violation(config, now)
+        };
+
+        *self.stats.dropped.entry(kind).or_default() += 1;
+        match verdict {
+            Verdict::Warn => self.stats.warnings += 1,
+            Verdict::Throttle => self.stats.throttles += 1,
+            Verdict::Disconnect => self.stats.disconnects += 1,
+            Verdict::Allow | Verdict::Drop => {}
+        }
+        verdict
+    }
+
+    /// Drops the buckets of clients that are gone.
+    pub fn retain(&mut self, connected: &[ClientId]) {
+        self.clients
+            .retain(|client_id, _| connected.contains(client_id));
+    }
+}
+
+impl ClientLimits {
+    fn violation(&mut self, config: &RateLimitsConfig, now: f32) -> Verdict {
+        match self.last_violation {
+            Some(last) if now - last < VIOLATION_SPACING => return Verdict::Drop,
+            Some(last) if now - last > config.forgive_seconds => self.violations = 0,
+            _ => {}
+        }
+        self.last_violation = Some(now);
+        self.violations += 1;
+        if self.violations >= config.disconnect_after {
+            Verdict::Disconnect
+        } else if self.violations > config.warnings {
+            self.throttled_until = now + config.throttle_seconds;
+            Verdict::Throttle
+        } else {
+            Verdict::Warn
+        }
+    }
+}
+
+impl std::fmt::Display for RateLimitStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} warnings, {} throttles, {} disconnects",
+            self.warnings, self.throttles, self.disconnects
+        )?;
+        for (kind, dropped) in self.dropped.iter() {
+            write!(f, "\n{} {} dropped", kind, dropped)?;
+        }
+        Ok(())
+    }
+}
@@ -1 +1,514 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_quinnet::{
+    server::{
+        certificate::CertificateRetrievalMode, ConnectionLostEvent, Endpoint, Server,
+        ServerConfiguration,
+    },
+    shared::{channel::ChannelId, ClientId},
+};
+
+use crate::{
+    lobby::{LobbyRequest, LobbyRequestEvent, Rooms},
+    protocol::{self, ClientEvent, ClientMessage, PasswordRejection, ServerMessage},
+    replay::ReplayRecorder,
+};
+
+use super::{
+    admin::{self, AdminCommandEvent, AdminSessions, BanList},
+    names,
+    password::PasswordGate,
+    rate_limit::{RateLimiter, Verdict},
+    ServerConfig,
+};
+
+/// Where client messages go once read, besides straight back to clients.
+#[derive(SystemParam)]
+pub struct ClientMessageWriters<'w> {
+    client_event: EventWriter<'w, protocol::ClientEvent>,
+    action_results: EventWriter<'w, protocol::ActionResultEvent>,
+    lobby: EventWriter<'w, LobbyRequestEvent>,
+    admin: EventWriter<'w, AdminCommandEvent>,
+}
+
+/// Who may send what, and how fast.
+#[derive(SystemParam)]
+pub struct Moderation<'w> {
+    config: Res<'w, ServerConfig>,
+    bans: Res<'w, BanList>,
+    rates: ResMut<'w, RateLimiter>,
+    passwords: ResMut<'w, PasswordGate>,
+    admins: ResMut<'w, AdminSessions>,
+    time: Res<'w, Time>,
+}
+
+pub fn handle_client_messages(
+    mut server: ResMut<Server>,
+    mut users: ResMut<protocol::Users>,
+    rooms: Res<Rooms>,
+    mut recorder: ResMut<ReplayRecorder>,
+    mut moderation: Moderation,
+    mut writers: ClientMessageWriters,
+) {
+    let endpoint = server.endpoint_mut();
+    let now = moderation.time.elapsed_seconds();
+    let clients = endpoint.clients();
+    moderation.rates.retain(&clients);
+    moderation.passwords.retain(&clients);
+    moderation.admins.retain(&clients);
+    for client_id in clients {
+        while let Some(message) = endpoint.try_receive_message_from::<ClientMessage>(client_id) {
+            // Leaving is never limited
+            let verdict = match message {
+                ClientMessage::Disconnect {} => Verdict::Allow,
+                _ => moderation.rates.check(client_id, message.kind(), now),
+            };
+            let dropped = matches!(verdict, Verdict::Drop | Verdict::Warn | Verdict::Throttle);
+            if let (true, ClientMessage::SendCommand { request_id, .. }) = (dropped, &message) {
+                // Clients wait for the reply to a command before sending the next one
+                writers.action_results.send(protocol::ActionResultEvent {
+                    client_id,
+                    request_id: *request_id,
+                    result: Err(protocol::ActionRejection::RateLimited),
+                });
+            }
+            let notice = match verdict {
+                Verdict::Allow => None,
+                Verdict::Drop => continue,
+                Verdict::Warn => Some("You are sending too fast, slow down".to_string()),
+                Verdict::Throttle => Some(format!(
+                    "You are sending too fast, your messages are ignored for {} seconds",
+                    moderation.config.rate_limits.throttle_seconds
+                )),
+                Verdict::Disconnect => {
+                    let lobby = &mut writers.lobby;
+                    let notice = "Disconnected for sending too fast";
+                    admin::kick(
+                        endpoint,
+                        &mut users,
+                        &rooms,
+                        &mut recorder,
+                        lobby,
+                        client_id,
+                        notice,
+                    );
+                    break;
+                }
+            };
+            if let Some(message) = notice {
+                warn!("Client {} is flooding: {}", client_id, message);
+                endpoint.try_send_message(client_id, ServerMessage::ServerNotice { message });
+                continue;
+            }
+            // Nothing but joining counts until the client got past the password and name checks
+            let joined = users.names.contains_key(&client_id);
+            if !joined
+                && !matches!(
+                    message,
+                    ClientMessage::Join { .. } | ClientMessage::Disconnect {}
+                )
+            {
+                debug!(
+                    "Dropped {} from client {} before it joined",
+                    message.kind(),
+                    client_id
+                );
+                continue;
+            }
+
+            match message {
+                ClientMessage::Join { name, password } => {
+                    if joined {
+                        warn!(
+                            "Received a Join from an already connected client: {}",
+                            client_id
+                        )
+                    } else if let Err(reason) =
+                        moderation
+                            .passwords
+                            .check(client_id, password.as_deref(), now)
+                    {
+                        // Never the password itself
+                        info!("Client {} was refused: {}", client_id, reason);
+                        let disconnect = matches!(
+                            reason,
+                            PasswordRejection::TooManyAttempts | PasswordRejection::Locked
+                        );
+                        endpoint.try_send_message(
+                            client_id,
+                            ServerMessage::PasswordRejected { name, reason },
+                        );
+                        if disconnect {
+                            if let Err(e) = endpoint.disconnect_client(client_id) {
+                                warn!("Failed to disconnect {}: {:?}", client_id, e);
+                            }
+                            break;
+                        }
+                    } else if moderation.bans.is_banned(&name) {
+                        info!("{} is banned, disconnecting {}", name, client_id);
+                        endpoint.try_send_message(
+                            client_id,
+                            ServerMessage::ServerNotice {
+                                message: "You are banned from this server".to_string(),
+                            },
+                        );
+                        if let Err(e) = endpoint.disconnect_client(client_id) {
+                            warn!("Failed to disconnect {}: {:?}", client_id, e);
+                        }
+                        break;
+                    } else if let Err(reason) =
+                        names::validate_name(&name, &moderation.config.names, &users)
+                    {
+                        info!(
+                            "Client {} can't be called {:?}: {}",
+                            client_id, name, reason
+                        );
+                        endpoint.try_send_message(
+                            client_id,
+                            ServerMessage::NameRejected { name, reason },
+                        );
+                    } else {
+                        info!("{} connected", name);
+                        users.names.insert(client_id, name.clone());
+                        // Initialize this client with existing state
+                        endpoint
+                            .send_message(
+                                client_id,
+                                ServerMessage::InitClient {
+                                    client_id: client_id,
+                                    usernames: users.names.clone(),
+                                },
+                            )
+                            .unwrap();
+                        // Everyone learns the name, only the lobby hears about the arrival
+                        let named = ServerMessage::UserListUpdate {
+                            client_id,
+                            username: Some(name.clone()),
+                        };
+                        recorder.record_all(&named);
+                        endpoint.try_send_group_message(users.names.keys(), named);
+                        let connected = ServerMessage::ClientConnected {
+                            client_id: client_id,
+                            username: name.clone(),
+                        };
+                        endpoint.try_send_group_message(
+                            rooms.audience_of(client_id, &users).iter(),
+                            connected,
+                        );
+                        // The map and player come once the client picks a room
+                        writers.lobby.send(LobbyRequestEvent {
+                            client_id,
+                            request: LobbyRequest::ListRooms,
+                        });
+                        // Unless a restored match kept a seat under this name
+                        writers.lobby.send(LobbyRequestEvent {
+                            client_id,
+                            request: LobbyRequest::Resume { username: name },
+                        });
+                    }
+                }
+                ClientMessage::Disconnect {} => {
+                    // We tell the server to disconnect this user
+                    endpoint.disconnect_client(client_id).unwrap();
+                    handle_disconnect(
+                        endpoint,
+                        &mut users,
+                        &rooms,
+                        &mut recorder,
+                        &mut writers.lobby,
+                        client_id,
+                    );
+                }
+                ClientMessage::ChatMessage { message } if message.starts_with("/login ") => {
+                    if moderation.config.admin.password.is_none() {
+                        let message = "This server has no admin password".to_string();
+                        endpoint
+                            .try_send_message(client_id, ServerMessage::ServerNotice { message });
+                        continue;
+                    }
+                    let given = message["/login ".len()..].trim();
+                    // Never the password itself
+                    let result = moderation.admins.login(client_id, given, now);
+                    match result {
+                        Ok(()) => {
+                            info!("Client {} logged in as admin", client_id);
+                            let message = "Logged in as admin".to_string();
+                            endpoint.try_send_message(
+                                client_id,
+                                ServerMessage::ServerNotice { message },
+                            );
+                        }
+                        Err(
+                            reason @ (PasswordRejection::TooManyAttempts
+                            | PasswordRejection::Locked),
+                        ) => {
+                            info!("Client {} failed to log in as admin: {}", client_id, reason);
+                            let lobby = &mut writers.lobby;
+                            let notice = format!("Admin login refused: {}", reason);
+                            admin::kick(
+                                endpoint,
+                                &mut users,
+                                &rooms,
+                                &mut recorder,
+                                lobby,
+                                client_id,
+                                &notice,
+                            );
+                            break;
+                        }
+                        Err(reason) => {
+                            info!("Client {} failed to log in as admin: {}", client_id, reason);
+                            let message = format!("Admin login refused: {}", reason);
+                            endpoint.try_send_message(
+                                client_id,
+                                ServerMessage::ServerNotice { message },
+                            );
+                        }
+                    }
+                }
+                ClientMessage::ChatMessage { message } if message.starts_with('/') => {
+                    if moderation.admins.is_admin(client_id) {
+                        writers.admin.send(AdminCommandEvent {
+                            issuer: Some(client_id),
+                            line: message[1..].to_string(),
+                        });
+                    } else {
+                        endpoint.try_send_message(
+                            client_id,
+                            ServerMessage::ServerNotice {
+                                message:
+                                    "Only admins can use server commands, /login <password> first"
+                                        .to_string(),
+                            },
+                        );
+                    }
+                }
+                ClientMessage::ChatMessage { message } => {
+                    info!(
+                        "Chat message | {:?}: {}",
+                        users.names.get(&client_id),
+                        message
+                    );
+                    let chat = ServerMessage::ChatMessage {
+                        client_id: client_id,
+                        message: message,
+                    };
+                    if let Some(room) = rooms.room_of(client_id) {
+                        recorder.record(room, &chat);
+                    }
+                    endpoint.try_send_group_message_on(
+                        rooms.audience_of(client_id, &users).iter(),
+                        ChannelId::UnorderedReliable,
+                        chat,
+                    );
+                }
+                ClientMessage::Whisper { to, message } => {
+                    if !users.names.contains_key(&to) {
+                        warn!("Whisper from {} to an unknown client_id: {}", client_id, to);
+                        continue;
+                    }
+                    let whisper = ServerMessage::Whisper {
+                        from: client_id,
+                        to,
+                        message,
+                    };
+                    let mut audience = vec![client_id, to];
+                    audience.dedup();
+                    endpoint.try_send_group_message_on(
+                        audience.iter(),
+                        ChannelId::UnorderedReliable,
+                        whisper,
+                    );
+                }
+                ClientMessage::SendCommand {
+                    request_id,
+                    command,
+                } => match rooms.seat_of(client_id) {
+                    Some(room) => writers.client_event.send(ClientEvent {
+                        request_id,
+                        command,
+                        client_id,
+                        room,
+                    }),
+                    None => writers.action_results.send(protocol::ActionResultEvent {
+                        client_id,
+                        request_id,
+                        result: Err(protocol::ActionRejection::NotPlaying),
+                    }),
+                },
+                ClientMessage::ListRooms {} => writers.lobby.send(LobbyRequestEvent {
+                    client_id,
+                    request: LobbyRequest::ListRooms,
+                }),
+                ClientMessage::CreateRoom { name } => writers.lobby.send(LobbyRequestEvent {
+                    client_id,
+                    request: LobbyRequest::CreateRoom { name },
+                }),
+                ClientMessage::JoinRoom { room } => writers.lobby.send(LobbyRequestEvent {
+                    client_id,
+                    request: LobbyRequest::JoinRoom { room },
+                }),
+                ClientMessage::SpectateRoom { room } => writers.lobby.send(LobbyRequestEvent {
+                    client_id,
+                    request: LobbyRequest::SpectateRoom { room },
+                }),
+                ClientMessage::LeaveRoom {} => writers.lobby.send(LobbyRequestEvent {
+                    client_id,
+                    request: LobbyRequest::LeaveRoom,
+                }),
+                ClientMessage::ChooseColor { color } => writers.lobby.send(LobbyRequestEvent {
+                    client_id,
+                    request: LobbyRequest::ChooseColor { color },
+                }),
+                ClientMessage::SetReady { ready } => writers.lobby.send(LobbyRequestEvent {
+                    client_id,
+                    request: LobbyRequest::SetReady { ready },
+                }),
+                ClientMessage::SetLevel { level } => writers.lobby.send(LobbyRequestEvent {
+                    client_id,
+                    request: LobbyRequest::SetLevel { level },
+                }),
+                ClientMessage::StartMatch {} => writers.lobby.send(LobbyRequestEvent {
+                    client_id,
+                    request: LobbyRequest::StartMatch,
+                }),
+            }
+        }
+    }
+}
+
+pub fn handle_server_events(
+    mut connection_lost_events: EventReader<ConnectionLostEvent>,
+    mut server: ResMut<Server>,
+    mut users: ResMut<protocol::Users>,
+    rooms: Res<Rooms>,
+    mut recorder: ResMut<ReplayRecorder>,
+    mut lobby: EventWriter<LobbyRequestEvent>,
+) {
+    // The server signals us about users that lost connection
+    for client in connection_lost_events.iter() {
+        handle_disconnect(
+            server.endpoint_mut(),
+            &mut users,
+            &rooms,
+            &mut recorder,
+            &mut lobby,
+            client.id,
+        );
+    }
+}
+
+/// Shared disconnection behaviour, whether the client lost connection, asked to disconnect
+/// or was kicked
+pub(super) fn handle_disconnect(
+    endpoint: &mut Endpoint,
+    users: &mut ResMut<protocol::Users>,
+    rooms: &Rooms,
+    recorder: &mut ReplayRecorder,
+    lobby: &mut EventWriter<LobbyRequestEvent>,
+    client_id: ClientId,
+) {
+    // Remove this user
+    if let Some(username) = users.names.remove(&client_id) {
+        // Free its spot in whatever room it was in
+        lobby.send(LobbyRequestEvent {
+            client_id,
+            request: LobbyRequest::LeaveRoom,
+        });
+        // Its room, or the lobby, hears about it, everyone else just forgets the name
+        let disconnected = ServerMessage::ClientDisconnected {
+            client_id: client_id,
+        };
+        if let Some(room) = rooms.room_of(client_id) {
+            recorder.record(room, &disconnected);
+        }
+        let audience = rooms.audience_of(client_id, users);
+        endpoint.try_send_group_message(
+            audience.iter().filter(|user| **user != client_id),
+            disconnected,
+        );
+        let unnamed = ServerMessage::UserListUpdate {
+            client_id,
+            username: None,
+        };
+        recorder.record_all(&unnamed);
+        endpoint.try_send_group_message(users.names.keys(), unnamed);
+        info!("{} disconnected", username);
+    } else {
+        warn!(
+            "Received a Disconnect from an unknown or disconnected client: {}",
+            client_id
+        )
+    }
+}
+
+/// Replies to every client action handled this update, bots don't get one.
+pub fn send_action_results(
+    mut results: EventReader<protocol::ActionResultEvent>,
+    mut server: ResMut<Server>,
+    users: Res<protocol::Users>,
+) {
+    let endpoint = server.endpoint_mut();
+    for result in results.iter() {
+        if !users.names.contains_key(&result.client_id) {
+            continue;
+        }
+        let request_id = result.request_id;
+        let reply = match &result.result {
+            Ok(()) => ServerMessage::ActionAccepted { request_id },
+            Err(reason) => ServerMessage::ActionRejected {
+                request_id,
+                reason: reason.clone(),
+            },
+        };
+        endpoint.try_send_message(result.client_id, reply);
+    }
+}
+
+#[derive(Resource)]
+pub struct PlayerChannel(pub ChannelId);
+
+pub fn start_listening(
+    mut server: ResMut<Server>,
+    mut commands: Commands,
+    config: Res<ServerConfig>,
+) {
+    let certificate = &config.certificate;
+    let mode = if certificate.generate {
+        CertificateRetrievalMode::LoadFromFileOrGenerateSelfSigned {
+            cert_file: certificate.cert_file.clone(),
+            key_file: certificate.key_file.clone(),
+            save_on_disk: certificate.save,
+            server_hostname: certificate.hostname.clone(),
+        }
+    } else {
+        CertificateRetrievalMode::LoadFromFile {
+            cert_file: certificate.cert_file.clone(),
+            key_file: certificate.key_file.clone(),
+        }
+    };
+    let certificate = server
+        .start_endpoint(
+            ServerConfiguration::from_addr(SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
+                config.port,
+            )),
+            mode,
+        )
+        .unwrap();
+    // For players to check against what their client pinned
+    info!(
+        "Certificate fingerprint: {}",
+        certificate.cert_fingerprint.to_base64()
+    );
+
+    let player_channel: PlayerChannel = PlayerChannel(
+        server
+            .endpoint_mut()
+            .open_channel(bevy_quinnet::shared::channel::ChannelType::OrderedReliable)
+            .unwrap(),
+    );
+
+    commands.insert_resource(player_channel);
+}
@@ -0,0 +1,52 @@
+use bevy::prelude::*;
+
+use super::*;
+use crate::{client::ClientConfig, players::ActionRequests};
+
+/// How long the reason of a refused action stays on screen, in seconds.
+const FEEDBACK_SECONDS: f32 = 3.0;
+
+/// Text telling the player why the server refused their last action.
+#[derive(Component)]
+pub struct ActionFeedback;
+
+pub fn setup(mut commands: Commands, config: Res<ClientConfig>, assets: Res<UiAssets>) {
+    if config.replay.is_some() || config.edit.is_some() {
+        return;
+    }
+    let style = TextStyle {
+        color: ERROR_COLOR,
+        ..text_style(&assets)
+    };
+    commands.spawn((
+        TextBundle::from_section("", style).with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                right: Val::Px(10.0),
+                bottom: Val::Px(10.0),
+                ..default()
+            },
+            ..default()
+        }),
+        ActionFeedback,
+    ));
+}
+
+pub fn update_action_feedback(
+    requests: Option<Res<ActionRequests>>,
+    mut feedback: Query<&mut Text, With<ActionFeedback>>,
+) {
+    let Some(requests) = requests else {
+        return;
+    };
+    let Ok(mut text) = feedback.get_single_mut() else {
+        return;
+    };
+    let message = match &requests.rejection {
+        Some(reason) if requests.rejection_age < FEEDBACK_SECONDS => reason.to_string(),
+        _ => String::new(),
+    };
+    if text.sections[0].value != message {
+        text.sections[0].value = message;
+    }
+}
@@ -0,0 +1,107 @@
+use std::net::SocketAddr;
+
+use bevy::prelude::*;
+
+use super::*;
+use crate::discovery::ServerBrowser;
+
+/// The servers found on the LAN, shown until one is picked.
+#[derive(Component)]
+pub struct BrowserPanel;
+
+#[derive(Component)]
+pub enum BrowserButton {
+    Connect(SocketAddr),
+    Refresh,
+}
+
+pub fn setup(mut commands: Commands, browser: Option<Res<ServerBrowser>>) {
+    if browser.is_none() {
+        return;
+    }
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Percent(30.0),
+                    top: Val::Percent(20.0),
+                    ..default()
+                },
+                size: Size::width(Val::Percent(40.0)),
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(10.0)),
+                ..default()
+            },
+            background_color: PANEL_COLOR.into(),
+            ..default()
+        },
+        BrowserPanel,
+    ));
+}
+
+pub fn rebuild_browser_panel(
+    mut commands: Commands,
+    browser: Option<Res<ServerBrowser>>,
+    assets: Res<UiAssets>,
+    mut panel: Query<(Entity, &mut Visibility), With<BrowserPanel>>,
+) {
+    let Some(browser) = browser else {
+        return;
+    };
+    if !browser.is_changed() {
+        return;
+    }
+    let Ok((panel, mut visibility)) = panel.get_single_mut() else {
+        return;
+    };
+    commands.entity(panel).despawn_descendants();
+    if !browser.browsing {
+        *visibility = Visibility::Hidden;
+        return;
+    }
+
+    commands.entity(panel).with_children(|parent| {
+        parent.spawn(TextBundle::from_section(
+            "Servers on the LAN",
+            text_style(&assets),
+        ));
+        if browser.servers.is_empty() {
+            parent.spawn(TextBundle::from_section(
+                "Looking for servers...",
+                text_style(&assets),
+            ));
+        }
+        for server in browser.servers.iter() {
+            let info = &server.info;
+            let label = format!(
+                "{} - {} - {}/{} players{}",
+                info.name,
+                info.level,
+                info.players,
+                info.max_players,
+                if info.password { " - password" } else { "" }
+            );
+            spawn_button(parent, &assets, &label, BrowserButton::Connect(server.addr));
+        }
+        spawn_button(parent, &assets, "Refresh", BrowserButton::Refresh);
+    });
+}
+
+pub fn handle_browser_buttons(
+    buttons: Query<(&Interaction, &BrowserButton), Changed<Interaction>>,
+    browser: Option<ResMut<ServerBrowser>>,
+) {
+    let Some(mut browser) = browser else {
+        return;
+    };
+    for (interaction, button) in buttons.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+        match button {
+            BrowserButton::Connect(addr) => browser.chosen = Some(*addr),
+            BrowserButton::Refresh => browser.refresh(),
+        }
+    }
+}
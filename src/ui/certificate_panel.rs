@@ -0,0 +1,105 @@
+use bevy::prelude::*;
+use bevy_quinnet::client::certificate::CertVerifierAction;
+
+use super::*;
+use crate::client::CertificatePrompt;
+
+/// Warns that a server's certificate changed since it was pinned.
+#[derive(Component)]
+pub struct CertificatePanel;
+
+#[derive(Component)]
+pub struct CertificateButton(CertVerifierAction);
+
+pub fn setup(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Percent(25.0),
+                    top: Val::Percent(30.0),
+                    ..default()
+                },
+                size: Size::width(Val::Percent(50.0)),
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(10.0)),
+                ..default()
+            },
+            background_color: PANEL_COLOR.into(),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        CertificatePanel,
+    ));
+}
+
+pub fn rebuild_certificate_panel(
+    mut commands: Commands,
+    prompt: Res<CertificatePrompt>,
+    assets: Res<UiAssets>,
+    mut panel: Query<(Entity, &mut Visibility), With<CertificatePanel>>,
+) {
+    if !prompt.is_changed() {
+        return;
+    }
+    let Ok((panel, mut visibility)) = panel.get_single_mut() else {
+        return;
+    };
+    commands.entity(panel).despawn_descendants();
+    let Some(interaction) = &prompt.pending else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+    *visibility = Visibility::Inherited;
+
+    let info = &interaction.info;
+    let known = info
+        .known_fingerprint
+        .as_ref()
+        .map(|fingerprint| fingerprint.to_base64())
+        .unwrap_or_default();
+    let warning = format!(
+        "The certificate of {:?} changed since it was pinned. \
+        Someone may be intercepting the connection, or the server got a new certificate.",
+        info.server_name
+    );
+    let fingerprints = format!("Pinned: {}\nNow: {}", known, info.fingerprint.to_base64());
+    commands.entity(panel).with_children(|parent| {
+        let style = TextStyle {
+            color: ERROR_COLOR,
+            ..text_style(&assets)
+        };
+        parent.spawn(TextBundle::from_section(warning, style));
+        parent.spawn(TextBundle::from_section(fingerprints, text_style(&assets)));
+        spawn_button(
+            parent,
+            &assets,
+            "Disconnect",
+            CertificateButton(CertVerifierAction::AbortConnection),
+        );
+        spawn_button(
+            parent,
+            &assets,
+            "Trust this time",
+            CertificateButton(CertVerifierAction::TrustOnce),
+        );
+        spawn_button(
+            parent,
+            &assets,
+            "Trust and pin the new certificate",
+            CertificateButton(CertVerifierAction::TrustAndStore),
+        );
+    });
+}
+
+pub fn handle_certificate_buttons(
+    buttons: Query<(&Interaction, &CertificateButton), Changed<Interaction>>,
+    mut prompt: ResMut<CertificatePrompt>,
+) {
+    for (interaction, CertificateButton(action)) in buttons.iter() {
+        if *interaction == Interaction::Clicked {
+            prompt.answer(*action);
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use bevy::{prelude::*, window::ReceivedCharacter};
+use bevy_quinnet::client::Client;
+
+use super::*;
+use crate::{
+    chat::{self, ChatLineKind, ChatLog},
+    client::ClientConfig,
+    protocol,
+};
+
+/// Lines of the scrollback shown at once, PageUp and PageDown scroll by as many.
+const VISIBLE_LINES: usize = 8;
+const WHISPER_COLOR: Color = Color::rgb(0.9, 0.6, 1.0);
+const SYSTEM_COLOR: Color = Color::rgb(0.6, 0.6, 0.6);
+
+/// The scrollback, one text section per line.
+#[derive(Component)]
+pub struct ChatLines;
+
+/// What is being typed, or how to start typing.
+#[derive(Component)]
+pub struct ChatInput;
+
+pub fn setup(mut commands: Commands, config: Res<ClientConfig>, assets: Res<UiAssets>) {
+    // Replays show the chat that was recorded, the editor has nobody to talk to
+    if config.edit.is_some() {
+        return;
+    }
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(10.0),
+                    bottom: Val::Px(60.0),
+                    ..default()
+                },
+                flex_direction: FlexDirection::Column,
+                max_size: Size::width(Val::Px(500.0)),
+                padding: UiRect::all(Val::Px(6.0)),
+                ..default()
+            },
+            background_color: PANEL_COLOR.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn((TextBundle::default(), ChatLines));
+            if config.replay.is_none() {
+                let style = TextStyle {
+                    color: SYSTEM_COLOR,
+                    ..text_style(&assets)
+                };
+                parent.spawn((TextBundle::from_section("", style), ChatInput));
+            }
+        });
+}
+
+/// Enter opens the chat input and sends what was typed, Escape closes it without sending.
+pub fn type_chat(
+    keys: Res<Input<KeyCode>>,
+    mut characters: EventReader<ReceivedCharacter>,
+    mut log: ResMut<ChatLog>,
+    users: Res<protocol::Users>,
+    client: Res<Client>,
+) {
+    let typed: String = characters
+        .iter()
+        .map(|character| character.char)
+        .filter(|character| !character.is_control())
+        .collect();
+    let Some(connection) = client.get_connection() else {
+        return;
+    };
+
+    if keys.just_pressed(KeyCode::PageUp) {
+        let top = log.lines.len().saturating_sub(VISIBLE_LINES);
+        log.scroll = (log.scroll + VISIBLE_LINES).min(top);
+    }
+    if keys.just_pressed(KeyCode::PageDown) {
+        log.scroll = log.scroll.saturating_sub(VISIBLE_LINES);
+    }
+
+    if log.input.is_none() {
+        if keys.just_pressed(KeyCode::Return) {
+            log.input = Some(String::new());
+        }
+        return;
+    }
+    if keys.just_pressed(KeyCode::Escape) {
+        log.input = None;
+        return;
+    }
+    if keys.just_pressed(KeyCode::Return) {
+        let input = log.input.take().unwrap_or_default();
+        match chat::parse_input(&input, &users) {
+            Ok(Some(message)) => {
+                if connection.send_message(message).is_err() {
+                    log.system("The message could not be sent");
+                }
+            }
+            Ok(None) => {}
+            Err(error) => log.system(error),
+        }
+        log.scroll = 0;
+        return;
+    }
+    if keys.just_pressed(KeyCode::Back) || !typed.is_empty() {
+        let input = log.input.get_or_insert_with(String::new);
+        if keys.just_pressed(KeyCode::Back) {
+            input.pop();
+        }
+        input.push_str(&typed);
+    }
+}
+
+pub fn update_chat_panel(
+    log: Res<ChatLog>,
+    assets: Res<UiAssets>,
+    mut lines: Query<&mut Text, With<ChatLines>>,
+    mut input: Query<&mut Text, (With<ChatInput>, Without<ChatLines>)>,
+) {
+    if !log.is_changed() {
+        return;
+    }
+    if let Ok(mut text) = lines.get_single_mut() {
+        let visible: Vec<_> = log
+            .lines
+            .iter()
+            .rev()
+            .skip(log.scroll)
+            .take(VISIBLE_LINES)
+            .collect();
+        text.sections = visible
+            .into_iter()
+            .rev()
+            .enumerate()
+            .map(|(i, line)| {
+                let color = match line.kind {
+                    ChatLineKind::Message { .. } => Color::WHITE,
+                    ChatLineKind::Whisper { .. } => WHISPER_COLOR,
+                    ChatLineKind::System => SYSTEM_COLOR,
+                };
+                let separator = if i == 0 { "" } else { "\n" };
+                TextSection::new(
+                    format!("{}{}", separator, line),
+                    TextStyle {
+                        color,
+                        ..text_style(&assets)
+                    },
+                )
+            })
+            .collect();
+    }
+    if let Ok(mut text) = input.get_single_mut() {
+        let (value, color) = match &log.input {
+            Some(typed) => (format!("> {}_", typed), Color::WHITE),
+            None => (
+                "Enter to chat, /w <name> <message> to whisper".to_string(),
+                SYSTEM_COLOR,
+            ),
+        };
+        text.sections[0].value = value;
+        text.sections[0].style.color = color;
+    }
+}
@@ -0,0 +1,100 @@
+use bevy::prelude::*;
+
+use super::*;
+use crate::{
+    client::ClientConfig,
+    editor::{EditorAction, EditorTool, LevelEditor, Picked},
+    map::level::MANA_COLORS,
+};
+
+/// Text showing the tool in use and the outcome of the last action.
+#[derive(Component)]
+pub struct EditorStatus;
+
+#[derive(Component)]
+pub struct EditorButton(EditorAction);
+
+pub fn setup(mut commands: Commands, config: Res<ClientConfig>, assets: Res<UiAssets>) {
+    if config.edit.is_none() {
+        return;
+    }
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(10.0),
+                    top: Val::Px(10.0),
+                    ..default()
+                },
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(6.0)),
+                ..default()
+            },
+            background_color: PANEL_COLOR.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            for (i, tool) in EditorTool::ALL.into_iter().enumerate() {
+                let label = format!("{} {}", i + 1, tool.name());
+                spawn_button(
+                    parent,
+                    &assets,
+                    &label,
+                    EditorButton(EditorAction::Tool(tool)),
+                );
+            }
+            let actions = [
+                ("C Color", EditorAction::NextColor),
+                ("Undo", EditorAction::Undo),
+                ("Redo", EditorAction::Redo),
+                ("Save", EditorAction::Save),
+            ];
+            for (label, action) in actions {
+                spawn_button(parent, &assets, label, EditorButton(action));
+            }
+            parent.spawn((
+                TextBundle::from_section("", text_style(&assets)),
+                EditorStatus,
+            ));
+        });
+}
+
+pub fn update_editor_status(
+    editor: Option<Res<LevelEditor>>,
+    mut status: Query<&mut Text, With<EditorStatus>>,
+) {
+    let Some(editor) = editor else {
+        return;
+    };
+    let Ok(mut text) = status.get_single_mut() else {
+        return;
+    };
+    let pending = match editor.pending {
+        Some(Picked::Vertex(id)) => format!(", from vertex {}", id),
+        Some(Picked::Mana(id)) => format!(", from mana {}", id),
+        _ => String::new(),
+    };
+    text.sections[0].value = format!(
+        "{}\n{} ({}{})\n{}",
+        editor.level.name,
+        editor.tool.name(),
+        MANA_COLORS[editor.mana_color],
+        pending,
+        editor.status
+    );
+}
+
+pub fn handle_editor_buttons(
+    buttons: Query<(&Interaction, &EditorButton), Changed<Interaction>>,
+    editor: Option<ResMut<LevelEditor>>,
+) {
+    let Some(mut editor) = editor else {
+        return;
+    };
+    for (interaction, EditorButton(action)) in buttons.iter() {
+        if *interaction == Interaction::Clicked {
+            editor.apply(*action);
+        }
+    }
+}
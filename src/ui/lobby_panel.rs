@@ -0,0 +1,246 @@
+use bevy::prelude::*;
+use bevy_quinnet::client::Client;
+
+use super::*;
+use crate::{
+    client::ClientConfig,
+    lobby::LobbyState,
+    protocol::{self, ClientMessage, PlayerColor, RoomId, RoomInfo, RoomPhase},
+};
+
+/// Root node of the room list, or of the current room once joined.
+#[derive(Component)]
+pub struct LobbyPanel;
+
+#[derive(Component)]
+pub enum LobbyButton {
+    Join(RoomId),
+    Spectate(RoomId),
+    Create,
+    Refresh,
+    Leave,
+    Color(PlayerColor),
+    Ready(bool),
+    Level(String),
+    Start,
+}
+
+pub fn setup(mut commands: Commands, config: Res<ClientConfig>) {
+    // Replays and the editor have no lobby to talk to
+    if config.replay.is_some() || config.edit.is_some() {
+        return;
+    }
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(10.0),
+                    top: Val::Px(10.0),
+                    ..default()
+                },
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(6.0)),
+                ..default()
+            },
+            background_color: PANEL_COLOR.into(),
+            ..default()
+        },
+        LobbyPanel,
+    ));
+}
+
+pub fn rebuild_lobby_panel(
+    mut commands: Commands,
+    lobby: Res<LobbyState>,
+    users: Res<protocol::Users>,
+    assets: Res<UiAssets>,
+    panel: Query<Entity, With<LobbyPanel>>,
+) {
+    if !lobby.is_changed() && !users.is_changed() {
+        return;
+    }
+    let Ok(panel) = panel.get_single() else {
+        return;
+    };
+
+    commands.entity(panel).despawn_descendants();
+    commands
+        .entity(panel)
+        .with_children(|parent| match lobby.current_room_info() {
+            Some(room) => spawn_room(parent, &assets, room, &lobby.levels, &users),
+            None => {
+                parent.spawn(TextBundle::from_section("Rooms", text_style(&assets)));
+                if let Some(rejection) = &lobby.rejection {
+                    parent.spawn(TextBundle::from_section(
+                        rejection.to_string(),
+                        TextStyle {
+                            color: ERROR_COLOR,
+                            ..text_style(&assets)
+                        },
+                    ));
+                }
+                for room in lobby.rooms.iter() {
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::Row,
+                                ..default()
+                            },
+                            ..default()
+                        })
+                        .with_children(|row| {
+                            spawn_button(
+                                row,
+                                &assets,
+                                &format!(
+                                    "{} - {}/{} players, {}",
+                                    room.name,
+                                    room.players,
+                                    room.max_players,
+                                    phase_name(room.phase)
+                                ),
+                                LobbyButton::Join(room.id),
+                            );
+                            spawn_button(row, &assets, "Watch", LobbyButton::Spectate(room.id));
+                        });
+                }
+                spawn_button(parent, &assets, "Create room", LobbyButton::Create);
+                spawn_button(parent, &assets, "Refresh", LobbyButton::Refresh);
+            }
+        });
+}
+
+fn phase_name(phase: RoomPhase) -> &'static str {
+    match phase {
+        RoomPhase::Waiting => "waiting",
+        RoomPhase::Loading => "loading",
+        RoomPhase::Playing => "playing",
+    }
+}
+
+/// The seats of the current room, with the ready-up controls while it is waiting.
+fn spawn_room(
+    parent: &mut ChildBuilder,
+    assets: &UiAssets,
+    room: &RoomInfo,
+    levels: &[String],
+    users: &protocol::Users,
+) {
+    parent.spawn(TextBundle::from_section(
+        format!("{} - {}, {}", room.name, room.level, phase_name(room.phase)),
+        text_style(assets),
+    ));
+    for seat in room.seats.iter() {
+        let name = users
+            .names
+            .get(&seat.client_id)
+            .map(String::as_str)
+            .unwrap_or("?");
+        let host = if room.host == Some(seat.client_id) {
+            " (host)"
+        } else {
+            ""
+        };
+        let ready = if seat.ready { "ready" } else { "not ready" };
+        parent.spawn(TextBundle::from_section(
+            format!("{}{}: {}", name, host, ready),
+            TextStyle {
+                color: seat.color.tint(),
+                ..text_style(assets)
+            },
+        ));
+    }
+
+    if !room.spectators.is_empty() {
+        parent.spawn(TextBundle::from_section(
+            format!("{} watching", room.spectators.len()),
+            text_style(assets),
+        ));
+    }
+    if room.spectators.contains(&users.self_id) {
+        parent.spawn(TextBundle::from_section(
+            "Spectating, Tab follows the next player",
+            text_style(assets),
+        ));
+        if room.players < room.max_players {
+            spawn_button(parent, assets, "Play", LobbyButton::Join(room.id));
+        }
+    }
+
+    let me = room
+        .seats
+        .iter()
+        .find(|seat| seat.client_id == users.self_id);
+    if let (RoomPhase::Waiting, Some(me)) = (room.phase, me) {
+        spawn_button(
+            parent,
+            assets,
+            &format!("Color: {:?}", me.color),
+            LobbyButton::Color(me.color.next()),
+        );
+        spawn_button(
+            parent,
+            assets,
+            if me.ready { "Not ready" } else { "Ready" },
+            LobbyButton::Ready(!me.ready),
+        );
+        if room.host == Some(users.self_id) {
+            let next_level = levels
+                .iter()
+                .position(|level| *level == room.level)
+                .map_or(0, |index| index + 1);
+            if let Some(level) = levels.get(next_level % levels.len().max(1)) {
+                spawn_button(
+                    parent,
+                    assets,
+                    &format!("Level: {}", room.level),
+                    LobbyButton::Level(level.clone()),
+                );
+            }
+            if room.seats.iter().all(|seat| seat.ready) {
+                spawn_button(parent, assets, "Start", LobbyButton::Start);
+            }
+        }
+    }
+    spawn_button(parent, assets, "Leave", LobbyButton::Leave);
+}
+
+pub fn handle_lobby_buttons(
+    buttons: Query<(&Interaction, &LobbyButton), Changed<Interaction>>,
+    client: Res<Client>,
+    users: Res<protocol::Users>,
+) {
+    for (interaction, button) in buttons.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+        let message = match button {
+            LobbyButton::Join(room) => ClientMessage::JoinRoom { room: *room },
+            LobbyButton::Spectate(room) => ClientMessage::SpectateRoom { room: *room },
+            LobbyButton::Create => ClientMessage::CreateRoom {
+                name: format!(
+                    "{}'s room",
+                    users
+                        .names
+                        .get(&users.self_id)
+                        .map(String::as_str)
+                        .unwrap_or("Someone")
+                ),
+            },
+            LobbyButton::Refresh => ClientMessage::ListRooms {},
+            LobbyButton::Leave => ClientMessage::LeaveRoom {},
+            LobbyButton::Color(color) => ClientMessage::ChooseColor { color: *color },
+            LobbyButton::Ready(ready) => ClientMessage::SetReady { ready: *ready },
+            LobbyButton::Level(level) => ClientMessage::SetLevel {
+                level: level.clone(),
+            },
+            LobbyButton::Start => ClientMessage::StartMatch {},
+        };
+        if let Some(connection) = client.get_connection() {
+            if let Err(e) = connection.send_message(message) {
+                warn!("Failed to send lobby request: {:?}", e);
+            }
+        }
+    }
+}
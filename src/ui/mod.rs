@@ -0,0 +1,121 @@
+use bevy::prelude::*;
+
+use crate::client;
+
+mod action_feedback;
+mod browser_panel;
+mod certificate_panel;
+mod chat_panel;
+mod editor_panel;
+mod lobby_panel;
+mod name_panel;
+mod replay_panel;
+
+pub const FONT_SIZE: f32 = 18.0;
+pub const PANEL_COLOR: Color = Color::rgba(0.1, 0.1, 0.1, 0.8);
+pub const BUTTON_COLOR: Color = Color::rgb(0.2, 0.2, 0.2);
+pub const BUTTON_HOVERED_COLOR: Color = Color::rgb(0.3, 0.3, 0.3);
+pub const BUTTON_PRESSED_COLOR: Color = Color::rgb(0.4, 0.5, 0.4);
+pub const ERROR_COLOR: Color = Color::rgb(1.0, 0.4, 0.4);
+
+/// Screen space panels of the client, drawn on top of the map.
+pub struct ClientUiPlugin;
+
+impl Plugin for ClientUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(setup)
+            .add_startup_system(lobby_panel::setup)
+            .add_system(button_colors)
+            .add_system(lobby_panel::rebuild_lobby_panel)
+            .add_system(lobby_panel::handle_lobby_buttons)
+            // The fonts are only there once `setup`'s commands ran
+            .add_startup_system(replay_panel::setup.in_base_set(StartupSet::PostStartup))
+            .add_system(replay_panel::update_replay_status)
+            .add_system(replay_panel::handle_replay_buttons)
+            .add_startup_system(editor_panel::setup.in_base_set(StartupSet::PostStartup))
+            .add_system(editor_panel::update_editor_status)
+            .add_system(editor_panel::handle_editor_buttons)
+            .add_startup_system(action_feedback::setup.in_base_set(StartupSet::PostStartup))
+            .add_system(action_feedback::update_action_feedback)
+            .add_startup_system(chat_panel::setup.in_base_set(StartupSet::PostStartup))
+            .add_system(
+                chat_panel::type_chat
+                    .run_if(client::join_prompt_closed)
+                    .before(chat_panel::update_chat_panel),
+            )
+            .add_system(chat_panel::update_chat_panel)
+            .add_startup_system(browser_panel::setup)
+            .add_system(browser_panel::rebuild_browser_panel)
+            .add_system(browser_panel::handle_browser_buttons)
+            .add_startup_system(certificate_panel::setup)
+            .add_system(certificate_panel::rebuild_certificate_panel)
+            .add_system(certificate_panel::handle_certificate_buttons)
+            .add_startup_system(name_panel::open_name_entry.in_base_set(StartupSet::PreStartup))
+            .add_startup_system(name_panel::setup.in_base_set(StartupSet::PostStartup))
+            // After the chat, so the Enter sending the name doesn't open the chat input too
+            .add_system(name_panel::type_name.after(chat_panel::type_chat))
+            .add_system(name_panel::type_password.after(chat_panel::type_chat))
+            .add_system(
+                name_panel::update_name_panel
+                    .after(name_panel::type_name)
+                    .after(name_panel::type_password),
+            );
+    }
+}
+
+#[derive(Resource)]
+pub struct UiAssets {
+    pub font: Handle<Font>,
+}
+
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(UiAssets {
+        font: asset_server.load("fonts/DejaVuSans.ttf"),
+    });
+}
+
+type ButtonChanged = (Changed<Interaction>, With<Button>);
+
+fn button_colors(mut buttons: Query<(&Interaction, &mut BackgroundColor), ButtonChanged>) {
+    for (interaction, mut color) in buttons.iter_mut() {
+        *color = match interaction {
+            Interaction::Clicked => BUTTON_PRESSED_COLOR,
+            Interaction::Hovered => BUTTON_HOVERED_COLOR,
+            Interaction::None => BUTTON_COLOR,
+        }
+        .into();
+    }
+}
+
+pub fn text_style(assets: &UiAssets) -> TextStyle {
+    TextStyle {
+        font: assets.font.clone(),
+        font_size: FONT_SIZE,
+        color: Color::WHITE,
+    }
+}
+
+/// A text button tagged with `marker`, to be found again when it is clicked.
+pub fn spawn_button(
+    parent: &mut ChildBuilder,
+    assets: &UiAssets,
+    label: &str,
+    marker: impl Component,
+) {
+    parent
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    padding: UiRect::all(Val::Px(4.0)),
+                    margin: UiRect::vertical(Val::Px(2.0)),
+                    ..default()
+                },
+                background_color: BUTTON_COLOR.into(),
+                ..default()
+            },
+            marker,
+        ))
+        .with_children(|button| {
+            button.spawn(TextBundle::from_section(label, text_style(assets)));
+        });
+}
@@ -0,0 +1,201 @@
+use bevy::{prelude::*, window::ReceivedCharacter};
+use bevy_quinnet::client::Client;
+
+use super::*;
+use crate::{
+    client::{ClientConfig, NameEntry, PasswordEntry},
+    protocol::{self, ClientMessage},
+};
+
+/// The box in the middle of the screen, only visible while a name or password is typed.
+#[derive(Component)]
+pub struct NamePanel;
+
+/// What is asked for, the name or the password.
+#[derive(Component)]
+pub struct NameTitle;
+
+#[derive(Component)]
+pub struct NameInput;
+
+/// Why the server refused the last name or password.
+#[derive(Component)]
+pub struct NameError;
+
+/// Without `--name` the name is asked for instead of joining under a random one. The server
+/// browser asks once a server is picked.
+pub fn open_name_entry(config: Res<ClientConfig>, mut entry: ResMut<NameEntry>) {
+    let connects = config.replay.is_none() && config.edit.is_none() && !config.browse;
+    if config.name.is_none() && connects {
+        entry.editing = Some(String::new());
+    }
+}
+
+pub fn setup(mut commands: Commands, config: Res<ClientConfig>, assets: Res<UiAssets>) {
+    if config.replay.is_some() || config.edit.is_some() {
+        return;
+    }
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        left: Val::Percent(35.0),
+                        top: Val::Percent(40.0),
+                        ..default()
+                    },
+                    size: Size::width(Val::Percent(30.0)),
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(10.0)),
+                    ..default()
+                },
+                background_color: PANEL_COLOR.into(),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            NamePanel,
+        ))
+        .with_children(|parent| {
+            parent.spawn((TextBundle::from_section("", text_style(&assets)), NameTitle));
+            parent.spawn((TextBundle::from_section("", text_style(&assets)), NameInput));
+            let style = TextStyle {
+                color: ERROR_COLOR,
+                ..text_style(&assets)
+            };
+            parent.spawn((TextBundle::from_section("", style), NameError));
+        });
+}
+
+/// Enter joins under the typed name, the entry opens again if the server refuses it.
+pub fn type_name(
+    keys: Res<Input<KeyCode>>,
+    mut characters: EventReader<ReceivedCharacter>,
+    mut entry: ResMut<NameEntry>,
+    config: Res<ClientConfig>,
+    client: Res<Client>,
+) {
+    let typed: String = characters
+        .iter()
+        .map(|character| character.char)
+        .filter(|character| protocol::allowed_name_character(*character))
+        .collect();
+    let Some(connection) = client.get_connection() else {
+        return;
+    };
+    let Some(name) = entry.editing.as_ref() else {
+        return;
+    };
+
+    if keys.just_pressed(KeyCode::Return) {
+        if name.is_empty() {
+            return;
+        }
+        let message = ClientMessage::Join {
+            name: name.clone(),
+            password: config.password.clone(),
+        };
+        if connection.send_message(message).is_err() {
+            println!("Failed to Join Game");
+            return;
+        }
+        entry.editing = None;
+        return;
+    }
+    if keys.just_pressed(KeyCode::Back) || !typed.is_empty() {
+        if let Some(name) = entry.editing.as_mut() {
+            if keys.just_pressed(KeyCode::Back) {
+                name.pop();
+            }
+            name.push_str(&typed);
+        }
+    }
+}
+
+/// Enter joins again with the typed password, which is kept for later joins.
+pub fn type_password(
+    keys: Res<Input<KeyCode>>,
+    mut characters: EventReader<ReceivedCharacter>,
+    mut entry: ResMut<PasswordEntry>,
+    mut config: ResMut<ClientConfig>,
+    client: Res<Client>,
+) {
+    let typed: String = characters
+        .iter()
+        .map(|character| character.char)
+        .filter(|character| !character.is_control())
+        .collect();
+    let Some(connection) = client.get_connection() else {
+        return;
+    };
+    let Some(password) = entry.editing.as_ref() else {
+        return;
+    };
+
+    if keys.just_pressed(KeyCode::Return) {
+        let message = ClientMessage::Join {
+            name: entry.name.clone(),
+            password: Some(password.clone()),
+        };
+        if connection.send_message(message).is_err() {
+            println!("Failed to Join Game");
+            return;
+        }
+        config.password = entry.editing.take();
+        return;
+    }
+    if keys.just_pressed(KeyCode::Back) || !typed.is_empty() {
+        if let Some(password) = entry.editing.as_mut() {
+            if keys.just_pressed(KeyCode::Back) {
+                password.pop();
+            }
+            password.push_str(&typed);
+        }
+    }
+}
+
+type ErrorText = (With<NameError>, Without<NameInput>, Without<NameTitle>);
+
+pub fn update_name_panel(
+    entry: Res<NameEntry>,
+    password: Res<PasswordEntry>,
+    mut panel: Query<&mut Visibility, With<NamePanel>>,
+    mut title: Query<&mut Text, With<NameTitle>>,
+    mut input: Query<&mut Text, (With<NameInput>, Without<NameTitle>)>,
+    mut error: Query<&mut Text, ErrorText>,
+) {
+    if !entry.is_changed() && !password.is_changed() {
+        return;
+    }
+    // The password is asked for once a name was picked, so it goes first
+    let (heading, typed, rejection) = match (&password.editing, &entry.editing) {
+        (Some(typed), _) => (
+            format!("Password to join as {}", password.name),
+            "*".repeat(typed.chars().count()),
+            password.rejection.as_ref().map(ToString::to_string),
+        ),
+        (None, Some(typed)) => (
+            "Pick a name, then press Enter".to_string(),
+            typed.clone(),
+            entry.rejection.as_ref().map(ToString::to_string),
+        ),
+        (None, None) => {
+            if let Ok(mut visibility) = panel.get_single_mut() {
+                *visibility = Visibility::Hidden;
+            }
+            return;
+        }
+    };
+    if let Ok(mut visibility) = panel.get_single_mut() {
+        *visibility = Visibility::Inherited;
+    }
+    if let Ok(mut text) = title.get_single_mut() {
+        text.sections[0].value = heading;
+    }
+    if let Ok(mut text) = input.get_single_mut() {
+        text.sections[0].value = format!("> {}_", typed);
+    }
+    if let Ok(mut text) = error.get_single_mut() {
+        text.sections[0].value = rejection.unwrap_or_default();
+    }
+}
@@ -0,0 +1,88 @@
+use bevy::prelude::*;
+
+use super::*;
+use crate::{
+    client::ClientConfig,
+    replay::{ReplayControl, ReplayPlayback},
+};
+
+/// Text showing where the replay is at.
+#[derive(Component)]
+pub struct ReplayStatus;
+
+#[derive(Component)]
+pub struct ReplayButton(ReplayControl);
+
+pub fn setup(mut commands: Commands, config: Res<ClientConfig>, assets: Res<UiAssets>) {
+    if config.replay.is_none() {
+        return;
+    }
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(10.0),
+                    bottom: Val::Px(10.0),
+                    ..default()
+                },
+                flex_direction: FlexDirection::Row,
+                align_items: AlignItems::Center,
+                gap: Size::width(Val::Px(4.0)),
+                padding: UiRect::all(Val::Px(6.0)),
+                ..default()
+            },
+            background_color: PANEL_COLOR.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            let controls = [
+                ("-10s", ReplayControl::Seek(-10.0)),
+                ("Pause", ReplayControl::TogglePause),
+                ("+10s", ReplayControl::Seek(10.0)),
+                ("Slower", ReplayControl::Slower),
+                ("Faster", ReplayControl::Faster),
+            ];
+            for (label, control) in controls {
+                spawn_button(parent, &assets, label, ReplayButton(control));
+            }
+            parent.spawn((
+                TextBundle::from_section("", text_style(&assets)),
+                ReplayStatus,
+            ));
+        });
+}
+
+pub fn update_replay_status(
+    playback: Option<Res<ReplayPlayback>>,
+    mut status: Query<&mut Text, With<ReplayStatus>>,
+) {
+    let Some(playback) = playback else {
+        return;
+    };
+    let Ok(mut text) = status.get_single_mut() else {
+        return;
+    };
+    text.sections[0].value = format!(
+        "{} {:.0}s / {:.0}s x{}{}",
+        playback.header.room.name,
+        playback.time,
+        playback.duration(),
+        playback.speed,
+        if playback.paused { " (paused)" } else { "" }
+    );
+}
+
+pub fn handle_replay_buttons(
+    buttons: Query<(&Interaction, &ReplayButton), Changed<Interaction>>,
+    playback: Option<ResMut<ReplayPlayback>>,
+) {
+    let Some(mut playback) = playback else {
+        return;
+    };
+    for (interaction, ReplayButton(control)) in buttons.iter() {
+        if *interaction == Interaction::Clicked {
+            playback.apply(*control);
+        }
+    }
+}
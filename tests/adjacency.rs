@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+
+use bevy::prelude::Entity;
+use catan::{
+    map::{
+        self,
+        adjacency::{self, SpawnedMapObject},
+        EdgeClientServerLookup, MapInitData, MaterialClientServerLookup, VertexClientServerLookup,
+    },
+    protocol::RoomId,
+};
+
+/// A triangle of vertexes with a mana point linked to all three, and a tail vertex 3 off vertex 2.
+fn triangle() -> MapInitData {
+    serde_json::from_value(serde_json::json!({
+        "version": map::LEVEL_VERSION,
+        "name": "triangle",
+        "map_start_vertexes": [0],
+        "vertex_positions": [
+            {"x": 0.0, "y": 0.0},
+            {"x": 100.0, "y": 0.0},
+            {"x": 50.0, "y": 80.0},
+            {"x": 50.0, "y": 180.0}
+        ],
+        "vertex_connections": [{"a": 0, "b": 1}, {"a": 1, "b": 2}, {"a": 2, "b": 0}, {"a": 2, "b": 3}],
+        "mana_points": [{"x": 50.0, "y": 30.0, "color": "red"}],
+        "mana_connections": [{"a": 0, "b": 0}, {"a": 0, "b": 1}, {"a": 0, "b": 2}]
+    }))
+    .unwrap()
+}
+
+struct Spawned {
+    objects: Vec<SpawnedMapObject>,
+    vertexes: VertexClientServerLookup,
+    edges: EdgeClientServerLookup,
+    materials: MaterialClientServerLookup,
+}
+
+fn spawn(rooms: &[RoomId], level: &MapInitData) -> Spawned {
+    let mut spawned = Spawned {
+        objects: Vec::new(),
+        vertexes: VertexClientServerLookup(HashMap::new()),
+        edges: EdgeClientServerLookup(HashMap::new()),
+        materials: MaterialClientServerLookup(HashMap::new()),
+    };
+    for room in rooms {
+        for spawn in map::level_spawn_events(*room, level) {
+            let entity = Entity::from_raw(spawned.objects.len() as u32);
+            let lookup = match spawn.map_type {
+                map::VERTEX => &mut spawned.vertexes.0,
+                map::EDGE => &mut spawned.edges.0,
+                _ => &mut spawned.materials.0,
+            };
+            lookup.insert((*room, spawn.map_type_id), entity);
+            spawned.objects.push(SpawnedMapObject {
+                room: spawn.room,
+                map_type: spawn.map_type,
+                id: spawn.map_type_id,
+                entity,
+                adjacencies: map::Adjacencies {
+                    vertex_list: spawn.vertex_list,
+                    edge_list: spawn.edge_list,
+                    material_list: spawn.material_list,
+                },
+                material_type: spawn.material_type,
+            });
+        }
+    }
+    spawned
+}
+
+fn sorted(mut entities: Vec<Entity>) -> Vec<Entity> {
+    entities.sort();
+    entities
+}
+
+#[test]
+fn every_kind_of_neighbor_is_linked() {
+    let room = RoomId(1);
+    let spawned = spawn(&[room], &triangle());
+    let resolved = adjacency::resolve_adjacencies(
+        &spawned.objects,
+        &spawned.vertexes,
+        &spawned.edges,
+        &spawned.materials,
+    );
+    let vertex = |id| spawned.vertexes.0[&(room, id)];
+    let edge = |id| spawned.edges.0[&(room, id)];
+    let mana = spawned.materials.0[&(room, 0)];
+    assert_eq!(resolved.len(), spawned.objects.len());
+
+    let of_vertex = &resolved[&vertex(2)];
+    assert_eq!(
+        sorted(of_vertex.vertex_list.clone()),
+        sorted(vec![vertex(0), vertex(1), vertex(3)])
+    );
+    assert_eq!(of_vertex.material_list, vec![mana]);
+
+    // Edge 1 goes from vertex 1 to 2, touching every other edge but only the triangle's mana
+    let of_edge = &resolved[&edge(1)];
+    assert_eq!(
+        sorted(of_edge.vertex_list.clone()),
+        vec![vertex(1), vertex(2)]
+    );
+    assert_eq!(
+        sorted(of_edge.edge_list.clone()),
+        sorted(vec![edge(0), edge(2), edge(3)])
+    );
+    assert_eq!(of_edge.material_list, vec![mana]);
+    assert!(resolved[&edge(3)].material_list.is_empty());
+    assert_eq!(resolved[&edge(3)].edge_list.len(), 2);
+
+    let of_mana = &resolved[&mana];
+    assert_eq!(
+        sorted(of_mana.vertex_list.clone()),
+        sorted(vec![vertex(0), vertex(1), vertex(2)])
+    );
+    assert_eq!(
+        sorted(of_mana.edge_list.clone()),
+        sorted(vec![edge(0), edge(1), edge(2)])
+    );
+}
+
+#[test]
+fn rooms_with_the_same_level_stay_apart() {
+    let rooms = [RoomId(1), RoomId(2)];
+    let spawned = spawn(&rooms, &triangle());
+    let resolved = adjacency::resolve_adjacencies(
+        &spawned.objects,
+        &spawned.vertexes,
+        &spawned.edges,
+        &spawned.materials,
+    );
+    let room_of: HashMap<Entity, RoomId> = spawned
+        .objects
+        .iter()
+        .map(|object| (object.entity, object.room))
+        .collect();
+    for object in spawned.objects.iter() {
+        let adjacencies = &resolved[&object.entity];
+        let neighbors = adjacencies
+            .vertex_list
+            .iter()
+            .chain(adjacencies.edge_list.iter())
+            .chain(adjacencies.material_list.iter());
+        for neighbor in neighbors {
+            assert_eq!(room_of[neighbor], object.room);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+mod harness;
+
+use catan::{
+    chat::ChatLog,
+    lobby,
+    protocol::{ClientMessage, RoomId, Users},
+    server::{AdminCommand, BanList, Password, ServerConfig},
+};
+use harness::TestHarness;
+
+fn connected(harness: &TestHarness, name: &str) -> bool {
+    let users = harness.server.world.resource::<Users>();
+    users.names.values().any(|user| user == name)
+}
+
+fn heard(harness: &TestHarness, index: usize, text: &str) -> bool {
+    let log = harness.clients[index].world.resource::<ChatLog>();
+    log.lines.iter().any(|line| line.text == text)
+}
+
+fn chat(harness: &mut TestHarness, index: usize, message: &str) {
+    harness.send_from_client(
+        index,
+        ClientMessage::ChatMessage {
+            message: message.to_string(),
+        },
+    );
+}
+
+#[test]
+fn parses_admin_commands() {
+    assert_eq!(
+        AdminCommand::parse(" kick mallory  too loud "),
+        Ok(AdminCommand::Kick {
+            name: "mallory".to_string(),
+            reason: "too loud".to_string()
+        })
+    );
+    assert_eq!(
+        AdminCommand::parse("restart"),
+        Ok(AdminCommand::Restart {
+            room: lobby::DEFAULT_ROOM
+        })
+    );
+    assert_eq!(
+        AdminCommand::parse("level levels/level_1.json 3"),
+        Ok(AdminCommand::Level {
+            level: "levels/level_1.json".to_string(),
+            room: RoomId(3)
+        })
+    );
+    assert!(AdminCommand::parse("restart main").is_err());
+    assert!(AdminCommand::parse("ban").is_err());
+    assert!(AdminCommand::parse("say").is_err());
+    assert!(AdminCommand::parse("shutdown").is_err());
+}
+
+#[test]
+fn admins_ban_from_the_chat_and_banned_names_stay_out() {
+    let mut config = ServerConfig::default();
+    config.admin.password = Some(Password("sesame".to_string()));
+    let mut harness = TestHarness::with_config(0, config);
+    let root = harness.add_client_named(Some("root"));
+    let mallory = harness.add_client_named(Some("mallory"));
+    assert!(harness.run_until(300, |h| connected(h, "root") && connected(h, "mallory")));
+
+    // Only admins get to run commands, whatever their name
+    chat(&mut harness, root, "/kick mallory");
+    assert!(harness.run_until(300, |h| heard(
+        h,
+        root,
+        "Server: Only admins can use server commands, /login <password> first"
+    )));
+    chat(&mut harness, mallory, "/login open");
+    assert!(harness.run_until(300, |h| heard(
+        h,
+        mallory,
+        "Server: Admin login refused: Wrong password, 2 attempts left"
+    )));
+    chat(&mut harness, mallory, "/kick root");
+    for _ in 0..30 {
+        harness.step();
+    }
+    assert!(connected(&harness, "root"));
+
+    chat(&mut harness, root, "/login sesame");
+    assert!(harness.run_until(300, |h| heard(h, root, "Server: Logged in as admin")));
+    chat(&mut harness, root, "/ban Mallory");
+    assert!(harness.run_until(300, |h| !connected(h, "mallory")));
+    assert!(harness.run_until(300, |h| heard(h, root, "Server: Banned the name Mallory")));
+    let bans = harness.server.world.resource::<BanList>();
+    assert!(bans.is_banned("mallory"));
+    let path = harness
+        .server
+        .world
+        .resource::<ServerConfig>()
+        .admin
+        .ban_list
+        .clone();
+    assert!(BanList::load(&path).is_banned("mallory"));
+
+    harness.add_client_named(Some("MALLORY"));
+    for _ in 0..60 {
+        harness.step();
+    }
+    assert!(!connected(&harness, "MALLORY"));
+    let _ = std::fs::remove_file(path);
+}
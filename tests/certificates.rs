@@ -0,0 +1,89 @@
+mod harness;
+
+use bevy_quinnet::client::certificate::CertVerifierAction;
+use catan::{
+    client::{CertificatePrompt, ClientConfig},
+    server::{CertificateConfig, ServerConfig},
+};
+use harness::TestHarness;
+
+#[test]
+fn generated_certificates_are_kept_for_the_next_start() {
+    let dir = std::env::temp_dir();
+    let cert_file = dir.join(format!("catan-cert-{}.pem", std::process::id()));
+    let key_file = dir.join(format!("catan-key-{}.pem", std::process::id()));
+    let certificate = CertificateConfig {
+        cert_file: cert_file.to_string_lossy().into_owned(),
+        key_file: key_file.to_string_lossy().into_owned(),
+        ..Default::default()
+    };
+
+    let config = ServerConfig {
+        certificate: certificate.clone(),
+        ..Default::default()
+    };
+    drop(TestHarness::with_config(0, config));
+    let saved = std::fs::read(&cert_file).expect("the certificate was not saved");
+
+    // Loading it back must not need generating a new one
+    let config = ServerConfig {
+        certificate: CertificateConfig {
+            generate: false,
+            ..certificate
+        },
+        ..Default::default()
+    };
+    let mut harness = TestHarness::with_config(1, config);
+    assert!(harness.run_until(300, |h| h.client_id(0).is_some()));
+    assert_eq!(std::fs::read(&cert_file).unwrap(), saved);
+
+    let _ = std::fs::remove_file(cert_file);
+    let _ = std::fs::remove_file(key_file);
+}
+
+#[test]
+fn changed_certificates_are_asked_about() {
+    let known_hosts =
+        std::env::temp_dir().join(format!("catan-known-hosts-{}", std::process::id()));
+    let _ = std::fs::remove_file(&known_hosts);
+    let pinning = || ClientConfig {
+        known_hosts: Some(known_hosts.to_string_lossy().into_owned()),
+        ..Default::default()
+    };
+
+    // The first connection pins the certificate without asking
+    let mut harness = TestHarness::new(0);
+    let first = harness.add_client_with(pinning());
+    assert!(harness.run_until(300, |h| h.client_id(first).is_some()));
+    let pinned = std::fs::read_to_string(&known_hosts).expect("the certificate was not pinned");
+
+    // A server generating a new certificate looks like someone in the middle
+    harness.restart_server(ServerConfig::default());
+    let second = harness.add_client_with(pinning());
+    let asked = harness.run_until(300, |h| {
+        h.client(second)
+            .world
+            .resource::<CertificatePrompt>()
+            .pending
+            .is_some()
+    });
+    assert!(asked, "the changed certificate was never asked about");
+    assert!(harness.client_id(second).is_none());
+
+    harness
+        .client(second)
+        .world
+        .resource_mut::<CertificatePrompt>()
+        .answer(CertVerifierAction::TrustAndStore);
+    assert!(harness.run_until(300, |h| h.client_id(second).is_some()));
+    assert!(harness
+        .client(second)
+        .world
+        .resource::<CertificatePrompt>()
+        .pending
+        .is_none());
+    let repinned = std::fs::read_to_string(&known_hosts).unwrap();
+    assert_ne!(repinned, pinned);
+
+    let _ = std::fs::remove_file(known_hosts);
+}
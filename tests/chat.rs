@@ -0,0 +1,149 @@
+mod harness;
+
+use std::collections::HashMap;
+
+use catan::{
+    chat::{self, ChatLineKind, ChatLog},
+    lobby,
+    protocol::{ClientMessage, Users},
+};
+use harness::TestHarness;
+
+fn said(log: &ChatLog) -> Vec<(ChatLineKind, String)> {
+    log.lines
+        .iter()
+        .filter(|line| line.kind != ChatLineKind::System)
+        .map(|line| (line.kind.clone(), line.text.clone()))
+        .collect()
+}
+
+#[test]
+fn parses_chat_input_and_whispers() {
+    let users = Users {
+        self_id: 1,
+        names: HashMap::from([(1, "alice".to_string()), (2, "bob".to_string())]),
+    };
+
+    assert!(matches!(chat::parse_input("   ", &users), Ok(None)));
+    assert!(matches!(
+        chat::parse_input(" hello there ", &users),
+        Ok(Some(ClientMessage::ChatMessage { message })) if message == "hello there"
+    ));
+    assert!(matches!(
+        chat::parse_input("/w bob  see you", &users),
+        Ok(Some(ClientMessage::Whisper { to: 2, message })) if message == "see you"
+    ));
+    assert_eq!(
+        chat::parse_input("/w carol hi", &users).unwrap_err(),
+        "No user named carol"
+    );
+    assert!(chat::parse_input("/w bob", &users).is_err());
+}
+
+#[test]
+fn scrollback_keeps_the_newest_lines() {
+    let mut log = ChatLog::default();
+    for i in 0..chat::HISTORY_LINES + 5 {
+        log.system(i.to_string());
+    }
+    assert_eq!(log.lines.len(), chat::HISTORY_LINES);
+    assert_eq!(log.lines.front().unwrap().text, "5");
+
+    // Scrolled up, new lines don't move what is being read
+    log.scroll = 3;
+    log.system("new");
+    assert_eq!(log.scroll, 4);
+}
+
+#[test]
+fn room_chat_and_whispers_reach_the_right_users() {
+    let mut harness = TestHarness::new(3);
+    for index in 0..3 {
+        assert!(harness.join_room(index, lobby::DEFAULT_ROOM));
+    }
+    let names: Vec<String> = (0..3)
+        .map(|index| {
+            let id = harness.client_id(index).unwrap();
+            harness.client(0).world.resource::<Users>().names[&id].clone()
+        })
+        .collect();
+    let bob = harness.client_id(1).unwrap();
+
+    harness.send_from_client(
+        0,
+        ClientMessage::ChatMessage {
+            message: "hello".to_string(),
+        },
+    );
+    harness.send_from_client(
+        0,
+        ClientMessage::Whisper {
+            to: bob,
+            message: "psst".to_string(),
+        },
+    );
+    let delivered = harness.run_until(300, |h| {
+        said(h.client(1).world.resource::<ChatLog>()).len() == 2
+            && said(h.client(2).world.resource::<ChatLog>()).len() == 1
+            && said(h.client(0).world.resource::<ChatLog>()).len() == 2
+    });
+    assert!(delivered, "chat never arrived");
+
+    let message = (
+        ChatLineKind::Message {
+            from: names[0].clone(),
+        },
+        "hello".to_string(),
+    );
+    let whisper = (
+        ChatLineKind::Whisper {
+            from: names[0].clone(),
+            to: names[1].clone(),
+        },
+        "psst".to_string(),
+    );
+    let mut bob_heard = said(harness.client(1).world.resource::<ChatLog>());
+    bob_heard.sort_by_key(|(_, text)| text.clone());
+    assert_eq!(bob_heard, vec![message.clone(), whisper]);
+    assert_eq!(
+        said(harness.client(2).world.resource::<ChatLog>()),
+        vec![message]
+    );
+
+    // Users joining show up as system lines
+    let joined = format!("{} joined", names[2]);
+    let log = harness.client(0).world.resource::<ChatLog>();
+    assert!(log
+        .lines
+        .iter()
+        .any(|line| line.kind == ChatLineKind::System && line.text == joined));
+}
+
+#[test]
+fn arrivals_and_departures_are_heard_nearby_only() {
+    let mut harness = TestHarness::new(1);
+    assert!(harness.join_room(0, lobby::DEFAULT_ROOM));
+    let newcomer = harness.add_client_named(Some("Newcomer"));
+    let named = harness.run_until(300, |h| {
+        h.client(0)
+            .world
+            .resource::<Users>()
+            .names
+            .values()
+            .any(|name| name == "Newcomer")
+    });
+    assert!(named, "the name never reached the room");
+    let heard = |harness: &TestHarness, index: usize, text: &str| {
+        let log = harness.clients[index].world.resource::<ChatLog>();
+        log.lines.iter().any(|line| line.text == text)
+    };
+    assert!(harness.run_until(300, |h| heard(h, newcomer, "Newcomer joined")));
+    assert!(!heard(&harness, 0, "Newcomer joined"));
+
+    // Whispers still reach across rooms
+    let users = harness.client(newcomer).world.resource::<Users>().clone();
+    let first = harness.client_id(0).unwrap();
+    let whisper = chat::parse_input(&format!("/w {} hi", users.names[&first]), &users);
+    harness.send_from_client(newcomer, whisper.unwrap().unwrap());
+    assert!(harness.run_until(300, |h| heard(h, 0, "hi")));
+}
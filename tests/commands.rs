@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use catan::{
+    map::{MapGraph, MapGraphs},
+    protocol::{
+        ActionRejection, ActionResultEvent, ClientEvent, CommandEnvelope, CommandEvent,
+        CommandKind, GameCommand, RoomId,
+    },
+    server::{self, CommandRegistry, RegisterCommand},
+};
+
+const ROOM: RoomId = RoomId(1);
+
+/// Commands that made it to the handler.
+#[derive(Resource, Default)]
+struct Handled(Vec<u32>);
+
+/// Pings that made it to the handler, by request id.
+#[derive(Resource, Default)]
+struct Pinged(Vec<u32>);
+
+fn only_vertex_zero(
+    command: &GameCommand,
+    graph: Option<&MapGraph>,
+) -> Result<(), ActionRejection> {
+    let GameCommand::Move { vertex } = command else {
+        return Err(ActionRejection::UnknownCommand);
+    };
+    match graph {
+        Some(graph) if graph.contains_vertex(*vertex) && *vertex == 0 => Ok(()),
+        _ => Err(ActionRejection::UnknownTarget),
+    }
+}
+
+fn handle_moves(mut commands: EventReader<CommandEvent>, mut handled: ResMut<Handled>) {
+    for event in commands.iter() {
+        let GameCommand::Move { vertex } = event.command else {
+            continue;
+        };
+        handled.0.push(vertex);
+    }
+}
+
+fn handle_pings(mut commands: EventReader<CommandEvent>, mut pinged: ResMut<Pinged>) {
+    for event in commands.iter() {
+        if event.command.kind() == CommandKind::Ping {
+            pinged.0.push(event.request_id);
+        }
+    }
+}
+
+fn app() -> App {
+    let mut graph = MapGraph::default();
+    graph.add_vertex(0);
+    graph.add_vertex(1);
+
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_event::<ClientEvent>()
+        .add_event::<CommandEvent>()
+        .add_event::<ActionResultEvent>()
+        .init_resource::<CommandRegistry>()
+        .init_resource::<Handled>()
+        .init_resource::<Pinged>()
+        .insert_resource(MapGraphs(HashMap::from([(ROOM, graph)])))
+        .add_system(server::dispatch_commands);
+    app
+}
+
+fn send_moves(app: &mut App, vertexes: &[u32]) -> Vec<(u32, Result<(), ActionRejection>)> {
+    let moves: Vec<GameCommand> = vertexes
+        .iter()
+        .map(|vertex| GameCommand::Move { vertex: *vertex })
+        .collect();
+    send_commands(app, &moves)
+}
+
+fn send_commands(
+    app: &mut App,
+    commands: &[GameCommand],
+) -> Vec<(u32, Result<(), ActionRejection>)> {
+    for (request_id, command) in commands.iter().enumerate() {
+        app.world.send_event(ClientEvent {
+            request_id: request_id as u32,
+            command: command.clone().into(),
+            client_id: 7,
+            room: ROOM,
+        });
+    }
+    app.update();
+    let results = app.world.resource::<Events<ActionResultEvent>>();
+    results
+        .iter_current_update_events()
+        .map(|result| (result.request_id, result.result.clone()))
+        .collect()
+}
+
+#[test]
+fn registered_commands_are_validated_before_their_handler() {
+    let mut app = app();
+    app.register_command(CommandKind::Move, only_vertex_zero, handle_moves);
+
+    let results = send_moves(&mut app, &[0, 1, 5]);
+
+    assert_eq!(app.world.resource::<Handled>().0, vec![0]);
+    assert_eq!(
+        results,
+        vec![
+            (1, Err(ActionRejection::UnknownTarget)),
+            (2, Err(ActionRejection::UnknownTarget)),
+        ]
+    );
+}
+
+#[test]
+fn malformed_commands_are_refused() {
+    let mut app = app();
+    app.register_command(CommandKind::Move, only_vertex_zero, handle_moves);
+
+    app.world.send_event(ClientEvent {
+        request_id: 4,
+        command: CommandEnvelope(vec![0xff; 4]),
+        client_id: 7,
+        room: ROOM,
+    });
+    app.update();
+
+    assert!(app.world.resource::<Handled>().0.is_empty());
+    let results = app.world.resource::<Events<ActionResultEvent>>();
+    let results: Vec<_> = results
+        .iter_current_update_events()
+        .map(|result| (result.request_id, result.result.clone()))
+        .collect();
+    assert_eq!(results, vec![(4, Err(ActionRejection::Malformed))]);
+}
+
+#[test]
+fn unregistered_commands_are_refused() {
+    let mut app = app();
+
+    let results = send_moves(&mut app, &[0]);
+
+    assert!(app.world.resource::<Handled>().0.is_empty());
+    assert_eq!(results, vec![(0, Err(ActionRejection::UnknownCommand))]);
+}
+
+#[test]
+fn each_kind_reaches_only_its_handler() {
+    let mut app = app();
+    app.register_command(CommandKind::Move, only_vertex_zero, handle_moves)
+        .register_command(CommandKind::Ping, |_, _| Ok(()), handle_pings);
+
+    let results = send_commands(
+        &mut app,
+        &[
+            GameCommand::Ping {},
+            GameCommand::Move { vertex: 0 },
+            GameCommand::Ping {},
+            GameCommand::Move { vertex: 1 },
+        ],
+    );
+
+    assert_eq!(app.world.resource::<Handled>().0, vec![0]);
+    assert_eq!(app.world.resource::<Pinged>().0, vec![0, 2]);
+    assert_eq!(results, vec![(3, Err(ActionRejection::UnknownTarget))]);
+}
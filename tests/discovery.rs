@@ -0,0 +1,57 @@
+mod harness;
+
+use catan::{
+    discovery::ServerBrowser,
+    server::{AccessConfig, Password, ServerConfig},
+};
+use harness::TestHarness;
+
+fn browser(harness: &mut TestHarness, index: usize) -> &ServerBrowser {
+    harness.client(index).world.resource::<ServerBrowser>()
+}
+
+#[test]
+fn browsers_find_servers_and_connect() {
+    let mut harness = TestHarness::new(1);
+    let browsing = harness.add_browsing_client("Alice");
+    assert!(harness.run_until(300, |h| !browser(h, browsing).servers.is_empty()));
+
+    let server = browser(&mut harness, browsing).servers[0].clone();
+    assert_eq!(server.addr.port(), harness.port);
+    assert_eq!(server.info.name, "Catan server");
+    assert_eq!(server.info.players, 1);
+    assert!(!server.info.password);
+    assert!(server.info.max_players > 0);
+
+    harness
+        .client(browsing)
+        .world
+        .resource_mut::<ServerBrowser>()
+        .chosen = Some(server.addr);
+    assert!(harness.run_until(300, |h| h.client_id(browsing).is_some()));
+    assert!(!browser(&mut harness, browsing).browsing);
+}
+
+#[test]
+fn replies_tell_about_passwords_and_can_be_turned_off() {
+    let config = ServerConfig {
+        access: AccessConfig {
+            password: Some(Password("hunter2".to_string())),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut harness = TestHarness::with_config(0, config);
+    let browsing = harness.add_browsing_client("Alice");
+    assert!(harness.run_until(300, |h| !browser(h, browsing).servers.is_empty()));
+    assert!(browser(&mut harness, browsing).servers[0].info.password);
+
+    let mut config = ServerConfig::default();
+    config.discovery.enabled = false;
+    let mut harness = TestHarness::with_config(0, config);
+    let browsing = harness.add_browsing_client("Alice");
+    for _ in 0..150 {
+        harness.step();
+    }
+    assert!(browser(&mut harness, browsing).servers.is_empty());
+}
@@ -0,0 +1,88 @@
+mod harness;
+
+use std::fs;
+
+use bevy::prelude::*;
+use catan::{
+    editor::{EditorAction, LevelEditor, Picked},
+    map::{self, level},
+};
+use harness::TestHarness;
+
+#[test]
+fn edits_are_undone_and_saved_to_the_level_format() {
+    let dir = std::env::temp_dir().join(format!("catan-editor-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("drawn.json");
+
+    let mut editor = LevelEditor::open(&path).unwrap();
+    assert_eq!(editor.level.name, "drawn");
+    let a = editor.add_vertex(Vec2::new(0.0, 0.0));
+    let b = editor.add_vertex(Vec2::new(100.0, 0.0));
+    let c = editor.add_vertex(Vec2::new(0.0, 100.0));
+    assert!(editor.connect(a, b));
+    assert!(!editor.connect(b, a));
+    assert!(!editor.connect(a, a));
+    assert!(editor.connect(b, c));
+    let mana = editor.add_mana(Vec2::new(50.0, 50.0));
+    editor.toggle_link(mana, a);
+    editor.toggle_start(a);
+    editor.toggle_start(c);
+    assert_eq!(level::validate(&editor.level), Vec::new());
+
+    // Deleting a vertex takes its edges, links and start along and renumbers the rest
+    editor.delete(Picked::Vertex(a));
+    assert_eq!(editor.level.vertex_positions.len(), 2);
+    let edges: Vec<(i32, i32)> = editor
+        .level
+        .vertex_connections
+        .iter()
+        .map(|edge| (edge.a, edge.b))
+        .collect();
+    assert_eq!(edges, vec![(0, 1)]);
+    assert!(editor.level.mana_connections.is_empty());
+    assert_eq!(editor.level.map_start_vertexes, vec![1]);
+
+    editor.apply(EditorAction::Undo);
+    assert_eq!(editor.level.vertex_positions.len(), 3);
+    assert_eq!(editor.level.vertex_connections.len(), 2);
+    editor.apply(EditorAction::Redo);
+    assert_eq!(editor.level.vertex_positions.len(), 2);
+    editor.apply(EditorAction::Undo);
+
+    assert_eq!(editor.save(), Ok(Vec::new()));
+    let saved = level::read_level(&fs::read(&path).unwrap(), "other").unwrap();
+    assert_eq!(saved.name, "drawn");
+    assert_eq!(saved.vertex_positions.len(), 3);
+    assert_eq!(saved.mana_connections.len(), 1);
+    assert_eq!(saved.map_start_vertexes, vec![a, c]);
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn editor_client_shows_the_level_as_it_changes() {
+    let level = map::level_path(&catan::server::ServerConfig::default().level);
+    let mut harness = TestHarness::new(0);
+    let editor = harness.add_editor_client(level.as_ref());
+    let expected = level::read_level(&fs::read(&level).unwrap(), "level")
+        .unwrap()
+        .vertex_positions
+        .len();
+    let vertexes = |app: &mut App| app.world.query::<&map::Vertex>().iter(&app.world).count();
+    assert!(harness.run_until(10, |h| vertexes(h.client(editor)) == expected));
+
+    harness
+        .client(editor)
+        .world
+        .resource_mut::<LevelEditor>()
+        .add_vertex(Vec2::new(5000.0, 5000.0));
+    assert!(harness.run_until(10, |h| vertexes(h.client(editor)) == expected + 1));
+    harness
+        .client(editor)
+        .world
+        .resource_mut::<LevelEditor>()
+        .apply(EditorAction::Undo);
+    assert!(harness.run_until(10, |h| vertexes(h.client(editor)) == expected));
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use bevy::prelude::Vec2;
+use catan::map::{
+    generator::{self, GeneratorConfig, GeneratorKind},
+    level::{self, MapInitData},
+};
+
+fn configs() -> Vec<GeneratorConfig> {
+    let mut configs = Vec::new();
+    for seed in 0..5 {
+        for (kind, players, size) in [
+            (GeneratorKind::Hex, 4, 2),
+            (GeneratorKind::Hex, 3, 1),
+            (GeneratorKind::Planar, 4, 30),
+            (GeneratorKind::Symmetric, 4, 8),
+            (GeneratorKind::Symmetric, 3, 10),
+        ] {
+            configs.push(GeneratorConfig {
+                kind,
+                players,
+                size,
+                seed,
+            });
+        }
+    }
+    configs
+}
+
+fn positions(level: &MapInitData) -> Vec<Vec2> {
+    level
+        .vertex_positions
+        .iter()
+        .map(|v| Vec2::new(v.x, v.y))
+        .collect()
+}
+
+#[test]
+fn generated_levels_are_valid() {
+    for config in configs() {
+        let level = generator::generate(&config).unwrap();
+        assert_eq!(level::validate(&level), vec![], "{}", config.level_name());
+        assert_eq!(level.map_start_vertexes.len(), config.players);
+        assert!(!level.mana_points.is_empty());
+    }
+}
+
+#[test]
+fn the_same_seed_gives_the_same_level() {
+    for config in configs() {
+        let first = generator::generate(&config).unwrap();
+        let second = generator::generate(&config).unwrap();
+        assert_eq!(
+            serde_json::to_value(first).unwrap(),
+            serde_json::to_value(second).unwrap()
+        );
+    }
+    let config = GeneratorConfig {
+        kind: GeneratorKind::Planar,
+        players: 4,
+        size: 30,
+        seed: 1,
+    };
+    let other = GeneratorConfig {
+        seed: 2,
+        ..config.clone()
+    };
+    assert_ne!(
+        positions(&generator::generate(&config).unwrap()),
+        positions(&generator::generate(&other).unwrap())
+    );
+}
+
+#[test]
+fn edges_never_cross() {
+    for config in configs() {
+        let level = generator::generate(&config).unwrap();
+        let vertexes = positions(&level);
+        let edges: Vec<(usize, usize)> = level
+            .vertex_connections
+            .iter()
+            .map(|e| (e.a as usize, e.b as usize))
+            .collect();
+        for (i, a) in edges.iter().enumerate() {
+            for b in edges[i + 1..].iter() {
+                assert!(
+                    !generator::segments_cross(&vertexes, *a, *b),
+                    "{}: {:?} crosses {:?}",
+                    config.level_name(),
+                    a,
+                    b
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn mana_colors_are_balanced() {
+    for config in configs() {
+        let level = generator::generate(&config).unwrap();
+        let mut counts: HashMap<&str, usize> =
+            level::MANA_COLORS.iter().map(|color| (*color, 0)).collect();
+        for mana in level.mana_points.iter() {
+            *counts.get_mut(mana.color.as_str()).unwrap() += 1;
+        }
+        let most = counts.values().max().unwrap();
+        let least = counts.values().min().unwrap();
+        // Symmetric maps repeat the colors of one wedge for every player
+        let allowed = match config.kind {
+            GeneratorKind::Symmetric => config.players,
+            _ => 1,
+        };
+        assert!(most - least <= allowed, "{}", config.level_name());
+    }
+}
+
+#[test]
+fn symmetric_starts_are_equally_far_apart() {
+    for config in configs()
+        .into_iter()
+        .filter(|c| c.kind == GeneratorKind::Symmetric)
+    {
+        let level = generator::generate(&config).unwrap();
+        let vertexes = positions(&level);
+        let starts: Vec<Vec2> = level
+            .map_start_vertexes
+            .iter()
+            .map(|v| vertexes[*v as usize])
+            .collect();
+        let gaps: Vec<f32> = (0..starts.len())
+            .map(|i| starts[i].distance(starts[(i + 1) % starts.len()]))
+            .collect();
+        for gap in gaps.iter() {
+            assert!((gap - gaps[0]).abs() < 2.0, "{:?}", gaps);
+        }
+    }
+}
+
+#[test]
+fn level_names_round_trip() {
+    let config = GeneratorConfig {
+        kind: GeneratorKind::Symmetric,
+        players: 3,
+        size: 10,
+        seed: 42,
+    };
+    let name = config.level_name();
+    assert_eq!(name, "generated/symmetric?players=3&size=10&seed=42");
+    assert_eq!(GeneratorConfig::from_level_name(&name), Some(Ok(config)));
+    assert_eq!(
+        GeneratorConfig::from_level_name("generated/hex?seed=7"),
+        Some(Ok(GeneratorConfig {
+            kind: GeneratorKind::Hex,
+            players: 4,
+            size: 2,
+            seed: 7,
+        }))
+    );
+    assert!(GeneratorConfig::from_level_name("levels/level_3.json").is_none());
+    assert_eq!(generator::seed_of(&name), Some(42));
+    assert_eq!(generator::seed_of("generated/hex"), None);
+    assert_eq!(generator::seed_of("levels/level_3.json"), None);
+    assert!(matches!(
+        GeneratorConfig::from_level_name("generated/spiral"),
+        Some(Err(_))
+    ));
+}
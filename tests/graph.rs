@@ -0,0 +1,126 @@
+use bevy::prelude::Entity;
+use catan::{
+    map::{
+        self,
+        adjacency::SpawnedMapObject,
+        generator::{self, GeneratorConfig, GeneratorKind},
+        MapGraph, MapInitData,
+    },
+    protocol::RoomId,
+};
+
+/// A square 0-1-2-3 with a diagonal 0-2, a red mana point on 0, 1 and 2, and a lone vertex 4.
+fn square() -> MapInitData {
+    serde_json::from_value(serde_json::json!({
+        "version": map::LEVEL_VERSION,
+        "name": "square",
+        "map_start_vertexes": [0],
+        "vertex_positions": [
+            {"x": 0.0, "y": 0.0},
+            {"x": 100.0, "y": 0.0},
+            {"x": 100.0, "y": 100.0},
+            {"x": 0.0, "y": 100.0},
+            {"x": 300.0, "y": 300.0}
+        ],
+        "vertex_connections": [
+            {"a": 0, "b": 1}, {"a": 1, "b": 2}, {"a": 2, "b": 3}, {"a": 3, "b": 0}, {"a": 0, "b": 2}
+        ],
+        "mana_points": [{"x": 70.0, "y": 30.0, "color": "red"}],
+        "mana_connections": [{"a": 0, "b": 0}, {"a": 0, "b": 1}, {"a": 0, "b": 2}]
+    }))
+    .unwrap()
+}
+
+fn sorted(mut ids: Vec<u32>) -> Vec<u32> {
+    ids.sort();
+    ids
+}
+
+#[test]
+fn answers_neighbor_questions() {
+    let graph = MapGraph::from_level(&square());
+
+    assert_eq!(sorted(graph.neighbors(0).to_vec()), vec![1, 2, 3]);
+    assert_eq!(sorted(graph.edges_of(2).to_vec()), vec![1, 2, 4]);
+    assert_eq!(graph.edge_between(2, 0), Some(4));
+    assert_eq!(graph.edge_between(1, 3), None);
+    assert_eq!(graph.edge_ends(3), Some((3, 0)));
+    assert_eq!(graph.materials_of(1), &[0]);
+    assert!(graph.materials_of(3).is_empty());
+    assert_eq!(
+        sorted(graph.vertexes_of_material(0).to_vec()),
+        vec![0, 1, 2]
+    );
+    assert_eq!(graph.material_color(0), Some(map::RED));
+    assert!(graph.neighbors(42).is_empty());
+}
+
+#[test]
+fn finds_shortest_paths_around_blocked_vertexes() {
+    let graph = MapGraph::from_level(&square());
+
+    assert_eq!(
+        graph.shortest_path(1, 3, |_| true).map(|p| p.len()),
+        Some(3)
+    );
+    assert_eq!(graph.shortest_path(1, 1, |_| true), Some(vec![1]));
+    // With 0 and 2 filled there is no way from 1 to 3
+    assert_eq!(graph.shortest_path(1, 3, |v| v != 0 && v != 2), None);
+    assert_eq!(graph.shortest_path(1, 3, |v| v != 0), Some(vec![1, 2, 3]));
+    assert_eq!(graph.shortest_path(0, 4, |_| true), None);
+}
+
+#[test]
+fn splits_the_map_into_components() {
+    let graph = MapGraph::from_level(&square());
+    assert_eq!(graph.components(), vec![vec![0, 1, 2, 3], vec![4]]);
+
+    let generated = generator::generate(&GeneratorConfig {
+        kind: GeneratorKind::Planar,
+        players: 4,
+        size: 40,
+        seed: 3,
+    })
+    .unwrap();
+    let graph = MapGraph::from_level(&generated);
+    assert_eq!(graph.components().len(), 1);
+    assert_eq!(graph.vertex_count(), generated.vertex_positions.len());
+}
+
+#[test]
+fn spawned_maps_give_the_same_graph_as_their_level() {
+    let level = square();
+    let rooms = [RoomId(1), RoomId(2)];
+    let spawned: Vec<SpawnedMapObject> = rooms
+        .iter()
+        .flat_map(|room| map::level_spawn_events(*room, &level))
+        .enumerate()
+        .map(|(i, spawn)| SpawnedMapObject {
+            room: spawn.room,
+            map_type: spawn.map_type,
+            id: spawn.map_type_id,
+            entity: Entity::from_raw(i as u32),
+            adjacencies: map::Adjacencies {
+                vertex_list: spawn.vertex_list,
+                edge_list: spawn.edge_list,
+                material_list: spawn.material_list,
+            },
+            material_type: spawn.material_type,
+        })
+        .collect();
+
+    let graphs = MapGraph::from_spawned(&spawned);
+    let expected = MapGraph::from_level(&level);
+    assert_eq!(graphs.len(), 2);
+    for graph in graphs.values() {
+        for vertex in expected.vertexes() {
+            assert_eq!(
+                sorted(graph.neighbors(vertex).to_vec()),
+                sorted(expected.neighbors(vertex).to_vec())
+            );
+            assert_eq!(graph.materials_of(vertex), expected.materials_of(vertex));
+        }
+        assert_eq!(graph.material_color(0), Some(map::RED));
+        assert_eq!(graph.components(), expected.components());
+    }
+}
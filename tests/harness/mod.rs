@@ -0,0 +1,273 @@
+//! Runs a server and any number of clients in one process over loopback, without windows.
+//!
+//! Every app advances by exactly one fixed tick per step, so movement and timers are
+//! deterministic; only network delivery depends on the machine, which `run_until` absorbs.
+
+#![allow(dead_code)]
+
+use std::{net::UdpSocket, path::Path, thread::sleep, time::Duration};
+
+use bevy::{input::InputPlugin, prelude::*, time::TimeUpdateStrategy};
+use bevy_interact_2d::InteractionState;
+use bevy_quinnet::client::Client;
+
+use catan::{
+    client::{ClientConfig, ClientPlugin},
+    discovery::ServerBrowser,
+    lobby::{self, LobbyState},
+    map, players,
+    protocol::{self, ClientMessage, RoomId},
+    server::{CertificateConfig, ServerConfig, ServerPlugin},
+};
+
+pub const TICK: Duration = Duration::from_millis(33);
+
+pub struct TestHarness {
+    pub server: App,
+    pub clients: Vec<App>,
+    pub port: u16,
+}
+
+/// The plugins the real binaries get from `DefaultPlugins` that gameplay code relies on.
+fn headless_app() -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(AssetPlugin::default())
+        .add_plugin(InputPlugin)
+        .add_asset::<Image>()
+        .add_asset::<TextureAtlas>()
+        .init_resource::<InteractionState>()
+        .insert_resource(TimeUpdateStrategy::ManualDuration(TICK));
+    app
+}
+
+fn free_port() -> u16 {
+    UdpSocket::bind("127.0.0.1:0")
+        .and_then(|socket| socket.local_addr())
+        .map(|addr| addr.port())
+        .expect("no free port for the test server")
+}
+
+/// A server listening on `port`, with everything tests must not share turned off.
+fn start_server(mut config: ServerConfig, port: u16) -> App {
+    config.port = port;
+    // Tests type nothing on stdin and must not share a ban list
+    config.admin.console = false;
+    config.admin.ban_list = std::env::temp_dir()
+        .join(format!("catan-bans-{}.json", port))
+        .to_string_lossy()
+        .into_owned();
+    // Nor answer each other's discovery probes
+    config.discovery.port = free_port();
+    // Nor leave certificates behind, unless the test is about them
+    if config.certificate.cert_file == CertificateConfig::default().cert_file {
+        config.certificate.save = false;
+    }
+
+    let mut server = headless_app();
+    server.insert_resource(config).add_plugin(ServerPlugin);
+    server.setup();
+    // Let the endpoint start listening before anyone connects
+    server.update();
+    // Levels load in the background, the main room opens once its level is in
+    for _ in 0..300 {
+        if !server.world.resource::<lobby::Rooms>().rooms.is_empty() {
+            break;
+        }
+        sleep(Duration::from_millis(2));
+        server.update();
+    }
+    server
+}
+
+impl TestHarness {
+    pub fn new(clients: usize) -> Self {
+        Self::with_config(clients, ServerConfig::default())
+    }
+
+    pub fn with_config(clients: usize, config: ServerConfig) -> Self {
+        let port = free_port();
+        let mut harness = Self {
+            server: start_server(config, port),
+            clients: Vec::new(),
+            port,
+        };
+        for _ in 0..clients {
+            harness.add_client();
+        }
+        harness
+    }
+
+    /// Stops the server and starts one with `config` on the same port, clients stay.
+    pub fn restart_server(&mut self, config: ServerConfig) {
+        // The old endpoint lets go of the port first
+        self.server = App::new();
+        self.server = start_server(config, self.port);
+    }
+
+    pub fn add_client(&mut self) -> usize {
+        self.add_client_named(None)
+    }
+
+    /// A client joining under `name` rather than a random one, to get restored seats back.
+    pub fn add_client_named(&mut self, name: Option<&str>) -> usize {
+        self.add_client_with(ClientConfig {
+            name: name.map(str::to_owned),
+            // Every test server has a new certificate, and nobody answers prompts
+            known_hosts: None,
+            ..Default::default()
+        })
+    }
+
+    /// A client with its own `config`, connecting to this server.
+    pub fn add_client_with(&mut self, config: ClientConfig) -> usize {
+        let mut client = headless_app();
+        client
+            .insert_resource(ClientConfig {
+                server_addr: format!("127.0.0.1:{}", self.port).parse().unwrap(),
+                ..config
+            })
+            .add_plugin(ClientPlugin);
+        client.setup();
+        self.clients.push(client);
+        self.clients.len() - 1
+    }
+
+    /// A client looking for servers on this server's discovery port instead of connecting.
+    pub fn add_browsing_client(&mut self, name: &str) -> usize {
+        let port = self.server.world.resource::<ServerConfig>().discovery.port;
+        let mut client = headless_app();
+        client
+            .insert_resource(ClientConfig {
+                name: Some(name.to_owned()),
+                known_hosts: None,
+                browse: true,
+                ..Default::default()
+            })
+            .insert_resource(ServerBrowser::with_port(port))
+            .add_plugin(ClientPlugin);
+        client.setup();
+        self.clients.push(client);
+        self.clients.len() - 1
+    }
+
+    /// A client playing `replay` back instead of connecting, stepped along with the others.
+    pub fn add_replay_client(&mut self, replay: &Path) -> usize {
+        let mut client = headless_app();
+        client
+            .insert_resource(ClientConfig {
+                replay: Some(replay.to_path_buf()),
+                ..Default::default()
+            })
+            .add_plugin(ClientPlugin);
+        client.setup();
+        self.clients.push(client);
+        self.clients.len() - 1
+    }
+
+    /// A client editing the level at `level` instead of connecting.
+    pub fn add_editor_client(&mut self, level: &Path) -> usize {
+        let mut client = headless_app();
+        client
+            .insert_resource(ClientConfig {
+                edit: Some(level.to_path_buf()),
+                ..Default::default()
+            })
+            .add_plugin(ClientPlugin);
+        client.setup();
+        self.clients.push(client);
+        self.clients.len() - 1
+    }
+
+    pub fn client(&mut self, index: usize) -> &mut App {
+        &mut self.clients[index]
+    }
+
+    /// Advances the server and then every client by one tick.
+    pub fn step(&mut self) {
+        self.server.update();
+        for client in self.clients.iter_mut() {
+            client.update();
+        }
+        // Give the network threads a chance to deliver what was just sent
+        sleep(Duration::from_millis(2));
+    }
+
+    /// Steps until `done` holds, returning false if it still doesn't after `max_steps`.
+    pub fn run_until(&mut self, max_steps: usize, mut done: impl FnMut(&mut Self) -> bool) -> bool {
+        for _ in 0..max_steps {
+            if done(self) {
+                return true;
+            }
+            self.step();
+        }
+        done(self)
+    }
+
+    pub fn send_from_client(&mut self, index: usize, message: ClientMessage) {
+        self.clients[index]
+            .world
+            .resource::<Client>()
+            .connection()
+            .send_message(message)
+            .expect("client failed to send");
+    }
+
+    /// Waits for the client to connect, then moves it into `room` and waits until it is there.
+    pub fn join_room(&mut self, index: usize, room: RoomId) -> bool {
+        if !self.run_until(300, |h| h.client_id(index).is_some()) {
+            return false;
+        }
+        self.send_from_client(index, ClientMessage::JoinRoom { room });
+        self.run_until(300, |h| current_room(h.client(index)) == Some(room))
+    }
+
+    /// The id the server gave this client, once `InitClient` arrived.
+    pub fn client_id(&mut self, index: usize) -> Option<u64> {
+        let users = self.clients[index].world.resource::<protocol::Users>();
+        users
+            .names
+            .contains_key(&users.self_id)
+            .then_some(users.self_id)
+    }
+}
+
+pub fn players(app: &mut App) -> Vec<players::Player> {
+    app.world
+        .query::<&players::Player>()
+        .iter(&app.world)
+        .cloned()
+        .collect()
+}
+
+pub fn player_of(app: &mut App, client_id: u64) -> Option<players::Player> {
+    players(app)
+        .into_iter()
+        .find(|p| p.client_owner_id == client_id)
+}
+
+pub fn current_room(app: &App) -> Option<RoomId> {
+    app.world.resource::<LobbyState>().current_room
+}
+
+/// Looks up a vertex in the client's current room, or in the main room on the server.
+pub fn vertex_entity(app: &App, id: u32) -> Option<Entity> {
+    let room = app
+        .world
+        .get_resource::<LobbyState>()
+        .and_then(|lobby| lobby.current_room)
+        .unwrap_or(lobby::DEFAULT_ROOM);
+    app.world
+        .resource::<map::VertexClientServerLookup>()
+        .0
+        .get(&(room, id))
+        .copied()
+}
+
+pub fn vertex(app: &App, id: u32) -> Option<(&map::Vertex, &map::Adjacencies)> {
+    let entity = vertex_entity(app, id)?;
+    Some((
+        app.world.get::<map::Vertex>(entity)?,
+        app.world.get::<map::Adjacencies>(entity)?,
+    ))
+}
@@ -0,0 +1,205 @@
+mod harness;
+
+use catan::{lobby, map, players, protocol};
+use harness::TestHarness;
+
+fn level_3_counts() -> (usize, usize, usize) {
+    let text = std::fs::read_to_string("./assets/levels/level_3.json").unwrap();
+    let level: serde_json::Value = serde_json::from_str(&text).unwrap();
+    let count = |key: &str| level[key].as_array().unwrap().len();
+    (
+        count("vertex_positions"),
+        count("vertex_connections"),
+        count("mana_points"),
+    )
+}
+
+#[test]
+fn joining_client_receives_the_whole_map() {
+    let (vertexes, edges, materials) = level_3_counts();
+    let mut harness = TestHarness::new(1);
+    assert!(harness.join_room(0, lobby::DEFAULT_ROOM));
+
+    let received = harness.run_until(300, |h| {
+        h.client(0)
+            .world
+            .resource::<map::VertexClientServerLookup>()
+            .0
+            .len()
+            == vertexes
+    });
+    assert!(received, "client never received every vertex of level_3");
+
+    let client = harness.client(0);
+    assert_eq!(
+        client
+            .world
+            .resource::<map::EdgeClientServerLookup>()
+            .0
+            .len(),
+        edges
+    );
+    assert_eq!(
+        client
+            .world
+            .resource::<map::MaterialClientServerLookup>()
+            .0
+            .len(),
+        materials
+    );
+    for id in 0..vertexes as u32 {
+        assert!(
+            harness::vertex(client, id).is_some(),
+            "vertex {} is missing on the client",
+            id
+        );
+    }
+}
+
+#[test]
+fn move_to_adjacent_free_vertex_updates_server_and_client() {
+    let mut harness = TestHarness::new(1);
+    assert!(harness.join_room(0, lobby::DEFAULT_ROOM));
+
+    assert!(
+        harness.run_until(300, |h| {
+            let Some(id) = h.client_id(0) else {
+                return false;
+            };
+            harness::player_of(h.client(0), id).is_some()
+        }),
+        "client never saw its own player"
+    );
+    let client_id = harness.client_id(0).unwrap();
+    let start = harness::player_of(&mut harness.server, client_id)
+        .unwrap()
+        .current_vertex_id;
+
+    let (_, adjacencies) = harness::vertex(&harness.server, start).unwrap();
+    let target = adjacencies
+        .vertex_list
+        .clone()
+        .into_iter()
+        .find(|id| !harness::vertex(&harness.server, *id).unwrap().0.filled)
+        .expect("start vertex has no free neighbour");
+
+    harness.send_from_client(
+        0,
+        protocol::ClientMessage::SendCommand {
+            request_id: 1,
+            command: protocol::GameCommand::Move { vertex: target }.into(),
+        },
+    );
+
+    let arrived = harness.run_until(600, |h| {
+        let on_server = harness::player_of(&mut h.server, client_id)
+            .is_some_and(|p| p.current_vertex_id == target && p.next_entity.is_empty());
+        let target_entity = harness::vertex_entity(h.client(0), target);
+        let on_client = harness::player_of(h.client(0), client_id)
+            .is_some_and(|p| Some(p.current_vertex) == target_entity);
+        on_server && on_client
+    });
+    assert!(arrived, "player never reached vertex {}", target);
+
+    assert!(harness::vertex(&harness.server, target).unwrap().0.filled);
+    assert!(!harness::vertex(&harness.server, start).unwrap().0.filled);
+}
+
+/// Selects a vertex on the client's map the way a click does.
+fn select(app: &mut bevy::prelude::App, id: u32) {
+    let entity = harness::vertex_entity(app, id).unwrap();
+    app.world
+        .get_mut::<map::MapClickable>(entity)
+        .unwrap()
+        .selected = true;
+}
+
+#[test]
+fn refused_moves_are_explained_and_deselected() {
+    let mut harness = TestHarness::new(1);
+    assert!(harness.join_room(0, lobby::DEFAULT_ROOM));
+    assert!(harness.run_until(300, |h| {
+        let Some(id) = h.client_id(0) else {
+            return false;
+        };
+        harness::player_of(h.client(0), id).is_some()
+    }));
+    let client_id = harness.client_id(0).unwrap();
+    let start = harness::player_of(&mut harness.server, client_id)
+        .unwrap()
+        .current_vertex_id;
+
+    let (vertexes, _, _) = level_3_counts();
+    let graph = harness
+        .server
+        .world
+        .resource::<map::MapGraphs>()
+        .0
+        .get(&lobby::DEFAULT_ROOM)
+        .unwrap()
+        .clone();
+    let far = (0..vertexes as u32)
+        .find(|v| *v != start && !graph.neighbors(start).contains(v))
+        .unwrap();
+
+    select(harness.client(0), far);
+    let refused = harness.run_until(300, |h| {
+        h.client(0)
+            .world
+            .resource::<players::ActionRequests>()
+            .rejection
+            .is_some()
+    });
+    assert!(refused, "the server never answered the move");
+
+    let client = harness.client(0);
+    let requests = client.world.resource::<players::ActionRequests>();
+    assert_eq!(
+        requests.rejection,
+        Some(protocol::ActionRejection::NotAdjacent)
+    );
+    assert!(requests.pending.is_empty());
+    let entity = harness::vertex_entity(client, far).unwrap();
+    assert!(
+        !client
+            .world
+            .get::<map::MapClickable>(entity)
+            .unwrap()
+            .selected
+    );
+    assert_eq!(
+        harness::player_of(&mut harness.server, client_id)
+            .unwrap()
+            .current_vertex_id,
+        start
+    );
+}
+
+#[test]
+fn unanswered_moves_are_forgotten_on_leaving_the_room() {
+    let mut harness = TestHarness::new(1);
+    assert!(harness.join_room(0, lobby::DEFAULT_ROOM));
+    assert!(harness.run_until(300, |h| {
+        let Some(id) = h.client_id(0) else {
+            return false;
+        };
+        harness::player_of(h.client(0), id).is_some()
+    }));
+
+    // A reply that never comes, like one to a command lost with the connection
+    let vertex = harness::vertex_entity(harness.client(0), 0).unwrap();
+    harness
+        .client(0)
+        .world
+        .resource_mut::<players::ActionRequests>()
+        .pending
+        .insert(u32::MAX, vertex);
+    harness.send_from_client(0, protocol::ClientMessage::LeaveRoom {});
+
+    assert!(harness.run_until(300, |h| h
+        .client(0)
+        .world
+        .resource::<players::ActionRequests>()
+        .pending
+        .is_empty()));
+}
@@ -0,0 +1,37 @@
+mod harness;
+
+use catan::{lobby, map, server::ServerConfig};
+use harness::TestHarness;
+
+fn vertex_count(app: &mut bevy::prelude::App) -> usize {
+    app.world.query::<&map::Vertex>().iter(&app.world).count()
+}
+
+#[test]
+fn edited_level_is_sent_to_the_room_again() {
+    let mut harness = TestHarness::new(1);
+    assert!(harness.join_room(0, lobby::DEFAULT_ROOM));
+    let player = harness.client_id(0).unwrap();
+    assert!(harness.run_until(300, |h| harness::player_of(h.client(0), player).is_some()));
+    let old_vertex = harness::vertex_entity(&harness.clients[0], 0).unwrap();
+    let vertexes = vertex_count(harness.client(0));
+    assert!(vertexes > 0);
+
+    // Changing the asset is what the file watcher does when the level is saved
+    let level = ServerConfig::default().level;
+    let handle = harness.server.world.resource::<map::Levels>().0[&level].clone();
+    harness
+        .server
+        .world
+        .resource_mut::<bevy::prelude::Assets<map::MapInitData>>()
+        .get_mut(&handle)
+        .unwrap();
+
+    let reloaded = harness.run_until(300, |h| {
+        harness::vertex_entity(&h.clients[0], 0).is_some_and(|vertex| vertex != old_vertex)
+            && harness::player_of(h.client(0), player).is_some()
+    });
+    assert!(reloaded, "the client never got the reloaded map");
+    assert_eq!(vertex_count(harness.client(0)), vertexes);
+    assert!(harness::player_of(&mut harness.server, player).is_some());
+}
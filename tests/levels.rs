@@ -0,0 +1,105 @@
+use catan::map::{self, level, LEVEL_VERSION};
+
+#[test]
+fn every_level_loads() {
+    let levels = map::available_levels();
+    assert!(levels.contains(&"levels/blue/level_1.json".to_string()));
+    for name in levels {
+        let bytes = std::fs::read(map::level_path(&name)).unwrap();
+        let level = level::read_level(&bytes, &name).unwrap_or_else(|e| panic!("{}: {}", name, e));
+        assert_eq!(level.version, LEVEL_VERSION);
+        assert!(
+            !level.map_start_vertexes.is_empty(),
+            "{} has no start",
+            name
+        );
+        assert_eq!(level::validate(&level), Vec::new(), "{} is not valid", name);
+    }
+}
+
+#[test]
+fn unversioned_levels_are_migrated() {
+    let old = br#"{
+        "map_start_vertex": 1,
+        "win_conditions": { "go_to_vertex": 1 },
+        "vertex_positions": [{"x": 0, "y": 0}, {"x": 100, "y": 0}],
+        "vertex_connections": [{"A": 0, "B": 1}]
+    }"#;
+    let level = level::read_level(old, "old").unwrap();
+    assert_eq!(level.name, "old");
+    assert_eq!(level.map_start_vertexes, vec![1]);
+    assert_eq!(
+        (level.vertex_connections[0].a, level.vertex_connections[0].b),
+        (0, 1)
+    );
+    assert!(level.mana_points.is_empty());
+    assert!(level.extra.contains_key("win_conditions"));
+
+    // Writing it out and reading it back keeps everything
+    let text = serde_json::to_vec(&level).unwrap();
+    let again = level::read_level(&text, "renamed").unwrap();
+    assert_eq!(again.name, "old");
+    assert_eq!(again.map_start_vertexes, level.map_start_vertexes);
+    assert!(again.extra.contains_key("win_conditions"));
+}
+
+#[test]
+fn migrating_doesnt_make_up_start_vertexes() {
+    let old = br#"{
+        "vertex_positions": [{"x": 0, "y": 0}, {"x": 100, "y": 0}],
+        "vertex_connections": [{"A": 0, "B": 1}]
+    }"#;
+    let level = level::read_level(old, "old").unwrap();
+    assert!(level.map_start_vertexes.is_empty());
+    let problems: Vec<String> = level::validate(&level)
+        .iter()
+        .map(|problem| problem.to_string())
+        .collect();
+    assert_eq!(
+        problems,
+        ["map_start_vertexes: the level has no start vertexes"]
+    );
+}
+
+#[test]
+fn newer_levels_are_rejected() {
+    let newer = format!(r#"{{ "version": {} }}"#, LEVEL_VERSION + 1);
+    assert!(level::read_level(newer.as_bytes(), "newer").is_err());
+}
+
+#[test]
+fn every_problem_of_a_broken_level_is_reported() {
+    let broken = br#"{
+        "version": 1,
+        "name": "broken",
+        "map_start_vertexes": [4, 9, 1, 1],
+        "vertex_positions": [
+            {"x": 0, "y": 0}, {"x": 100, "y": 0}, {"x": 200, "y": 0},
+            {"x": 0, "y": 100}, {"x": 100, "y": 100}
+        ],
+        "vertex_connections": [
+            {"a": 0, "b": 1}, {"a": 1, "b": 2}, {"a": 2, "b": 7},
+            {"a": 1, "b": 0}, {"a": 3, "b": 3}
+        ],
+        "mana_points": [{"x": 50, "y": 50, "color": "pink"}],
+        "mana_connections": [{"a": 0, "b": 0}, {"a": 2, "b": 1}]
+    }"#;
+    let level = level::read_level(broken, "broken").unwrap();
+    let problems: Vec<String> = level::validate(&level)
+        .iter()
+        .map(|problem| problem.to_string())
+        .collect();
+    let expected = [
+        "vertex_connections[2]: vertex 7 does not exist",
+        "vertex_connections[3]: vertexes 1 and 0 are already connected by vertex_connections[0]",
+        "vertex_connections[4]: vertex 3 is connected to itself",
+        "map_start_vertexes[1]: vertex 9 does not exist",
+        "map_start_vertexes[3]: vertex 1 is already map_start_vertexes[2]",
+        "mana_points[0]: unknown color \"pink\"",
+        "mana_connections[1]: mana point 2 does not exist",
+        "vertex_positions[3]: vertexes [3] are not connected to the rest of the map",
+        "vertex_positions[4]: vertexes [4] are not connected to the rest of the map",
+        "map_start_vertexes[0]: vertex 4 can't reach the rest of the map",
+    ];
+    assert_eq!(problems, expected);
+}
@@ -0,0 +1,85 @@
+mod harness;
+
+use catan::{
+    client::NameEntry,
+    protocol::{ClientMessage, NameRejection, Users},
+    server::{validate_name, NamesConfig},
+};
+use harness::TestHarness;
+
+#[test]
+fn names_follow_the_rules() {
+    let config = NamesConfig::default();
+    let mut users = Users::default();
+    users.names.insert(1, "Alice".to_string());
+
+    assert_eq!(validate_name("bob_2.0", &config, &users), Ok(()));
+    assert_eq!(
+        validate_name("", &config, &users),
+        Err(NameRejection::TooShort { min: 2 })
+    );
+    assert_eq!(
+        validate_name(&"a".repeat(21), &config, &users),
+        Err(NameRejection::TooLong { max: 20 })
+    );
+    assert_eq!(
+        validate_name("bob smith", &config, &users),
+        Err(NameRejection::InvalidCharacters)
+    );
+    // Cyrillic "а" and fullwidth letters look like "admin" but don't lowercase to it
+    assert_eq!(
+        validate_name("\u{430}dmin", &config, &users),
+        Err(NameRejection::InvalidCharacters)
+    );
+    assert_eq!(
+        validate_name("\u{ff41}\u{ff44}\u{ff4d}\u{ff49}\u{ff4e}", &config, &users),
+        Err(NameRejection::InvalidCharacters)
+    );
+    assert_eq!(
+        validate_name("ALICE", &config, &users),
+        Err(NameRejection::Taken)
+    );
+    assert_eq!(
+        validate_name("Server", &config, &users),
+        Err(NameRejection::Reserved)
+    );
+}
+
+#[test]
+fn taken_names_are_refused_and_can_be_retried() {
+    let mut harness = TestHarness::new(0);
+    harness.add_client_named(Some("Alice"));
+    let second = harness.add_client_named(Some("alice"));
+    assert!(harness.run_until(300, |h| h
+        .client(second)
+        .world
+        .resource::<NameEntry>()
+        .rejection
+        .is_some()));
+
+    let entry = harness.client(second).world.resource::<NameEntry>();
+    assert_eq!(entry.rejection, Some(NameRejection::Taken));
+    assert_eq!(entry.editing.as_deref(), Some("alice"));
+    assert_eq!(harness.server.world.resource::<Users>().names.len(), 1);
+
+    harness.send_from_client(
+        second,
+        ClientMessage::Join {
+            name: "Alice2".to_string(),
+            password: None,
+        },
+    );
+    assert!(harness.run_until(300, |h| h
+        .server
+        .world
+        .resource::<Users>()
+        .names
+        .values()
+        .any(|name| name == "Alice2")));
+    assert!(harness.run_until(300, |h| h
+        .client(second)
+        .world
+        .resource::<NameEntry>()
+        .editing
+        .is_none()));
+}
@@ -0,0 +1,146 @@
+mod harness;
+
+use catan::{
+    client::PasswordEntry,
+    lobby,
+    protocol::{ClientMessage, PasswordRejection, Users},
+    server::{AccessConfig, BucketConfig, Password, PasswordGate, ServerConfig},
+};
+use harness::TestHarness;
+
+fn access() -> AccessConfig {
+    AccessConfig {
+        password: Some(Password("hunter2".to_string())),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn wrong_passwords_run_out() {
+    let mut gate = PasswordGate::new(access());
+    assert_eq!(gate.check(1, None, 0.0), Err(PasswordRejection::Required));
+    assert_eq!(
+        gate.check(1, Some("hunter"), 0.0),
+        Err(PasswordRejection::Wrong { attempts_left: 2 })
+    );
+    assert_eq!(gate.check(1, Some("hunter2"), 0.0), Ok(()));
+    assert_eq!(
+        gate.check(2, Some("a"), 0.0),
+        Err(PasswordRejection::Wrong { attempts_left: 2 })
+    );
+    gate.check(2, Some("b"), 0.0).unwrap_err();
+    assert_eq!(
+        gate.check(2, Some("c"), 0.0),
+        Err(PasswordRejection::TooManyAttempts)
+    );
+    assert!(!format!("{:?}", access()).contains("hunter2"));
+
+    let mut open = PasswordGate::new(AccessConfig::default());
+    assert_eq!(open.check(1, None, 0.0), Ok(()));
+}
+
+#[test]
+fn reconnecting_doesnt_buy_more_guesses() {
+    let mut gate = PasswordGate::new(AccessConfig {
+        failures: BucketConfig {
+            burst: 4.0,
+            per_second: 1.0,
+        },
+        ..access()
+    });
+    for client_id in 0..4 {
+        gate.check(client_id, Some("guess"), 0.0).unwrap_err();
+    }
+    // New clients don't get fresh guesses until the bucket refills
+    assert_eq!(
+        gate.check(10, Some("guess"), 0.0),
+        Err(PasswordRejection::Locked)
+    );
+    assert_eq!(
+        gate.check(10, Some("guess"), 1.0),
+        Err(PasswordRejection::Wrong { attempts_left: 2 })
+    );
+}
+
+#[test]
+fn guessers_dont_lock_out_the_right_password() {
+    let mut gate = PasswordGate::new(AccessConfig {
+        failures: BucketConfig {
+            burst: 2.0,
+            per_second: 0.0,
+        },
+        ..access()
+    });
+    for client_id in 0..5 {
+        gate.check(client_id, Some("guess"), 0.0).unwrap_err();
+    }
+    assert_eq!(
+        gate.check(5, Some("guess"), 0.0),
+        Err(PasswordRejection::Locked)
+    );
+    assert_eq!(gate.check(6, Some("hunter2"), 0.0), Ok(()));
+}
+
+#[test]
+fn clients_are_asked_for_the_password() {
+    let config = ServerConfig {
+        access: access(),
+        ..Default::default()
+    };
+    let mut harness = TestHarness::with_config(0, config);
+    let client = harness.add_client_named(Some("Alice"));
+    assert!(harness.run_until(300, |h| h
+        .client(client)
+        .world
+        .resource::<PasswordEntry>()
+        .editing
+        .is_some()));
+    let entry = harness.client(client).world.resource::<PasswordEntry>();
+    assert_eq!(entry.rejection, Some(PasswordRejection::Required));
+    assert_eq!(entry.name, "Alice");
+    assert!(harness.server.world.resource::<Users>().names.is_empty());
+
+    harness.send_from_client(
+        client,
+        ClientMessage::Join {
+            name: "Alice".to_string(),
+            password: Some("hunter2".to_string()),
+        },
+    );
+    assert!(harness.run_until(300, |h| h.client_id(client).is_some()));
+    assert!(harness
+        .client(client)
+        .world
+        .resource::<PasswordEntry>()
+        .editing
+        .is_none());
+}
+
+#[test]
+fn clients_without_the_password_cant_do_anything() {
+    let config = ServerConfig {
+        access: access(),
+        ..Default::default()
+    };
+    let mut harness = TestHarness::with_config(0, config);
+    let client = harness.add_client_named(Some("Mallory"));
+    assert!(harness.run_until(300, |h| h
+        .client(client)
+        .world
+        .resource::<PasswordEntry>()
+        .editing
+        .is_some()));
+
+    harness.send_from_client(
+        client,
+        ClientMessage::JoinRoom {
+            room: lobby::DEFAULT_ROOM,
+        },
+    );
+    for _ in 0..60 {
+        harness.step();
+    }
+    assert_eq!(harness::current_room(harness.client(client)), None);
+    let rooms = harness.server.world.resource::<lobby::Rooms>();
+    assert!(rooms.rooms[&lobby::DEFAULT_ROOM].seats.is_empty());
+}
@@ -0,0 +1,182 @@
+mod harness;
+
+use bevy::prelude::Entity;
+use catan::{
+    chat::ChatLog,
+    lobby,
+    players::ActionRequests,
+    protocol::{ActionRejection, ClientMessage, GameCommand, Users},
+    server::{BucketConfig, RateLimiter, RateLimitsConfig, ServerConfig, Verdict},
+};
+use harness::TestHarness;
+
+const CHAT: &str = "ChatMessage";
+
+fn limiter() -> RateLimiter {
+    let mut config = RateLimitsConfig::default();
+    config.kinds.insert(
+        CHAT.to_string(),
+        BucketConfig {
+            burst: 2.0,
+            per_second: 1.0,
+        },
+    );
+    config.warnings = 1;
+    config.disconnect_after = 3;
+    config.throttle_seconds = 5.0;
+    config.forgive_seconds = 30.0;
+    RateLimiter::new(config)
+}
+
+#[test]
+fn buckets_allow_bursts_and_refill() {
+    let mut rates = limiter();
+    assert_eq!(rates.check(1, CHAT, 0.0), Verdict::Allow);
+    assert_eq!(rates.check(1, CHAT, 0.0), Verdict::Allow);
+    assert_eq!(rates.check(1, CHAT, 0.0), Verdict::Warn);
+    // Other clients and other kinds have their own buckets
+    assert_eq!(rates.check(2, CHAT, 0.0), Verdict::Allow);
+    assert_eq!(rates.check(1, "ListRooms", 0.0), Verdict::Allow);
+
+    assert_eq!(rates.check(1, CHAT, 1.0), Verdict::Allow);
+    assert_eq!(rates.stats.warnings, 1);
+    assert_eq!(rates.stats.dropped.get(CHAT), Some(&1));
+}
+
+#[test]
+fn repeat_offenders_are_throttled_then_disconnected() {
+    let mut rates = limiter();
+    let mut verdicts = Vec::new();
+    let mut now = 0.0;
+    while verdicts.last() != Some(&Verdict::Disconnect) && now < 60.0 {
+        let verdict = rates.check(1, CHAT, now);
+        if verdict != Verdict::Allow && verdict != Verdict::Drop {
+            verdicts.push(verdict);
+        }
+        now += 0.1;
+    }
+    assert_eq!(
+        verdicts,
+        vec![Verdict::Warn, Verdict::Throttle, Verdict::Disconnect]
+    );
+    // Throttling held off the disconnect for its whole duration
+    assert!(now > 5.0);
+    assert_eq!(rates.stats.throttles, 1);
+    assert_eq!(rates.stats.disconnects, 1);
+}
+
+#[test]
+fn violations_are_forgiven_after_a_quiet_while() {
+    let mut rates = limiter();
+    for _ in 0..3 {
+        rates.check(1, CHAT, 0.0);
+    }
+    assert_eq!(rates.stats.warnings, 1);
+    for _ in 0..3 {
+        rates.check(1, CHAT, 100.0);
+    }
+    // A warning again, not a throttle
+    assert_eq!(rates.stats.warnings, 2);
+    assert_eq!(rates.stats.throttles, 0);
+
+    let mut unlimited = RateLimiter::new(RateLimitsConfig {
+        enabled: false,
+        ..Default::default()
+    });
+    assert!((0..1000).all(|_| unlimited.check(1, CHAT, 0.0) == Verdict::Allow));
+}
+
+#[test]
+fn chat_flooders_get_disconnected() {
+    let mut config = ServerConfig::default();
+    config.rate_limits.throttle_seconds = 1.0;
+    let mut harness = TestHarness::with_config(2, config);
+    assert!(harness.run_until(300, |h| h.client_id(0).is_some()
+        && h.client_id(1).is_some()));
+    let flooder = harness.client_id(0).unwrap();
+
+    let mut sent = 0;
+    let gone = harness.run_until(600, |h| {
+        if !h
+            .server
+            .world
+            .resource::<Users>()
+            .names
+            .contains_key(&flooder)
+        {
+            return true;
+        }
+        for _ in 0..3 {
+            h.send_from_client(
+                0,
+                ClientMessage::ChatMessage {
+                    message: sent.to_string(),
+                },
+            );
+            sent += 1;
+        }
+        false
+    });
+    assert!(gone, "the flooder was never disconnected");
+
+    // Most of the flood never reached the other client
+    let heard = harness.client(1).world.resource::<ChatLog>().lines.len();
+    assert!(
+        heard < sent / 2,
+        "{} of {} messages got through",
+        heard,
+        sent
+    );
+}
+
+/// Sends a move as if clicked, and waits for the server to answer it.
+fn answered_move(harness: &mut TestHarness, request_id: u32) -> Option<ActionRejection> {
+    let mut requests = harness.client(0).world.resource_mut::<ActionRequests>();
+    requests.pending.insert(request_id, Entity::from_raw(0));
+    requests.rejection = None;
+    harness.send_from_client(
+        0,
+        ClientMessage::SendCommand {
+            request_id,
+            command: GameCommand::Move { vertex: u32::MAX }.into(),
+        },
+    );
+    let answered = harness.run_until(300, |h| {
+        h.client(0)
+            .world
+            .resource::<ActionRequests>()
+            .pending
+            .is_empty()
+    });
+    assert!(answered, "move {} was never answered", request_id);
+    harness
+        .client(0)
+        .world
+        .resource::<ActionRequests>()
+        .rejection
+        .clone()
+}
+
+#[test]
+fn dropped_moves_are_answered() {
+    let mut config = ServerConfig::default();
+    config.rate_limits.kinds.insert(
+        "SendCommand".to_string(),
+        BucketConfig {
+            burst: 1.0,
+            per_second: 0.001,
+        },
+    );
+    let mut harness = TestHarness::with_config(1, config);
+    assert!(harness.join_room(0, lobby::DEFAULT_ROOM));
+
+    assert_eq!(
+        answered_move(&mut harness, 1),
+        Some(ActionRejection::UnknownTarget)
+    );
+    // Still waiting on this one would block every later move
+    assert_eq!(
+        answered_move(&mut harness, 2),
+        Some(ActionRejection::RateLimited)
+    );
+}
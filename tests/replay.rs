@@ -0,0 +1,80 @@
+mod harness;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use catan::{
+    lobby,
+    protocol::ServerMessage,
+    replay::{self, ReplayControl, ReplayPlayback},
+    server::ServerConfig,
+};
+use harness::TestHarness;
+
+fn recorded_replay(dir: &Path) -> Option<PathBuf> {
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| path.extension().is_some_and(|ext| ext == "replay"))
+}
+
+#[test]
+fn recorded_match_plays_back_in_a_client() {
+    let dir = std::env::temp_dir().join(format!("catan-replay-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let mut config = ServerConfig::default();
+    config.replays.record = true;
+    config.replays.dir = dir.to_string_lossy().into_owned();
+
+    let mut harness = TestHarness::with_config(1, config);
+    assert!(harness.join_room(0, lobby::DEFAULT_ROOM));
+    let player = harness.client_id(0).unwrap();
+    assert!(harness.run_until(300, |h| harness::player_of(h.client(0), player).is_some()));
+    for _ in 0..30 {
+        harness.step();
+    }
+
+    let path = recorded_replay(&dir).expect("the main room was not recorded");
+    let (header, frames) = replay::read_replay(&path).unwrap();
+    assert_eq!(header.room.id, lobby::DEFAULT_ROOM);
+    assert!(matches!(
+        frames.first().map(|f| &f.message),
+        Some(ServerMessage::InitMap { .. })
+    ));
+    assert!(frames
+        .iter()
+        .any(|f| matches!(f.message, ServerMessage::UpdatePlayers { .. })));
+
+    let viewer = harness.add_replay_client(&path);
+    let watched = harness.run_until(300, |h| {
+        harness::player_of(h.client(viewer), player).is_some()
+            && harness::vertex(h.client(viewer), 0).is_some()
+    });
+    assert!(watched, "the replay never showed the recorded player");
+    assert!(harness::player_of(harness.client(viewer), replay::REPLAY_VIEWER_ID).is_none());
+
+    // Seeking back starts over from the map
+    harness
+        .client(viewer)
+        .world
+        .resource_mut::<ReplayPlayback>()
+        .apply(ReplayControl::Seek(-1000.0));
+    assert_eq!(
+        harness
+            .client(viewer)
+            .world
+            .resource::<ReplayPlayback>()
+            .next_frame,
+        0
+    );
+    let replayed = harness.run_until(300, |h| {
+        harness::player_of(h.client(viewer), player).is_some()
+            && harness::vertex(h.client(viewer), 0).is_some()
+    });
+    assert!(replayed, "the replay did not come back after seeking");
+
+    let _ = fs::remove_dir_all(&dir);
+}
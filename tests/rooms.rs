@@ -0,0 +1,216 @@
+mod harness;
+
+use bevy::prelude::With;
+use catan::{
+    lobby, map,
+    protocol::{ClientMessage, JoinRejection, RoomId, RoomPhase},
+    server::ServerConfig,
+};
+use harness::TestHarness;
+
+fn server_room(harness: &TestHarness, room: RoomId) -> Option<&lobby::Room> {
+    harness
+        .server
+        .world
+        .resource::<lobby::Rooms>()
+        .rooms
+        .get(&room)
+}
+
+/// Has client `index` create a room and waits until it is seated there.
+fn create_room(harness: &mut TestHarness, index: usize, name: &str) -> RoomId {
+    assert!(harness.run_until(300, |h| h.client_id(index).is_some()));
+    harness.send_from_client(
+        index,
+        ClientMessage::CreateRoom {
+            name: name.to_string(),
+        },
+    );
+    assert!(harness.run_until(300, |h| harness::current_room(h.client(index)).is_some()));
+    harness::current_room(harness.client(index)).unwrap()
+}
+
+#[test]
+fn players_in_different_rooms_do_not_see_each_other() {
+    let mut harness = TestHarness::new(2);
+    assert!(harness.join_room(0, lobby::DEFAULT_ROOM));
+    create_room(&mut harness, 1, "Other");
+    harness.send_from_client(1, ClientMessage::SetReady { ready: true });
+    harness.send_from_client(1, ClientMessage::StartMatch {});
+
+    let first = harness.client_id(0).unwrap();
+    let second = harness.client_id(1).unwrap();
+    let both_spawned = harness.run_until(300, |h| {
+        harness::player_of(h.client(0), first).is_some()
+            && harness::player_of(h.client(1), second).is_some()
+    });
+    assert!(both_spawned, "players never spawned in their rooms");
+
+    let other_room = harness::current_room(harness.client(1)).unwrap();
+    assert_ne!(other_room, lobby::DEFAULT_ROOM);
+    // Give any stray broadcast time to arrive
+    for _ in 0..30 {
+        harness.step();
+    }
+    assert!(harness::player_of(harness.client(0), second).is_none());
+    assert!(harness::player_of(harness.client(1), first).is_none());
+    assert_eq!(harness::players(&mut harness.server).len(), 2);
+}
+
+#[test]
+fn last_member_leaving_closes_the_room() {
+    let mut harness = TestHarness::new(1);
+    let room = create_room(&mut harness, 0, "Temporary");
+
+    harness.send_from_client(0, ClientMessage::LeaveRoom {});
+    let closed = harness.run_until(300, |h| {
+        harness::current_room(h.client(0)).is_none()
+            && h.client(0)
+                .world
+                .resource::<lobby::LobbyState>()
+                .rooms
+                .iter()
+                .all(|r| r.id != room)
+    });
+    assert!(closed, "room was never closed");
+    assert!(!harness
+        .server
+        .world
+        .resource::<lobby::Rooms>()
+        .rooms
+        .contains_key(&room));
+    assert!(harness::players(&mut harness.server).is_empty());
+}
+
+#[test]
+fn host_starts_the_match_once_everyone_is_ready() {
+    let mut harness = TestHarness::new(2);
+    let room = create_room(&mut harness, 0, "Match");
+    assert!(harness.join_room(1, room));
+    let host = harness.client_id(0).unwrap();
+    let guest = harness.client_id(1).unwrap();
+
+    // Nobody is ready yet, and only the host may start
+    harness.send_from_client(0, ClientMessage::StartMatch {});
+    harness.send_from_client(0, ClientMessage::SetReady { ready: true });
+    harness.send_from_client(1, ClientMessage::SetReady { ready: true });
+    assert!(harness.run_until(300, |h| server_room(h, room).unwrap().all_ready()));
+    harness.send_from_client(1, ClientMessage::StartMatch {});
+    for _ in 0..30 {
+        harness.step();
+    }
+    assert_eq!(
+        server_room(&harness, room).unwrap().phase,
+        RoomPhase::Waiting
+    );
+    assert!(harness::players(&mut harness.server).is_empty());
+
+    harness.send_from_client(0, ClientMessage::StartMatch {});
+    let spawned = harness.run_until(300, |h| {
+        harness::player_of(h.client(0), host).is_some()
+            && harness::player_of(h.client(0), guest).is_some()
+    });
+    assert!(spawned, "the match never started");
+
+    // Seats take the start vertexes in id order
+    let mut starts: Vec<u32> = harness
+        .server
+        .world
+        .query_filtered::<(&map::Vertex, &RoomId), With<map::VertexStart>>()
+        .iter(&harness.server.world)
+        .filter(|(_, r)| **r == room)
+        .map(|(vertex, _)| vertex.id)
+        .collect();
+    starts.sort();
+    let start_of = |harness: &mut TestHarness, client_id| {
+        harness::player_of(&mut harness.server, client_id)
+            .unwrap()
+            .current_vertex_id
+    };
+    assert_eq!(start_of(&mut harness, host), starts[0]);
+    assert_eq!(start_of(&mut harness, guest), starts[1]);
+
+    let seats = &server_room(&harness, room).unwrap().seats;
+    assert_ne!(seats[0].color, seats[1].color);
+}
+
+#[test]
+fn joining_a_full_room_is_rejected_without_stopping_the_server() {
+    // Bots hold all but one start vertex of the main room
+    let mut config = ServerConfig::default();
+    config.bots.count = 5;
+    // Keep them from wandering onto the one free start vertex
+    config.bots.think_interval = 1000.0;
+    let mut harness = TestHarness::with_config(2, config);
+    assert!(harness.join_room(0, lobby::DEFAULT_ROOM));
+
+    assert!(harness.run_until(300, |h| h.client_id(1).is_some()));
+    harness.send_from_client(
+        1,
+        ClientMessage::JoinRoom {
+            room: lobby::DEFAULT_ROOM,
+        },
+    );
+    let rejected = harness.run_until(300, |h| {
+        h.client(1)
+            .world
+            .resource::<lobby::LobbyState>()
+            .rejection
+            .is_some()
+    });
+    assert!(rejected, "the extra client was never turned away");
+    assert_eq!(
+        harness
+            .client(1)
+            .world
+            .resource::<lobby::LobbyState>()
+            .rejection,
+        Some(JoinRejection::RoomFull { max_players: 1 })
+    );
+    assert_eq!(harness::current_room(harness.client(1)), None);
+
+    // The server keeps running the match for the seated player
+    let first = harness.client_id(0).unwrap();
+    assert!(harness.run_until(300, |h| harness::player_of(h.client(0), first).is_some()));
+}
+
+#[test]
+fn switching_rooms_leaves_the_previous_one() {
+    let mut harness = TestHarness::new(1);
+    assert!(harness.join_room(0, lobby::DEFAULT_ROOM));
+    let client_id = harness.client_id(0).unwrap();
+    assert!(
+        harness.run_until(300, |h| harness::player_of(h.client(0), client_id)
+            .is_some())
+    );
+
+    harness.send_from_client(
+        0,
+        ClientMessage::CreateRoom {
+            name: "Elsewhere".to_string(),
+        },
+    );
+    let moved = harness.run_until(300, |h| {
+        !matches!(
+            harness::current_room(h.client(0)),
+            None | Some(lobby::DEFAULT_ROOM)
+        )
+    });
+    assert!(moved, "never got into the new room");
+    let room = harness::current_room(harness.client(0)).unwrap();
+    let main_room_gone = harness.run_until(300, |h| {
+        let client = h.client(0);
+        let shown = client
+            .world
+            .query::<&RoomId>()
+            .iter(&client.world)
+            .any(|r| *r == lobby::DEFAULT_ROOM);
+        !shown && harness::players(client).is_empty()
+    });
+    assert!(main_room_gone, "the main room is still shown");
+    assert_eq!(harness::current_room(harness.client(0)), Some(room));
+    assert!(server_room(&harness, lobby::DEFAULT_ROOM)
+        .unwrap()
+        .seats
+        .is_empty());
+}
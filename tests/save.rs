@@ -0,0 +1,90 @@
+mod harness;
+
+use std::fs;
+
+use catan::{
+    lobby,
+    protocol::RoomPhase,
+    save::{self, PendingRestore, SaveGameEvent},
+    server::ServerConfig,
+};
+use harness::TestHarness;
+
+#[test]
+fn restored_match_gives_reconnecting_players_their_seat_back() {
+    let dir = std::env::temp_dir().join(format!("catan-save-{}", std::process::id()));
+    let path = dir.join("world.json");
+    let _ = fs::remove_dir_all(&dir);
+
+    let mut harness = TestHarness::new(0);
+    let alice = harness.add_client_named(Some("Alice"));
+    assert!(harness.join_room(alice, lobby::DEFAULT_ROOM));
+    let client_id = harness.client_id(alice).unwrap();
+    assert!(
+        harness.run_until(300, |h| harness::player_of(&mut h.server, client_id)
+            .is_some())
+    );
+    let saved = harness::player_of(&mut harness.server, client_id).unwrap();
+
+    harness.server.world.send_event(SaveGameEvent {
+        path: path.to_string_lossy().into_owned(),
+    });
+    harness.step();
+    let save = save::read_save(&path).expect("the game was not saved");
+    let main = save
+        .rooms
+        .iter()
+        .find(|room| room.id == lobby::DEFAULT_ROOM)
+        .unwrap();
+    assert!(main.map.is_some());
+    assert_eq!(main.players.len(), 1);
+    assert_eq!(main.players[0].username, "Alice");
+    assert_eq!(main.players[0].current_vertex, saved.current_vertex_id);
+    drop(harness);
+
+    // A new server picks the match up where it was saved
+    let mut config = ServerConfig::default();
+    config.saves.load = Some(path.to_string_lossy().into_owned());
+    let mut harness = TestHarness::with_config(0, config);
+    let restored = harness.run_until(300, |h| {
+        !h.server.world.resource::<PendingRestore>().is_restoring()
+            && harness::players(&mut h.server).len() == 1
+    });
+    assert!(restored, "the saved player was never restored");
+    {
+        let rooms = harness.server.world.resource::<lobby::Rooms>();
+        let main = &rooms.rooms[&lobby::DEFAULT_ROOM];
+        assert_eq!(main.phase, RoomPhase::Playing);
+        assert_eq!(main.vacant.len(), 1);
+    }
+    let restored = harness::players(&mut harness.server).remove(0);
+    assert_eq!(restored.current_vertex_id, saved.current_vertex_id);
+    assert!(
+        harness::vertex(&harness.server, saved.current_vertex_id)
+            .unwrap()
+            .0
+            .filled
+    );
+
+    // Alice connects again and plays the same player
+    let alice = harness.add_client_named(Some("Alice"));
+    let resumed = harness.run_until(300, |h| {
+        let Some(id) = h.client_id(alice) else {
+            return false;
+        };
+        harness::current_room(h.client(alice)) == Some(lobby::DEFAULT_ROOM)
+            && harness::player_of(h.client(alice), id).is_some()
+    });
+    assert!(resumed, "Alice never got the seat back");
+    let client_id = harness.client_id(alice).unwrap();
+    let player = harness::player_of(&mut harness.server, client_id).unwrap();
+    assert_eq!(player.id, restored.id);
+    assert_eq!(player.current_vertex_id, saved.current_vertex_id);
+    assert!(
+        harness.server.world.resource::<lobby::Rooms>().rooms[&lobby::DEFAULT_ROOM]
+            .vacant
+            .is_empty()
+    );
+
+    let _ = fs::remove_dir_all(&dir);
+}
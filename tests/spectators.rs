@@ -0,0 +1,95 @@
+mod harness;
+
+use catan::{
+    lobby,
+    protocol::{self, ClientMessage},
+};
+use harness::TestHarness;
+
+#[test]
+fn spectator_sees_the_match_without_a_player() {
+    let mut harness = TestHarness::new(2);
+    assert!(harness.join_room(0, lobby::DEFAULT_ROOM));
+    assert!(harness.run_until(300, |h| h.client_id(1).is_some()));
+    harness.send_from_client(
+        1,
+        ClientMessage::SpectateRoom {
+            room: lobby::DEFAULT_ROOM,
+        },
+    );
+
+    let player = harness.client_id(0).unwrap();
+    let spectator = harness.client_id(1).unwrap();
+    let watching = harness.run_until(300, |h| harness::player_of(h.client(1), player).is_some());
+    assert!(watching, "the spectator never saw the player");
+    assert!(harness
+        .client(1)
+        .world
+        .resource::<lobby::LobbyState>()
+        .is_spectating(spectator));
+    assert!(harness::vertex(&harness.clients[1], 0).is_some());
+
+    // Gameplay events from a spectator go nowhere
+    let start = harness::player_of(&mut harness.server, player)
+        .unwrap()
+        .current_vertex_id;
+    let (_, adjacencies) = harness::vertex(&harness.server, start).unwrap();
+    let target = adjacencies.vertex_list[0];
+    harness.send_from_client(
+        1,
+        ClientMessage::SendCommand {
+            request_id: 1,
+            command: protocol::GameCommand::Move { vertex: target }.into(),
+        },
+    );
+    for _ in 0..30 {
+        harness.step();
+    }
+    assert!(harness::player_of(&mut harness.server, spectator).is_none());
+    assert_eq!(harness::players(&mut harness.server).len(), 1);
+}
+
+#[test]
+fn spectators_outlast_the_players_and_can_take_a_seat() {
+    let mut harness = TestHarness::new(2);
+    assert!(harness.run_until(300, |h| h.client_id(0).is_some()
+        && h.client_id(1).is_some()));
+    harness.send_from_client(
+        0,
+        ClientMessage::CreateRoom {
+            name: "Watched".to_string(),
+        },
+    );
+    assert!(harness.run_until(300, |h| harness::current_room(h.client(0)).is_some()));
+    let room = harness::current_room(harness.client(0)).unwrap();
+    harness.send_from_client(0, ClientMessage::SetReady { ready: true });
+    harness.send_from_client(0, ClientMessage::StartMatch {});
+    harness.send_from_client(1, ClientMessage::SpectateRoom { room });
+    let player = harness.client_id(0).unwrap();
+    let watching = harness.run_until(300, |h| harness::player_of(h.client(1), player).is_some());
+    assert!(watching, "the spectator never saw the match");
+
+    // The last player leaving ends the match, the spectator stays in the room
+    harness.send_from_client(0, ClientMessage::LeaveRoom {});
+    let ended = harness.run_until(300, |h| {
+        let client = h.client(1);
+        let shown = client
+            .world
+            .query::<&protocol::RoomId>()
+            .iter(&client.world)
+            .any(|r| *r == room);
+        !shown && harness::current_room(client) == Some(room)
+    });
+    assert!(ended, "the spectator still sees the match");
+
+    // Taking a seat doesn't close the room on the way
+    harness.send_from_client(1, ClientMessage::JoinRoom { room });
+    let spectator = harness.client_id(1).unwrap();
+    let seated = harness.run_until(300, |h| {
+        h.server.world.resource::<lobby::Rooms>().seat_of(spectator) == Some(room)
+    });
+    assert!(seated, "the spectator never got a seat");
+    let rooms = harness.server.world.resource::<lobby::Rooms>();
+    assert!(rooms.rooms[&room].spectators.is_empty());
+    assert_eq!(harness::current_room(harness.client(1)), Some(room));
+}